@@ -66,9 +66,14 @@ class ha_yydb : public handler {
     YYDB_share* share;            ///< Shared lock info
     YYDB_share* get_share();      ///< Get the share
     std::uint64_t table_id = 0;   ///< Current row id
+    std::uint64_t bulk_id = 0;    ///< Current bulk insert, 0 if none
+    bool bulk_failed = false;     ///< A row of the bulk insert failed
 
     std::uint64_t get_row_pk();
 
+    void start_bulk_insert(ha_rows rows) override;
+    int end_bulk_insert() override;
+
     public:
     ha_yydb(handlerton* hton, TABLE_SHARE* table_arg);
     ~ha_yydb() override = default;
@@ -262,6 +267,9 @@ class ha_yydb : public handler {
     int delete_all_rows(void) override;
     ha_rows records_in_range(uint inx, key_range* min_key,
         key_range* max_key) override;
+    int check(THD* thd, HA_CHECK_OPT* check_opt) override;
+    int repair(THD* thd, HA_CHECK_OPT* check_opt) override;
+    int optimize(THD* thd, HA_CHECK_OPT* check_opt) override;
     int delete_table(const char* from, const dd::Table* table_def) override;
     int rename_table(const char* from, const char* to,
         const dd::Table* from_table_def,
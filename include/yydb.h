@@ -2,9 +2,14 @@
 
 #define Y_ENGINE_MAX_LOG_BUFFER_SIZE 256
 
+/* The number of the status variables, `STATUS_VALUES` of the Rust core */
+#define YYDB_STATUS_VALUES 20
+
 #include <mysql/components/services/log_builtins.h> /* LogErr */
 #include "mysqld_error.h"                           /* Errors */
 
+#include <string>
+
 extern REQUIRES_SERVICE_PLACEHOLDER(log_builtins);
 extern REQUIRES_SERVICE_PLACEHOLDER(log_builtins_string);
 
@@ -35,9 +40,11 @@ namespace yydb {
     /* Lifecycle */
 
     /** @brief
-      Initialize the YYDB core.
+      Initialize the YYDB core with the global options, the
+      `name=value` pairs separated by commas. Returns 1 if the
+      options are invalid.
      */
-    extern int ha_yydb_core_init();
+    extern int ha_yydb_core_init(const char* options);
 
     /** @brief
       Initialize the YYDB core.
@@ -46,23 +53,90 @@ namespace yydb {
 
     /* End of lifecycle */
 
+    /* Options */
+
+    /** @brief
+      Set a global option by name, applied to the opened tables.
+      Returns 1 if the option is invalid.
+    */
+    extern int ha_yydb_set_option(const char* name, const char* value);
+
+    /** @brief
+      Check a global option without setting it, with the other options.
+      Returns 1 if the option is invalid.
+    */
+    extern int ha_yydb_check_option(const char* name, const char* value);
+
+    /** @brief
+      Set the default level of the logs, `error` to `trace`.
+      Returns 1 if the level is unknown.
+    */
+    extern int ha_yydb_set_log_level(const char* level);
+
+    /** @brief
+      Set the levels of the modules, e.g. `structs::lsm=trace`.
+      Returns 1 if the filters are invalid.
+    */
+    extern int ha_yydb_set_log_filters(const char* filters);
+
+    /** @brief
+      Write the logs to a file rotated at `max_size` bytes, keeping
+      `max_files` old ones, or the MySQL error log if the path is empty.
+      Returns 1 if the file can't be opened.
+    */
+    extern int ha_yydb_set_log_file(const char* path, uint64_t max_size, uint32_t max_files);
+
+    /* End of options */
+
+    /* Status */
+
+    /** @brief
+      Get the status of the engine for SHOW ENGINE YYDB STATUS.
+    */
+    extern std::string ha_yydb_engine_status();
+
+    /** @brief
+      Copy the values of the status variables, the counters since the
+      engine started, then the open tables, the memtable bytes and the
+      SSTables of every level. Returns the number of values copied.
+    */
+    extern uint ha_yydb_status_values(uint64_t* values, uint count);
+
+    /* End of status */
+
     /* Table */
     extern uint64_t ha_yydb_open_table(const char* name);
 
     extern void ha_yydb_close_table(uint64_t table_id);
 
-    extern void ha_yydb_insert_row(uint64_t table_id, uint64_t key, const u_char* row, uint length);
+    extern int ha_yydb_insert_row(uint64_t table_id, uint64_t key, const u_char* row, uint length);
+
+    extern int ha_yydb_update_row(uint64_t table_id, uint64_t key, const u_char* old, const u_char* row, uint length);
+
+    extern uint64_t ha_yydb_begin_bulk_insert(uint64_t table_id);
 
-    extern void ha_yydb_update_row(uint64_t table_id, uint64_t key, const u_char* old, const u_char* row, uint length);
+    extern int ha_yydb_bulk_insert_row(uint64_t bulk_id, uint64_t key, const u_char* row, uint length);
 
-    extern void delete_row(uint64_t table_id, uint64_t key);
+    extern int ha_yydb_end_bulk_insert(uint64_t bulk_id, bool abort);
+
+    extern int delete_row(uint64_t table_id, uint64_t key);
 
     extern void rnd_init(uint64_t table_id);
 
     extern void rnd_end(uint64_t table_id);
 
+    extern void ha_yydb_set_columns(uint64_t table_id, uint row_size, const uint32_t* offsets, uint count);
+
+    extern void ha_yydb_set_projection(uint64_t table_id, const uint32_t* fields, uint count);
+
     extern int ha_yydb_rnd_next(uint64_t table_id, u_char * buf, uint length);
 
+    extern int ha_yydb_check_table(uint64_t table_id);
+
+    extern int ha_yydb_repair_table(uint64_t table_id);
+
+    extern int ha_yydb_compact_table(uint64_t table_id);
+
     extern void ha_yydb_delete_table(const char* name);
     /* End of table */
 }
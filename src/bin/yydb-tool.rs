@@ -0,0 +1,570 @@
+//! Inspect the files of a YYDB table offline, without MySQL.
+//!
+//! ```text
+//! yydb-tool meta <table_dir>              dump the manifest
+//! yydb-tool list <table_dir>              list the SSTable files
+//! yydb-tool sstable <file.lN> [--hex]     print the header, blocks and entries
+//! yydb-tool cache <table_dir> [--hex]     decode the memtable cache
+//! yydb-tool export <table_dir> <file>     export the rows to CSV or JSON lines
+//! yydb-tool import <file> <table_dir>     import the rows into a new table
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use chrono::{TimeZone, Utc};
+use console::style;
+use indicatif::HumanBytes;
+use yydb::{
+    core::block_on,
+    structs::{
+        lsm::*, manifest::Manifest, table::Table, AsyncFromIO, AsyncIterator, AsyncKvStoreWrite,
+        MemTable,
+    },
+    utils::*,
+};
+
+const USAGE: &str = "\
+Usage: yydb-tool <command> <path> [--hex]
+
+Commands:
+    meta <table_dir>       dump the manifest of a table
+    list <table_dir>       list the SSTable files of a table
+    sstable <file.lN>      print the header, the blocks and the entries of a SSTable
+    cache <table_dir>      decode the memtable cache of a table
+    export <table_dir> <file.csv|file.jsonl>
+                           export the rows of a table, the memtable cache merged
+    import <file.csv|file.jsonl> <table_dir>
+                           import the exported rows into a new table
+
+Options:
+    --hex                  print the values with a hex view
+    --base64               export the rows in base64 instead of hex";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    // the tables run their background jobs on the runtime of the engine
+    if let Err(err) = block_on(run(args)) {
+        eprintln!("{} {:?}", style("error:").red().bold(), err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Vec<String>) -> Result<()> {
+    let hex = args.iter().any(|arg| arg == "--hex");
+    let encoding = match args.iter().any(|arg| arg == "--base64") {
+        true => RowEncoding::Base64,
+        false => RowEncoding::Hex,
+    };
+    let args = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["meta", dir] => dump_meta(Path::new(dir)).await,
+        ["list", dir] => list_sstables(Path::new(dir)).await,
+        ["sstable", file] => dump_sstable(Path::new(file), hex).await,
+        ["cache", dir] => dump_cache(Path::new(dir), hex).await,
+        ["export", dir, file] => export_table(Path::new(dir), Path::new(file), encoding).await,
+        ["import", file, dir] => import_table(Path::new(file), dir).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Open an existing file only, `IOHandler` creates the missing ones.
+async fn open(path: &Path) -> Result<IOHandler> {
+    if !path.is_file() {
+        return Err(DbError::Other(format!("{} not found", path.display())));
+    }
+
+    IOHandler::new(path).await
+}
+
+/// The level and the creation time of a SSTable.
+fn describe_key(key: SSTableKey) -> String {
+    let created = Utc
+        .timestamp_micros(key.timestamp())
+        .single()
+        .map_or("-".to_string(), |time| time.format("%F %T%.6f").to_string());
+    format!("L{} {}", key.level(), created)
+}
+
+fn print_value(key: Key, value: &DataStore, hex: bool) -> Result<()> {
+    match value {
+        DataStore::Value(row) if hex => {
+            println!("[{}] {} bytes", style(key).green(), row.len());
+            print_hex_view(row)?;
+        }
+        DataStore::Value(row) => println!("[{}] {} bytes", style(key).green(), row.len()),
+        _ => println!("[{}] {}", style(key).green(), style(value).red()),
+    }
+
+    Ok(())
+}
+
+async fn dump_meta(dir: &Path) -> Result<()> {
+    let manifest = Manifest::from_io(&open(&dir.join(".meta")).await?).await?;
+
+    println!("Table id   : {:x}", manifest.table_id);
+    println!("Row size   : {}", manifest.row_size);
+    println!("Columns    : {:?}", manifest.sstable_options.columns);
+    println!("Options    : {}", manifest.options);
+    println!("SSTables   : {}", manifest.table_count());
+
+    for table in manifest.tables() {
+        let meta = table.meta();
+        println!(
+            "  {:x} {} {:>8} entries ({} deleted), key [{},{}], {}",
+            meta.key,
+            style(describe_key(meta.key)).cyan(),
+            meta.entries_count,
+            meta.deleted_count,
+            meta.min_key,
+            meta.max_key,
+            HumanBytes(meta.file_size)
+        );
+    }
+
+    Ok(())
+}
+
+async fn list_sstables(dir: &Path) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(key) = entry
+            .file_name()
+            .to_str()
+            .and_then(SSTableKey::from_file_name)
+        {
+            files.push((key, entry.path(), entry.metadata()?.len()));
+        }
+    }
+    files.sort();
+
+    for (key, path, size) in files {
+        println!(
+            "{} {} {}",
+            style(path.file_name().unwrap().to_string_lossy()).yellow(),
+            style(describe_key(key)).cyan(),
+            HumanBytes(size)
+        );
+    }
+
+    Ok(())
+}
+
+async fn dump_sstable(file: &Path, hex: bool) -> Result<()> {
+    let mut magic_number = [0; 4];
+    std::fs::File::open(file)?.read_exact(&mut magic_number)?;
+
+    let mut iter = SSTableIter::new(open(file).await?).await?;
+    let (min_key, max_key) = iter.key_range();
+
+    if let Some(key) = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(SSTableKey::from_file_name)
+    {
+        println!("Key        : {:x} {}", key, describe_key(key));
+    }
+    println!("Magic      : {}", String::from_utf8_lossy(&magic_number));
+    println!("Codec      : {}", iter.compression());
+    println!(
+        "Layout     : {}, restart every {} keys",
+        if iter.columnar() { "columns" } else { "rows" },
+        iter.restart_interval()
+    );
+    println!(
+        "Entries    : {} ({} deleted)",
+        iter.entries_count(),
+        iter.deleted_count()
+    );
+    println!("Key range  : [{},{}]", min_key, max_key);
+    println!("Checksum   : {:08x}", iter.raw_checksum());
+    println!("File size  : {}", HumanBytes(iter.file_size()));
+    println!("Footer     : {:?}", iter.footer());
+
+    let index = iter.index().await?;
+    println!("Blocks     : {}", index.len());
+    for handle in index.iter() {
+        println!(
+            "  @{:<8} {:>6} bytes {:>5} entries, key [{},{}], checksum {:08x}",
+            handle.offset,
+            handle.len,
+            handle.entries,
+            handle.first_key,
+            handle.last_key,
+            handle.checksum
+        );
+    }
+
+    iter.init_iter().await?;
+    while let Some((key, value)) = iter.next().await? {
+        print_value(key, &value, hex)?;
+    }
+
+    Ok(())
+}
+
+async fn dump_cache(dir: &Path, hex: bool) -> Result<()> {
+    let path = dir.join(".cache");
+    let memtable = MemTable::from_io(&open(&path).await?).await?;
+
+    println!("Entries    : {}", memtable.iter().await.len());
+    for (key, value) in memtable.iter().await {
+        print_value(key, &value, hex)?;
+    }
+
+    Ok(())
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The text of the row images in the exported files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowEncoding {
+    Hex,
+    Base64,
+}
+
+impl RowEncoding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hex => "hex",
+            Self::Base64 => "base64",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(Self::Hex),
+            "base64" => Some(Self::Base64),
+            _ => None,
+        }
+    }
+
+    fn encode(self, row: &[u8]) -> String {
+        let mut text = String::with_capacity(row.len() * 2);
+        match self {
+            Self::Hex => row.iter().for_each(|byte| {
+                write!(text, "{:02x}", byte).unwrap();
+            }),
+            Self::Base64 => {
+                for chunk in row.chunks(3) {
+                    let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+                        bits | (*byte as u32) << (16 - 8 * i)
+                    });
+                    for i in 0..4 {
+                        match i <= chunk.len() {
+                            true => text.push(
+                                BASE64_ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3f] as char,
+                            ),
+                            false => text.push('='),
+                        }
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    fn decode(self, text: &str) -> Option<Vec<u8>> {
+        let text = text.as_bytes();
+        match self {
+            Self::Hex if text.len().is_multiple_of(2) => text
+                .chunks(2)
+                .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect(),
+            Self::Base64 if text.len().is_multiple_of(4) => {
+                let mut row = Vec::with_capacity(text.len() / 4 * 3);
+                for chunk in text.chunks(4) {
+                    let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+                    if padding > 2 {
+                        return None;
+                    }
+
+                    let mut bits = 0u32;
+                    for (i, c) in chunk[..4 - padding].iter().enumerate() {
+                        let value = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+                        bits |= value << (18 - 6 * i);
+                    }
+                    row.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+                }
+                Some(row)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The format of the exported files, by the extension.
+///
+/// A CSV file starts with the header `key,hex` or `key,base64`, and a JSON
+/// line is `{"key":1,"hex":"..."}` or `{"key":1,"base64":"..."}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    JsonLines,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("jsonl" | "ndjson" | "json") => Ok(Self::JsonLines),
+            _ => Err(DbError::Other(format!(
+                "Unknown format of {}, expect .csv or .jsonl",
+                path.display()
+            ))),
+        }
+    }
+
+    fn header(self, encoding: RowEncoding) -> Option<String> {
+        match self {
+            Self::Csv => Some(format!("key,{}", encoding.name())),
+            Self::JsonLines => None,
+        }
+    }
+
+    fn format_row(self, key: Key, row: &[u8], encoding: RowEncoding) -> String {
+        match self {
+            Self::Csv => format!("{},{}", key, encoding.encode(row)),
+            Self::JsonLines => format!(
+                "{{\"key\":{},\"{}\":\"{}\"}}",
+                key,
+                encoding.name(),
+                encoding.encode(row)
+            ),
+        }
+    }
+
+    /// Parse a row, `encoding` is taken from the header of a CSV file.
+    fn parse_row(self, line: &str, encoding: Option<RowEncoding>) -> Option<(Key, DataInner)> {
+        match self {
+            Self::Csv => {
+                let (key, row) = line.split_once(',')?;
+                Some((key.trim().parse().ok()?, encoding?.decode(row.trim())?))
+            }
+            Self::JsonLines => {
+                let fields = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+                let (mut key, mut row) = (None, None);
+
+                // the values never contain a comma
+                for field in fields.split(',') {
+                    let (name, value) = field.split_once(':')?;
+                    let name = name.trim().strip_prefix('"')?.strip_suffix('"')?;
+                    let value = value.trim();
+
+                    match name {
+                        "key" => key = Some(value.parse().ok()?),
+                        _ => {
+                            let text = value.strip_prefix('"')?.strip_suffix('"')?;
+                            row = Some(RowEncoding::from_name(name)?.decode(text)?);
+                        }
+                    }
+                }
+
+                Some((key?, row?))
+            }
+        }
+    }
+}
+
+/// Export the rows of a table, the deleted ones are skipped.
+///
+/// The manifest, the SSTables and the memtable cache are only read like
+/// the other commands do, nothing is written into the table directory.
+async fn export_table(dir: &Path, file: &Path, encoding: RowEncoding) -> Result<()> {
+    let format = Format::from_path(file)?;
+    let manifest = Manifest::from_io(&open(&dir.join(".meta")).await?).await?;
+
+    // the rows of the memtable cache are newer than the SSTables
+    let path = dir.join(".cache");
+    let cache = match path.is_file() {
+        true => match MemTable::from_io(&open(&path).await?).await {
+            Ok(memtable) => memtable.iter().await.collect(),
+            Err(DbError::EmptyFile) => Vec::new(),
+            Err(err) => return Err(err),
+        },
+        false => Vec::new(),
+    };
+
+    let mut writer = BufWriter::new(File::create(file)?);
+    if let Some(header) = format.header(encoding) {
+        writeln!(writer, "{}", header)?;
+    }
+
+    // the SSTables are iterated from the newest one, the first
+    // version of a key is the live one
+    let mut cache = cache.into_iter();
+    let mut lsm_iter = manifest.iter();
+    let mut yielded = HashSet::new();
+    let mut count = 0;
+    loop {
+        let (key, value) = match cache.next() {
+            Some(kvstore) => kvstore,
+            None => match lsm_iter.next().await? {
+                Some(kvstore) => kvstore,
+                None => break,
+            },
+        };
+
+        if yielded.insert(key) {
+            if let DataStore::Value(row) = value {
+                writeln!(writer, "{}", format.format_row(key, &row, encoding))?;
+                count += 1;
+            }
+        }
+    }
+    writer.flush()?;
+
+    println!("Exported {} rows to {}", count, file.display());
+
+    Ok(())
+}
+
+/// Import the exported rows through the `Table` API into a new table.
+async fn import_table(file: &Path, dir: &str) -> Result<()> {
+    let format = Format::from_path(file)?;
+    if Path::new(dir).join(".meta").exists() {
+        return Err(DbError::Other(format!("{} is not a new table", dir)));
+    }
+
+    let mut lines = BufReader::new(File::open(file)?).lines();
+    let encoding = match format {
+        Format::Csv => {
+            let header = lines.next().transpose()?.unwrap_or_default();
+            let encoding = header
+                .trim()
+                .strip_prefix("key,")
+                .and_then(RowEncoding::from_name);
+            if encoding.is_none() {
+                return Err(DbError::Other(format!("Invalid CSV header {:?}", header)));
+            }
+            encoding
+        }
+        Format::JsonLines => None,
+    };
+
+    let table = Table::open(dir.to_string()).await?;
+    let mut count = 0;
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (key, row) = format.parse_row(&line, encoding).ok_or_else(|| {
+            let line_no = i + 1 + encoding.is_some() as usize;
+            DbError::Other(format!("Invalid row at {}:{}", file.display(), line_no))
+        })?;
+        table.set(key, row).await;
+        count += 1;
+    }
+
+    table.flush().await?;
+    table.close().await?;
+
+    println!("Imported {} rows to {}", count, dir);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_works() {
+        for len in 0..8 {
+            let row = (0..len).map(|i| (i * 37) as u8).collect::<Vec<_>>();
+            for encoding in [RowEncoding::Hex, RowEncoding::Base64] {
+                assert_eq!(encoding.decode(&encoding.encode(&row)), Some(row.clone()));
+            }
+        }
+
+        assert_eq!(RowEncoding::Base64.encode(b"yydb"), "eXlkYg==");
+        assert_eq!(RowEncoding::Hex.decode("0g"), None);
+        assert_eq!(RowEncoding::Base64.decode("eXlk=Yg="), None);
+
+        for format in [Format::Csv, Format::JsonLines] {
+            for encoding in [RowEncoding::Hex, RowEncoding::Base64] {
+                let line = format.format_row(42, b"row", encoding);
+                assert_eq!(
+                    format.parse_row(&line, Some(encoding)),
+                    Some((42, b"row".to_vec()))
+                );
+            }
+        }
+        assert_eq!(
+            Format::JsonLines.parse_row(r#"{ "key": 7, "hex": "ff" }"#, None),
+            Some((7, vec![0xff]))
+        );
+    }
+
+    async fn export_works_async() -> Result<()> {
+        let test_dir = Path::new("helper/tool_export_test");
+        let file = Path::new("helper/tool_export_test.csv");
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let table = Table::open(test_dir.to_string_lossy().to_string()).await?;
+        for i in 0..0x100u64 {
+            table.set(i, vec![i as u8; 0x10]).await;
+        }
+        table.flush().await?;
+        // the newer versions and the deletes stay in the memtable cache
+        for i in 0..0x10u64 {
+            table.set(i, vec![0xff; 0x10]).await;
+            table.delete(0x80 + i).await;
+        }
+        table.close().await?;
+        drop(table);
+
+        let snapshot = || -> Result<Vec<_>> {
+            let mut files = std::fs::read_dir(test_dir)?
+                .map(|entry| {
+                    let meta = entry?.metadata()?;
+                    Ok((meta.len(), meta.modified()?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            files.sort();
+            Ok(files)
+        };
+        let before = snapshot()?;
+
+        export_table(test_dir, file, RowEncoding::Hex).await?;
+        assert_eq!(snapshot()?, before);
+
+        let rows = std::fs::read_to_string(file)?
+            .lines()
+            .skip(1)
+            .map(|line| Format::Csv.parse_row(line, Some(RowEncoding::Hex)).unwrap())
+            .collect::<std::collections::BTreeMap<_, _>>();
+        assert_eq!(rows.len(), 0xf0);
+        assert_eq!(rows[&0], vec![0xff; 0x10]);
+        assert_eq!(rows[&0x10], vec![0x10; 0x10]);
+        assert!(!rows.contains_key(&0x80));
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_works() {
+        block_on(async {
+            export_works_async().await.unwrap();
+        });
+    }
+}
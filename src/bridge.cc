@@ -1,4 +1,6 @@
+#include <algorithm>
 #include <cstdio>
+#include <string>
 
 #include "yydb/include/bridge.h"
 #include "yydb/src/bridge.rs.h"
@@ -11,9 +13,9 @@ namespace yydb {
     /* End of logging */
 
     /* Lifecycle */
-    int ha_yydb_core_init() {
-        rust_init();
-        return 0;
+    int ha_yydb_core_init(const char* options) {
+        rust::Str options_str(options ? options : "");
+        return rust_init(options_str) ? 0 : 1;
     }
 
     int ha_yydb_core_deinit() {
@@ -22,6 +24,48 @@ namespace yydb {
     }
     /* End of lifecycle */
 
+    /* Options */
+    int ha_yydb_set_option(const char* name, const char* value) {
+        rust::Str name_str(name);
+        rust::Str value_str(value);
+        return set_option(name_str, value_str) ? 0 : 1;
+    }
+
+    int ha_yydb_check_option(const char* name, const char* value) {
+        rust::Str name_str(name);
+        rust::Str value_str(value);
+        return check_option(name_str, value_str) ? 0 : 1;
+    }
+
+    int ha_yydb_set_log_level(const char* level) {
+        rust::Str level_str(level);
+        return set_log_level(level_str) ? 0 : 1;
+    }
+
+    int ha_yydb_set_log_filters(const char* filters) {
+        rust::Str filters_str(filters ? filters : "");
+        return set_log_filters(filters_str) ? 0 : 1;
+    }
+
+    int ha_yydb_set_log_file(const char* path, uint64_t max_size, uint32_t max_files) {
+        rust::Str path_str(path ? path : "");
+        return set_log_file(path_str, max_size, max_files) ? 0 : 1;
+    }
+    /* End of options */
+
+    /* Status */
+    std::string ha_yydb_engine_status() {
+        return std::string(engine_status());
+    }
+
+    uint ha_yydb_status_values(uint64_t* values, uint count) {
+        rust::Vec<uint64_t> status = engine_status_values();
+        uint len = std::min<uint>(count, status.size());
+        std::copy(status.begin(), status.begin() + len, values);
+        return len;
+    }
+    /* End of status */
+
     /* Table */
     std::uint64_t ha_yydb_open_table(const char* name) {
         rust::Str name_str(name);
@@ -32,12 +76,32 @@ namespace yydb {
         close_table(table_id);
     }
 
-    void ha_yydb_insert_row(uint64_t table_id, uint64_t key, const u_char* row, uint length) {
-        insert_row(table_id, key, (const uint8_t*)row, length);
+    int ha_yydb_insert_row(uint64_t table_id, uint64_t key, const u_char* row, uint length) {
+        return insert_row(table_id, key, (const uint8_t*)row, length);
     }
 
-    void ha_yydb_update_row(uint64_t table_id, uint64_t key, const u_char* old, const u_char* row, uint length) {
-        update_row(table_id, key, (const uint8_t*)old, (const uint8_t*)row, length);
+    int ha_yydb_update_row(uint64_t table_id, uint64_t key, const u_char* old, const u_char* row, uint length) {
+        return update_row(table_id, key, (const uint8_t*)old, (const uint8_t*)row, length);
+    }
+
+    uint64_t ha_yydb_begin_bulk_insert(uint64_t table_id) {
+        return begin_bulk_insert(table_id);
+    }
+
+    int ha_yydb_bulk_insert_row(uint64_t bulk_id, uint64_t key, const u_char* row, uint length) {
+        return bulk_insert_row(bulk_id, key, (const uint8_t*)row, length);
+    }
+
+    int ha_yydb_end_bulk_insert(uint64_t bulk_id, bool abort) {
+        return end_bulk_insert(bulk_id, abort);
+    }
+
+    void ha_yydb_set_columns(uint64_t table_id, uint row_size, const uint32_t* offsets, uint count) {
+        set_columns(table_id, row_size, rust::Slice<const uint32_t>(offsets, count));
+    }
+
+    void ha_yydb_set_projection(uint64_t table_id, const uint32_t* fields, uint count) {
+        set_projection(table_id, rust::Slice<const uint32_t>(fields, count));
     }
 
     void ha_yydb_put_hex(const u_char* data, uint length) {
@@ -48,6 +112,18 @@ namespace yydb {
         return rnd_next(table_id, (uint8_t*)buf, length);
     }
 
+    int ha_yydb_check_table(uint64_t table_id) {
+        return check_table(table_id);
+    }
+
+    int ha_yydb_repair_table(uint64_t table_id) {
+        return repair_table(table_id);
+    }
+
+    int ha_yydb_compact_table(uint64_t table_id) {
+        return compact_table(table_id);
+    }
+
     void ha_yydb_delete_table(const char * name){
         rust::Str name_str(name);
         delete_table(name_str);
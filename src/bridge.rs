@@ -5,32 +5,70 @@ use crate::*;
 pub mod ffi {
     // Rust types and signatures exposed to C++.
     extern "Rust" {
-        // Init the rust part of the library.
-        pub fn rust_init();
+        // Init the rust part of the library with the global options,
+        // return false if the options are invalid.
+        pub fn rust_init(options: &str) -> bool;
 
         // Deinit the rust part of the library.
         pub fn rust_deinit();
 
+        // set a global option by name, return false if it is invalid.
+        pub fn set_option(name: &str, value: &str) -> bool;
+
+        // check a global option without setting it, return false if it is invalid.
+        pub fn check_option(name: &str, value: &str) -> bool;
+
+        // set the default level of the logs, return false if it is unknown.
+        pub fn set_log_level(level: &str) -> bool;
+
+        // set the levels of the modules, return false if they are invalid.
+        pub fn set_log_filters(filters: &str) -> bool;
+
+        // write the logs to a rotating file, or the mysql log if the path is empty.
+        pub fn set_log_file(path: &str, max_size: u64, max_files: u32) -> bool;
+
+        // get the status of the engine as text.
+        pub fn engine_status() -> String;
+
+        // get the values of the status variables, in the order of the handler.
+        pub fn engine_status_values() -> Vec<u64>;
+
         // Open a table by name.
         pub fn open_table(table_name: &str) -> u64;
 
         // close a table by id.
         pub fn close_table(id: u64);
 
-        // insert a row to a table.
-        pub unsafe fn insert_row(table_id: u64, key: u64, data: *const u8, len: u32);
+        // insert a row to a table, return a mysql error code.
+        pub unsafe fn insert_row(table_id: u64, key: u64, data: *const u8, len: u32) -> i32;
 
-        // update a row to a table.
+        // update a row to a table, return a mysql error code.
         pub unsafe fn update_row(
             table_id: u64,
             key: u64,
             data: *const u8,
             new_data: *const u8,
             len: u32,
-        );
+        ) -> i32;
+
+        // delete a row to a table, return a mysql error code.
+        pub unsafe fn delete_row(table_id: u64, key: u64) -> i32;
 
-        // delete a row to a table.
-        pub unsafe fn delete_row(table_id: u64, key: u64);
+        // begin a bulk insert to a table, return the id of the session,
+        // or 0 if the rows should be inserted one by one.
+        pub fn begin_bulk_insert(table_id: u64) -> u64;
+
+        // buffer a row of a bulk insert, return a mysql error code.
+        pub unsafe fn bulk_insert_row(bulk_id: u64, key: u64, data: *const u8, len: u32) -> i32;
+
+        // write or drop the rows of a bulk insert, return a mysql error code.
+        pub fn end_bulk_insert(bulk_id: u64, abort: bool) -> i32;
+
+        // split the rows of a table into columns by the field offsets.
+        pub fn set_columns(table_id: u64, row_size: u32, field_offsets: &[u32]);
+
+        // only read the fields in the next round.
+        pub fn set_projection(table_id: u64, fields: &[u32]);
 
         // init round
         pub fn rnd_init(table_id: u64);
@@ -38,9 +76,19 @@ pub mod ffi {
         // end round
         pub fn rnd_end(table_id: u64);
 
-        // read next row
+        // read next row, return 1 for a row, 0 for the end,
+        // and a negative mysql error code on error.
         pub unsafe fn rnd_next(table_id: u64, buf: *mut u8, len: u32) -> i32;
 
+        // verify a table, return a mysql admin code.
+        pub fn check_table(table_id: u64) -> i32;
+
+        // repair a table, return a mysql admin code.
+        pub fn repair_table(table_id: u64) -> i32;
+
+        // compact all the data of a table, return a mysql admin code.
+        pub fn compact_table(table_id: u64) -> i32;
+
         // put hex data to log
         pub unsafe fn put_hex(data: *const u8, len: u32);
 
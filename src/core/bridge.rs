@@ -1,8 +1,95 @@
 use crate::{
     structs::{kvstore::*, table::TableId},
-    utils::DataStore,
+    utils::{DataStore, DbError},
 };
 
+/// `HA_ERR_CRASHED` of MySQL, the table is corrupted.
+pub const HA_ERR_CRASHED: i32 = 126;
+
+/// `HA_ERR_GENERIC` of MySQL.
+pub const HA_ERR_GENERIC: i32 = 168;
+
+/// The results of `CHECK TABLE` and `REPAIR TABLE`, `HA_ADMIN_*` of MySQL.
+pub const HA_ADMIN_OK: i32 = 0;
+pub const HA_ADMIN_FAILED: i32 = -2;
+pub const HA_ADMIN_CORRUPT: i32 = -3;
+
+/// Set a global option by name, return false if it is invalid.
+pub fn set_option(name: &str, value: &str) -> bool {
+    let (name, value) = (name.to_string(), value.to_string());
+
+    run_async! {
+        match super::Runtime::global().set_option(&name, &value).await {
+            Ok(()) => true,
+            Err(err) => {
+                error!("Set option failed   : {}={} {:?}", name, value, err);
+                false
+            }
+        }
+    }
+}
+
+/// Check a global option without setting it, return false if it is invalid.
+pub fn check_option(name: &str, value: &str) -> bool {
+    let mut options = super::Runtime::global().options();
+    match options.set(name, value) {
+        Ok(()) => true,
+        Err(err) => {
+            warn!("Invalid option      : {}={} {:?}", name, value, err);
+            false
+        }
+    }
+}
+
+/// Set the default level of the logs, return false if it is unknown.
+pub fn set_log_level(level: &str) -> bool {
+    match crate::utils::logger::set_level(level) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Set log level failed: {:?}", err);
+            false
+        }
+    }
+}
+
+/// Set the levels of the modules, e.g. `structs::lsm=trace`,
+/// return false if they are invalid.
+pub fn set_log_filters(filters: &str) -> bool {
+    match crate::utils::logger::set_filters(filters) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Set log filters failed: {:?}", err);
+            false
+        }
+    }
+}
+
+/// Write the logs to a rotating file, or the MySQL error log if the
+/// path is empty, return false if the file can't be opened.
+pub fn set_log_file(path: &str, max_size: u64, max_files: u32) -> bool {
+    match crate::utils::logger::set_file(Some(path), max_size, max_files as usize) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Set log file failed : {:?} {:?}", path, err);
+            false
+        }
+    }
+}
+
+/// Get the status of the engine for `SHOW ENGINE YYDB STATUS`.
+pub fn engine_status() -> String {
+    run_async! {
+        super::Runtime::global().status().await.to_string()
+    }
+}
+
+/// Get the values of the status variables, see `EngineStatus::values`.
+pub fn engine_status_values() -> Vec<u64> {
+    run_async! {
+        super::Runtime::global().status().await.values()
+    }
+}
+
 #[inline(always)]
 pub fn open_table(table_name: &str) -> u64 {
     let table_name = table_name.to_string();
@@ -26,7 +113,8 @@ pub fn close_table(id: u64) {
 
 /// # Safety
 /// mysql will pass a pointer to a buffer, and we need to get data from it
-pub unsafe fn insert_row(table_id: u64, key: u64, data: *const u8, len: u32) {
+/// Return `HA_ERR_CRASHED` if the table is corrupted, otherwise 0.
+pub unsafe fn insert_row(table_id: u64, key: u64, data: *const u8, len: u32) -> i32 {
     trace!(
         "Inserting row       : [{:3<}]<{}> @{:016x}",
         key,
@@ -41,17 +129,32 @@ pub unsafe fn insert_row(table_id: u64, key: u64, data: *const u8, len: u32) {
     }
 
     run_async! {
-        if let Some(table) = super::Runtime::global().get_table(&TableId(table_id)).await {
+        let runtime = super::Runtime::global();
+        if runtime.is_corrupted(&TableId(table_id)).await {
+            warn!("Write refused       : @{:016x}", table_id);
+            return HA_ERR_CRASHED;
+        }
+
+        if let Some(table) = runtime.get_table(&TableId(table_id)).await {
             table.set(key, data.to_vec()).await;
         } else {
             warn!("Table not found     : @{:016x}", table_id);
         }
+
+        0
     }
 }
 
 /// # Safety
 /// mysql will pass a pointer to a buffer, and we need to get data from it
-pub unsafe fn update_row(table_id: u64, key: u64, _data: *const u8, new_data: *const u8, len: u32) {
+/// Return `HA_ERR_CRASHED` if the table is corrupted, otherwise 0.
+pub unsafe fn update_row(
+    table_id: u64,
+    key: u64,
+    _data: *const u8,
+    new_data: *const u8,
+    len: u32,
+) -> i32 {
     trace!(
         "Updating row        : [{:3<}]<{}> @{:016x}",
         key,
@@ -62,23 +165,222 @@ pub unsafe fn update_row(table_id: u64, key: u64, _data: *const u8, new_data: *c
     let new_data = std::slice::from_raw_parts(new_data, len as usize);
 
     run_async! {
-        if let Some(table) = super::Runtime::global().get_table(&TableId(table_id)).await {
+        let runtime = super::Runtime::global();
+        if runtime.is_corrupted(&TableId(table_id)).await {
+            warn!("Write refused       : @{:016x}", table_id);
+            return HA_ERR_CRASHED;
+        }
+
+        if let Some(table) = runtime.get_table(&TableId(table_id)).await {
             table.set(key, new_data.to_vec()).await;
         } else {
             warn!("Table not found     : @{:016x}", table_id);
         }
+
+        0
     }
 }
 
-pub fn delete_row(table_id: u64, key: u64) {
+/// Return `HA_ERR_CRASHED` if the table is corrupted, otherwise 0.
+pub fn delete_row(table_id: u64, key: u64) -> i32 {
     trace!("Deleting row        : [{:3<}] @{:016x}", key, table_id);
 
     run_async! {
-        if let Some(table) = super::Runtime::global().get_table(&TableId(table_id)).await {
+        let runtime = super::Runtime::global();
+        if runtime.is_corrupted(&TableId(table_id)).await {
+            warn!("Write refused       : @{:016x}", table_id);
+            return HA_ERR_CRASHED;
+        }
+
+        if let Some(table) = runtime.get_table(&TableId(table_id)).await {
             table.delete(key).await;
         } else {
             warn!("Table not found     : @{:016x}", table_id);
         }
+
+        0
+    }
+}
+
+/// Return 0 if the table is not found or corrupted, the rows are inserted
+/// by `insert_row` then.
+pub fn begin_bulk_insert(table_id: u64) -> u64 {
+    run_async! {
+        let runtime = super::Runtime::global();
+        if runtime.is_corrupted(&TableId(table_id)).await {
+            return 0;
+        }
+
+        match runtime.get_table(&TableId(table_id)).await {
+            Some(table) => runtime.begin_bulk_insert(table),
+            None => {
+                warn!("Table not found     : @{:016x}", table_id);
+                0
+            }
+        }
+    }
+}
+
+/// # Safety
+/// mysql will pass a pointer to a buffer, and we need to get data from it
+/// Only block on the runtime when a batch is full.
+pub unsafe fn bulk_insert_row(bulk_id: u64, key: u64, data: *const u8, len: u32) -> i32 {
+    trace!("Bulk inserting row  : [{:3<}]<{}> #{}", key, len, bulk_id);
+
+    let data = std::slice::from_raw_parts(data, len as usize).to_vec();
+    let runtime = super::Runtime::global();
+
+    match runtime.with_bulk_insert(bulk_id, |bulk| bulk.insert(key, data)) {
+        Some(false) => return 0,
+        Some(true) => (),
+        None => {
+            warn!("Bulk insert not found: #{}", bulk_id);
+            return HA_ERR_GENERIC;
+        }
+    }
+
+    let mut bulk = runtime.take_bulk_insert(bulk_id).unwrap();
+    run_async! {
+        let ret = bulk.spill().await;
+        super::Runtime::global().put_bulk_insert(bulk_id, bulk);
+
+        match ret {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("Bulk insert failed  : {:?} #{}", err, bulk_id);
+                HA_ERR_GENERIC
+            }
+        }
+    }
+}
+
+pub fn end_bulk_insert(bulk_id: u64, abort: bool) -> i32 {
+    let bulk = match super::Runtime::global().take_bulk_insert(bulk_id) {
+        Some(bulk) => bulk,
+        None => {
+            warn!("Bulk insert not found: #{}", bulk_id);
+            return HA_ERR_GENERIC;
+        }
+    };
+
+    if abort {
+        bulk.abort();
+        return 0;
+    }
+
+    run_async! {
+        match bulk.finish().await {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("Bulk insert failed  : {:?} #{}", err, bulk_id);
+                HA_ERR_GENERIC
+            }
+        }
+    }
+}
+
+pub fn set_columns(table_id: u64, row_size: u32, field_offsets: &[u32]) {
+    let field_offsets = field_offsets.to_vec();
+
+    run_async! {
+        if let Some(table) = super::Runtime::global().get_table(&TableId(table_id)).await {
+            debug!("Set columns         : {:?} @{:016x}", field_offsets, table_id);
+            if let Err(err) = table.set_columns(row_size, &field_offsets).await {
+                error!("Set columns failed  : {:?} @{:016x}", err, table_id);
+            }
+        } else {
+            warn!("Table not found     : @{:016x}", table_id);
+        }
+    }
+}
+
+pub fn set_projection(table_id: u64, fields: &[u32]) {
+    let fields = fields.to_vec();
+
+    run_async! {
+        if let Some(table) = super::Runtime::global().get_table(&TableId(table_id)).await {
+            trace!("Set projection      : {:?} @{:016x}", fields, table_id);
+            table.set_projection(&fields).await;
+        } else {
+            warn!("Table not found     : @{:016x}", table_id);
+        }
+    }
+}
+
+/// Verify the SSTables of a table, the table is marked as corrupted
+/// if any of them is damaged.
+pub fn check_table(table_id: u64) -> i32 {
+    run_async! {
+        let runtime = super::Runtime::global();
+        let table = match runtime.get_table(&TableId(table_id)).await {
+            Some(table) => table,
+            None => {
+                warn!("Table not found     : @{:016x}", table_id);
+                return HA_ADMIN_FAILED;
+            }
+        };
+
+        match table.check().await {
+            Ok(report) if report.is_ok() => {
+                info!("Table checked       : {} sstables @{:016x}", report.tables.len(), table_id);
+                HA_ADMIN_OK
+            }
+            Ok(report) => {
+                error!("Table check failed  : {} damaged @{:016x}", report.damaged().count(), table_id);
+                runtime.mark_corrupted(&TableId(table_id)).await;
+                HA_ADMIN_CORRUPT
+            }
+            Err(err) => {
+                error!("Table check failed  : {:?} @{:016x}", err, table_id);
+                HA_ADMIN_FAILED
+            }
+        }
+    }
+}
+
+/// Rebuild the manifest of a table from the SSTables passing the check.
+pub fn repair_table(table_id: u64) -> i32 {
+    run_async! {
+        let table = match super::Runtime::global().get_table(&TableId(table_id)).await {
+            Some(table) => table,
+            None => {
+                warn!("Table not found     : @{:016x}", table_id);
+                return HA_ADMIN_FAILED;
+            }
+        };
+
+        match table.repair().await {
+            Ok(_) => HA_ADMIN_OK,
+            Err(err) => {
+                error!("Table repair failed : {:?} @{:016x}", err, table_id);
+                HA_ADMIN_FAILED
+            }
+        }
+    }
+}
+
+/// Merge all the SSTables of a table into the bottom level.
+pub fn compact_table(table_id: u64) -> i32 {
+    run_async! {
+        let table = match super::Runtime::global().get_table(&TableId(table_id)).await {
+            Some(table) => table,
+            None => {
+                warn!("Table not found     : @{:016x}", table_id);
+                return HA_ADMIN_FAILED;
+            }
+        };
+
+        match table.compact_all().await {
+            Ok(_) => HA_ADMIN_OK,
+            Err(DbError::MissChecksum) => {
+                super::Runtime::global().mark_corrupted(&TableId(table_id)).await;
+                HA_ADMIN_CORRUPT
+            }
+            Err(err) => {
+                error!("Compaction failed   : {:?} @{:016x}", err, table_id);
+                HA_ADMIN_FAILED
+            }
+        }
     }
 }
 
@@ -132,21 +434,25 @@ pub unsafe fn rnd_next(table_id: u64, buf: *mut u8, len: u32) -> i32 {
 
                     if len != value_len {
                         error!("Buffer size mismatch: {} != {}", len, value_len);
-                        return -1;
+                        return -HA_ERR_GENERIC;
                     }
 
                     buf.copy_from_slice(value);
                     1
                 }
+                Err(DbError::MissChecksum) => {
+                    super::Runtime::global().mark_corrupted(&TableId(table_id)).await;
+                    -HA_ERR_CRASHED
+                }
                 Err(e) => {
                     error!("Error while reading next row: {:#?}", e);
-                    -1
+                    -HA_ERR_GENERIC
                 },
                 _ => 0
             }
         }  else {
             warn!("Table not found     : @{:016x}", table_id);
-            -1
+            -HA_ERR_GENERIC
         }
     }
 }
@@ -154,12 +460,12 @@ pub unsafe fn rnd_next(table_id: u64, buf: *mut u8, len: u32) -> i32 {
 #[inline(always)]
 pub fn delete_table(table_name: &str) {
     let table_name = table_name.to_string();
-    let id = TableId::new(&table_name);
 
     run_async! {
-        if super::Runtime::global().contains_table(&id).await {
-            super::Runtime::global().close_table(&id).await;
+        if let Some(table) = super::Runtime::global().get_table_by_name(&table_name).await {
+            super::Runtime::global().close_table(&table.id()).await;
         }
+        super::Runtime::global().clear_corrupted(&TableId::new(&table_name)).await;
 
         // do fs cleanup
         std::fs::remove_dir_all(table_name).ok();
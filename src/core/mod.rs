@@ -1,5 +1,12 @@
 #[cfg(feature = "mysql")]
 pub mod bridge;
 
+pub mod options;
 pub mod runtime;
+pub mod scheduler;
+pub mod status;
+
+pub use options::*;
 pub use runtime::*;
+pub use scheduler::*;
+pub use status::*;
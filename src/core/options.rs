@@ -0,0 +1,473 @@
+use bincode::{Decode, Encode};
+use std::fmt::Display;
+
+use crate::structs::{StallLimits, WalSyncMode};
+use crate::utils::*;
+
+/// The options of the engine, the global defaults are held by `Runtime`
+/// and every table may override a part of them, see `TableOptions`.
+///
+/// The options are set by `name=value` pairs, separated by commas,
+/// semicolons or whitespaces, e.g. `memtable_size=8M,compression=lz4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// The size of a memtable before it is flushed to L0.
+    pub memtable_size: usize,
+    /// The number of tables at a level to be compacted together.
+    pub compact_threshold: usize,
+    /// The codec of the data blocks in new SSTables.
+    pub compression: Compression,
+    /// Build range filters for new SSTables.
+    pub range_filter: bool,
+    /// Train a zstd dictionary when the SSTables are compacted.
+    pub zstd_dictionary: bool,
+    pub stall_limits: StallLimits,
+    /// The max keys a bloom filter is sized for, the global only.
+    pub max_filter_keys: usize,
+    /// The zstd level of the manifest, the memtable cache and the
+    /// block index, the global only.
+    pub meta_compression_level: u32,
+    /// The rows of a bulk insert buffered before they are sorted into
+    /// a SSTable, the global only.
+    pub bulk_insert_batch_size: usize,
+    /// The number of workers running the flushes and compactions,
+    /// the global only.
+    pub background_workers: usize,
+    /// The bytes of the decompressed blocks cached for all the tables,
+    /// 0 to disable the cache, the global only.
+    pub block_cache_size: usize,
+    /// How the writes to the memtables are logged, the global only.
+    pub wal_sync_mode: WalSyncMode,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            memtable_size: 4 << 20,
+            compact_threshold: 3,
+            compression: Compression::default(),
+            range_filter: true,
+            zstd_dictionary: false,
+            stall_limits: StallLimits::default(),
+            max_filter_keys: 0xff000,
+            meta_compression_level: DEFAULT_ZSTD_LEVEL as u32,
+            bulk_insert_batch_size: 64 << 20,
+            background_workers: 4,
+            block_cache_size: 8 << 20,
+            wal_sync_mode: WalSyncMode::default(),
+        }
+    }
+}
+
+impl Options {
+    /// Parse the options from the `name=value` pairs, the others are defaults.
+    /// The pairs are checked all together, see `validate`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut options = Self::default();
+
+        for pair in text
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|pair| !pair.is_empty())
+        {
+            let Some((name, value)) = pair.split_once('=') else {
+                return Err(DbError::Other(format!("Invalid option: {}", pair)));
+            };
+            options.apply(name.trim(), value.trim())?;
+        }
+
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Set an option by name, rejected if it conflicts with the others.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let mut options = *self;
+        options.apply(name, value)?;
+        options.validate()?;
+
+        *self = options;
+        Ok(())
+    }
+
+    fn apply(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "max_filter_keys" => self.max_filter_keys = parse_size(name, value)?.max(1),
+            "meta_compression_level" => {
+                self.meta_compression_level = match value.parse() {
+                    Ok(level @ 1..=22) => level,
+                    _ => return Err(invalid(name, value)),
+                }
+            }
+            "bulk_insert_batch_size" => {
+                self.bulk_insert_batch_size = parse_size(name, value)?.max(1)
+            }
+            "background_workers" => {
+                self.background_workers = match parse_size(name, value)? {
+                    workers @ 1..=64 => workers,
+                    _ => return Err(invalid(name, value)),
+                }
+            }
+            "block_cache_size" => self.block_cache_size = parse_size(name, value)?,
+            "wal_sync_mode" => self.wal_sync_mode = value.parse()?,
+            _ => {
+                let mut overrides = TableOptions::default();
+                overrides.apply(self, name, value)?;
+                *self = self.merge_unchecked(&overrides);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the options don't conflict with each other: the memtable must
+    /// be flushed before it reaches the hard limit, or the writes would be
+    /// stopped, and no soft limit is above its hard one.
+    pub fn validate(&self) -> Result<()> {
+        let limits = &self.stall_limits;
+
+        if self.memtable_size >= limits.memtable_hard_bytes {
+            return Err(DbError::Other(format!(
+                "memtable_size {} must be below memtable_hard_bytes {}",
+                self.memtable_size, limits.memtable_hard_bytes
+            )));
+        }
+
+        for (name, soft, hard) in [
+            (
+                "memtable",
+                limits.memtable_soft_bytes as u64,
+                limits.memtable_hard_bytes as u64,
+            ),
+            (
+                "l0_files",
+                limits.l0_soft_files as u64,
+                limits.l0_hard_files as u64,
+            ),
+            (
+                "pending_compaction",
+                limits.pending_compaction_soft_bytes,
+                limits.pending_compaction_hard_bytes,
+            ),
+        ] {
+            if soft > hard {
+                return Err(DbError::Other(format!(
+                    "The soft limit of {} {} is above the hard one {}",
+                    name, soft, hard
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the options of a table, with its overrides.
+    ///
+    /// The overrides of the memtable size and the stall limits are ignored
+    /// if they conflict with the global options, which may be changed after
+    /// the overrides were set.
+    pub fn merge(&self, overrides: &TableOptions) -> Self {
+        let merged = self.merge_unchecked(overrides);
+        if merged.validate().is_ok() {
+            return merged;
+        }
+
+        Self {
+            memtable_size: self.memtable_size,
+            stall_limits: self.stall_limits,
+            ..merged
+        }
+    }
+
+    fn merge_unchecked(&self, overrides: &TableOptions) -> Self {
+        Self {
+            memtable_size: overrides.memtable_size.unwrap_or(self.memtable_size),
+            compact_threshold: overrides
+                .compact_threshold
+                .unwrap_or(self.compact_threshold),
+            compression: overrides.compression.unwrap_or(self.compression),
+            range_filter: overrides.range_filter.unwrap_or(self.range_filter),
+            zstd_dictionary: overrides.zstd_dictionary.unwrap_or(self.zstd_dictionary),
+            stall_limits: overrides.stall_limits.unwrap_or(self.stall_limits),
+            ..*self
+        }
+    }
+}
+
+impl Display for Options {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limits = &self.stall_limits;
+        write!(
+            f,
+            "memtable_size={},compact_threshold={},compression={},range_filter={},\
+             zstd_dictionary={},memtable_soft_bytes={},memtable_hard_bytes={},\
+             l0_soft_files={},l0_hard_files={},pending_compaction_soft_bytes={},\
+             pending_compaction_hard_bytes={},max_filter_keys={},\
+             meta_compression_level={},bulk_insert_batch_size={},background_workers={},\
+             block_cache_size={},wal_sync_mode={}",
+            self.memtable_size,
+            self.compact_threshold,
+            self.compression,
+            self.range_filter,
+            self.zstd_dictionary,
+            limits.memtable_soft_bytes,
+            limits.memtable_hard_bytes,
+            limits.l0_soft_files,
+            limits.l0_hard_files,
+            limits.pending_compaction_soft_bytes,
+            limits.pending_compaction_hard_bytes,
+            self.max_filter_keys,
+            self.meta_compression_level,
+            self.bulk_insert_batch_size,
+            self.background_workers,
+            self.block_cache_size,
+            self.wal_sync_mode
+        )
+    }
+}
+
+/// The options overridden by a table, saved in its manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct TableOptions {
+    pub memtable_size: Option<usize>,
+    pub compact_threshold: Option<usize>,
+    pub compression: Option<Compression>,
+    pub range_filter: Option<bool>,
+    pub zstd_dictionary: Option<bool>,
+    pub stall_limits: Option<StallLimits>,
+}
+
+impl TableOptions {
+    /// Override an option by name, `default` to use the global one again.
+    ///
+    /// A single stall limit is set over the `defaults`, and the stall
+    /// limits are reset all together. Rejected if the options of the table
+    /// would conflict, see `Options::validate`.
+    pub fn set(&mut self, defaults: &Options, name: &str, value: &str) -> Result<()> {
+        let mut overrides = self.clone();
+        overrides.apply(defaults, name, value)?;
+        defaults.merge_unchecked(&overrides).validate()?;
+
+        *self = overrides;
+        Ok(())
+    }
+
+    fn apply(&mut self, defaults: &Options, name: &str, value: &str) -> Result<()> {
+        if value.eq_ignore_ascii_case("default") {
+            return self.reset(name);
+        }
+
+        match name {
+            "memtable_size" => self.memtable_size = Some(parse_size(name, value)?.max(1)),
+            "compact_threshold" => {
+                self.compact_threshold = match parse_size(name, value)? {
+                    threshold @ 2.. => Some(threshold),
+                    _ => return Err(invalid(name, value)),
+                }
+            }
+            "compression" => self.compression = Some(value.parse()?),
+            "range_filter" => self.range_filter = Some(parse_bool(name, value)?),
+            "zstd_dictionary" => self.zstd_dictionary = Some(parse_bool(name, value)?),
+            "memtable_soft_bytes"
+            | "memtable_hard_bytes"
+            | "l0_soft_files"
+            | "l0_hard_files"
+            | "pending_compaction_soft_bytes"
+            | "pending_compaction_hard_bytes" => {
+                let size = parse_size(name, value)?;
+                let limits = self.stall_limits.get_or_insert(defaults.stall_limits);
+                match name {
+                    "memtable_soft_bytes" => limits.memtable_soft_bytes = size,
+                    "memtable_hard_bytes" => limits.memtable_hard_bytes = size,
+                    "l0_soft_files" => limits.l0_soft_files = size,
+                    "l0_hard_files" => limits.l0_hard_files = size,
+                    "pending_compaction_soft_bytes" => {
+                        limits.pending_compaction_soft_bytes = size as u64
+                    }
+                    _ => limits.pending_compaction_hard_bytes = size as u64,
+                }
+            }
+            "max_filter_keys"
+            | "meta_compression_level"
+            | "bulk_insert_batch_size"
+            | "background_workers"
+            | "block_cache_size"
+            | "wal_sync_mode" => {
+                return Err(DbError::Other(format!("Global only option: {}", name)))
+            }
+            _ => return Err(unknown(name)),
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self, name: &str) -> Result<()> {
+        match name {
+            "memtable_size" => self.memtable_size = None,
+            "compact_threshold" => self.compact_threshold = None,
+            "compression" => self.compression = None,
+            "range_filter" => self.range_filter = None,
+            "zstd_dictionary" => self.zstd_dictionary = None,
+            "memtable_soft_bytes"
+            | "memtable_hard_bytes"
+            | "l0_soft_files"
+            | "l0_hard_files"
+            | "pending_compaction_soft_bytes"
+            | "pending_compaction_hard_bytes" => self.stall_limits = None,
+            _ => return Err(unknown(name)),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for TableOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut pairs = Vec::new();
+        if let Some(size) = self.memtable_size {
+            pairs.push(format!("memtable_size={}", size));
+        }
+        if let Some(threshold) = self.compact_threshold {
+            pairs.push(format!("compact_threshold={}", threshold));
+        }
+        if let Some(compression) = self.compression {
+            pairs.push(format!("compression={}", compression));
+        }
+        if let Some(enabled) = self.range_filter {
+            pairs.push(format!("range_filter={}", enabled));
+        }
+        if let Some(enabled) = self.zstd_dictionary {
+            pairs.push(format!("zstd_dictionary={}", enabled));
+        }
+        if let Some(limits) = self.stall_limits {
+            pairs.push(format!(
+                "memtable_soft_bytes={},memtable_hard_bytes={},l0_soft_files={},\
+                 l0_hard_files={},pending_compaction_soft_bytes={},\
+                 pending_compaction_hard_bytes={}",
+                limits.memtable_soft_bytes,
+                limits.memtable_hard_bytes,
+                limits.l0_soft_files,
+                limits.l0_hard_files,
+                limits.pending_compaction_soft_bytes,
+                limits.pending_compaction_hard_bytes
+            ));
+        }
+
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+/// Parse a number with an optional `K`, `M` or `G` suffix.
+fn parse_size(name: &str, value: &str) -> Result<usize> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(1 << shift))
+        .ok_or_else(|| invalid(name, value))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "on" | "true" => Ok(true),
+        "0" | "off" | "false" => Ok(false),
+        _ => Err(invalid(name, value)),
+    }
+}
+
+#[inline]
+fn invalid(name: &str, value: &str) -> DbError {
+    DbError::Other(format!("Invalid value of {}: {}", name, value))
+}
+
+#[inline]
+fn unknown(name: &str) -> DbError {
+    DbError::Other(format!("Unknown option: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() -> Result<()> {
+        let options = Options::parse(
+            "memtable_size=8M, compression=zstd:9;l0_soft_files=4,wal_sync_mode=fsync",
+        )?;
+        assert_eq!(options.memtable_size, 8 << 20);
+        assert_eq!(options.wal_sync_mode, WalSyncMode::Fsync);
+        assert_eq!(options.compression, Compression::Zstd(9));
+        assert_eq!(options.stall_limits.l0_soft_files, 4);
+        assert_eq!(
+            options.stall_limits.l0_hard_files,
+            StallLimits::default().l0_hard_files
+        );
+        assert_eq!(Options::parse(&options.to_string())?, options);
+
+        assert!(Options::parse("memtable_size").is_err());
+        assert!(Options::parse("memtable_size=8X").is_err());
+        assert!(Options::parse("compact_threshold=1").is_err());
+        assert!(Options::parse("meta_compression_level=0").is_err());
+        assert!(Options::parse("background_workers=0").is_err());
+        assert!(Options::parse("wal_sync_mode=always").is_err());
+        assert_eq!(Options::parse("block_cache_size=0")?.block_cache_size, 0);
+        assert!(Options::parse("unknown=1").is_err());
+
+        // the memtable must be flushed before the writes are stopped
+        assert!(Options::parse("memtable_size=64M").is_err());
+        assert!(Options::parse("memtable_size=128M,memtable_hard_bytes=256M").is_ok());
+        assert!(Options::parse("l0_soft_files=32").is_err());
+        let mut conflicting = options;
+        assert!(conflicting.set("memtable_hard_bytes", "8M").is_err());
+        assert!(conflicting
+            .set("pending_compaction_hard_bytes", "1M")
+            .is_err());
+        assert_eq!(conflicting, options);
+
+        let mut overrides = TableOptions::default();
+        overrides.set(&options, "range_filter", "off")?;
+        overrides.set(&options, "l0_hard_files", "32")?;
+        assert!(overrides.set(&options, "max_filter_keys", "1").is_err());
+        assert!(overrides.set(&options, "wal_sync_mode", "off").is_err());
+        assert!(overrides.set(&options, "unknown", "1").is_err());
+        assert!(overrides
+            .set(&options, "memtable_soft_bytes", "1G")
+            .is_err());
+        assert!(overrides.stall_limits.unwrap().l0_hard_files == 32);
+
+        let merged = options.merge(&overrides);
+        assert!(!merged.range_filter);
+        assert_eq!(merged.memtable_size, 8 << 20);
+        assert_eq!(merged.stall_limits.l0_soft_files, 4);
+        assert_eq!(merged.stall_limits.l0_hard_files, 32);
+
+        // the global memtable size is raised over the hard limit of the table
+        let mut large = TableOptions::default();
+        large.set(&options, "memtable_soft_bytes", "12M")?;
+        large.set(&options, "memtable_hard_bytes", "16M")?;
+        let raised = Options::parse("memtable_size=32M")?.merge(&large);
+        assert_eq!(raised.memtable_size, 32 << 20);
+        assert_eq!(raised.stall_limits, StallLimits::default());
+
+        let bytes = bincode::encode_to_vec(&overrides, BIN_CODE_CONF)?;
+        let decoded: TableOptions = bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0;
+        assert_eq!(decoded, overrides);
+
+        overrides.set(&options, "range_filter", "default")?;
+        overrides.set(&options, "l0_hard_files", "default")?;
+        assert!(overrides.is_empty());
+        assert_eq!(options.merge(&overrides), options);
+
+        Ok(())
+    }
+}
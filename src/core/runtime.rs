@@ -1,13 +1,22 @@
 use futures::Future;
 use indicatif::HumanBytes;
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 
+use super::{Counter, EngineStatus, Options, Scheduler, Statistics};
 use crate::structs::{
+    lsm::BlockCache,
     table::{Table, TableId},
-    SizedOnDisk,
+    BulkInsert, SizedOnDisk,
 };
+use crate::utils::Result;
 
 lazy_static! {
     static ref RUNTIME: Runtime = {
@@ -18,7 +27,14 @@ lazy_static! {
 
         Runtime {
             tokio_rt: rt,
+            options: std::sync::RwLock::new(Options::default()),
             tables: RwLock::new(BTreeMap::new()),
+            corrupted: RwLock::new(HashSet::new()),
+            bulk_inserts: Mutex::new(HashMap::new()),
+            next_bulk_id: AtomicU64::new(1),
+            scheduler: Scheduler::default(),
+            statistics: Statistics::default(),
+            block_cache: BlockCache::default(),
         }
     };
 }
@@ -30,12 +46,25 @@ lazy_static! {
 /// by `RUNTIME` static variable.
 pub struct Runtime {
     tokio_rt: tokio::runtime::Runtime,
+    /// The global options, read without blocking on the runtime.
+    options: std::sync::RwLock<Options>,
     tables: RwLock<BTreeMap<TableId, Arc<Table>>>,
+    /// The tables failed the checksum verification, writes are refused.
+    corrupted: RwLock<HashSet<TableId>>,
+    /// The bulk inserts in progress, locked without blocking on the runtime.
+    bulk_inserts: Mutex<HashMap<u64, BulkInsert>>,
+    next_bulk_id: AtomicU64,
+    scheduler: Scheduler,
+    statistics: Statistics,
+    block_cache: BlockCache,
 }
 
-/// Init the runtime of YYDB.
-pub fn init() {
-    Runtime::global();
+/// Init the runtime of YYDB with the global options.
+pub fn init(options: Options) {
+    run_async! {
+        Runtime::global().set_options(options).await;
+    }
+
     info!("Runtime Initialized.");
 }
 
@@ -97,28 +126,133 @@ impl Runtime {
         &RUNTIME
     }
 
+    /// Get the scheduler of background jobs.
+    #[inline(always)]
+    pub fn scheduler(&'static self) -> &'static Scheduler {
+        &self.scheduler
+    }
+
+    /// Get the counters of all the tables.
+    #[inline(always)]
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Get the cache of the data blocks of all the tables.
+    #[inline(always)]
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.block_cache
+    }
+
+    /// Get the counters with the memtables and SSTables of the opened tables.
+    pub async fn status(&self) -> EngineStatus {
+        let mut status = EngineStatus {
+            counters: Counter::ALL.map(|counter| self.statistics.get(counter)),
+            ..Default::default()
+        };
+
+        for table in self.tables().await {
+            status.open_tables += 1;
+            status.memtable_bytes += table.stats().await.memtable_bytes;
+            for (level, count) in table.level_counts().await {
+                *status.sstables.entry(level).or_default() += count;
+            }
+        }
+
+        status
+    }
+
+    /// Get the global options.
+    #[inline]
+    pub fn options(&self) -> Options {
+        *self.options.read().unwrap()
+    }
+
+    /// Set the global options, and apply them to the opened tables
+    /// except the options overridden by a table.
+    pub async fn set_options(&'static self, options: Options) {
+        *self.options.write().unwrap() = options;
+        info!("Options             : {}", options);
+
+        self.scheduler.set_workers(options.background_workers);
+        self.block_cache.set_capacity(options.block_cache_size);
+        for table in self.tables().await {
+            table.apply_options().await;
+        }
+    }
+
+    /// Set a global option by name, see `Options::set`.
+    pub async fn set_option(&'static self, name: &str, value: &str) -> Result<()> {
+        let mut options = self.options();
+        options.set(name, value)?;
+        self.set_options(options).await;
+        Ok(())
+    }
+
     #[inline]
     pub async fn contains_table(&self, id: &TableId) -> bool {
         self.tables.read().await.contains_key(id)
     }
 
     /// Open a table by name
+    ///
+    /// Returns `None` if the table cannot be opened, or if its id
+    /// collides with another opened table.
     pub async fn open_table(&self, table_name: String) -> Option<TableId> {
         let id = TableId::new(&table_name);
 
-        if self.tables.read().await.contains_key(&id) {
-            Some(id)
-        } else if let Ok(table) = Table::open(table_name).await {
-            info!(
-                "Table opened        : {}",
-                HumanBytes(table.size_on_disk().await.unwrap()).to_string()
+        if let Some(table) = self.tables.read().await.get(&id) {
+            return Self::check_collision(table, &table_name).then_some(id);
+        }
+
+        let table = match Table::open(table_name.clone()).await {
+            Ok(table) => table,
+            Err(err) => {
+                error!("Open table failed   : {:?} {:?}", table_name, err);
+                return None;
+            }
+        };
+
+        info!(
+            "Table opened        : {}",
+            HumanBytes(table.size_on_disk().await.unwrap())
+        );
+
+        let mut tables = self.tables.write().await;
+
+        // the table may be opened by others while we are loading it
+        if let Some(opened) = tables.get(&id) {
+            return Self::check_collision(opened, &table_name).then_some(id);
+        }
+
+        tables.insert(id, Arc::new(table));
+        Some(id)
+    }
+
+    /// Get a table by name, only if the table with the same id is
+    /// exactly the one we want.
+    pub async fn get_table_by_name(&self, table_name: &str) -> Option<Arc<Table>> {
+        self.tables
+            .read()
+            .await
+            .get(&TableId::new(table_name))
+            .filter(|table| table.name() == table_name)
+            .cloned()
+    }
+
+    #[inline]
+    fn check_collision(table: &Table, table_name: &str) -> bool {
+        if table.name() != table_name {
+            error!(
+                "Table id collision  : {:?} and {:?} @{:x}",
+                table.name(),
+                table_name,
+                table.id()
             );
-            let id = table.id();
-            self.tables.write().await.insert(id, Arc::new(table));
-            Some(id)
-        } else {
-            None
+            return false;
         }
+
+        true
     }
 
     /// Get a table by id.
@@ -127,6 +261,12 @@ impl Runtime {
         self.tables.read().await.get(id).cloned()
     }
 
+    /// Get all opened tables.
+    #[inline]
+    pub async fn tables(&self) -> Vec<Arc<Table>> {
+        self.tables.read().await.values().cloned().collect()
+    }
+
     /// Close a table by id.
     #[inline(always)]
     pub async fn close_table(&self, id: &TableId) {
@@ -141,10 +281,58 @@ impl Runtime {
         self.tables.write().await.insert(id, table);
     }
 
+    /// Mark a table as corrupted, until it is dropped.
+    pub async fn mark_corrupted(&self, id: &TableId) {
+        if self.corrupted.write().await.insert(*id) {
+            error!("Table corrupted     : @{:016x}", id.0);
+        }
+    }
+
+    #[inline]
+    pub async fn is_corrupted(&self, id: &TableId) -> bool {
+        self.corrupted.read().await.contains(id)
+    }
+
+    #[inline]
+    pub async fn clear_corrupted(&self, id: &TableId) {
+        self.corrupted.write().await.remove(id);
+    }
+
+    /// Begin a bulk insert into the table, get the id of the session.
+    pub fn begin_bulk_insert(&self, table: Arc<Table>) -> u64 {
+        let id = self.next_bulk_id.fetch_add(1, Ordering::Relaxed);
+        debug!("Bulk insert begin   : #{} @{:x}", id, table.id());
+
+        let bulk = BulkInsert::new(table, id);
+        self.bulk_inserts.lock().unwrap().insert(id, bulk);
+        id
+    }
+
+    /// Run `f` with the bulk insert, `None` if the session doesn't exist.
+    #[inline]
+    pub fn with_bulk_insert<T>(&self, id: u64, f: impl FnOnce(&mut BulkInsert) -> T) -> Option<T> {
+        self.bulk_inserts.lock().unwrap().get_mut(&id).map(f)
+    }
+
+    /// Take the bulk insert out to await on it.
+    #[inline]
+    pub fn take_bulk_insert(&self, id: u64) -> Option<BulkInsert> {
+        self.bulk_inserts.lock().unwrap().remove(&id)
+    }
+
+    #[inline]
+    pub fn put_bulk_insert(&self, id: u64, bulk: BulkInsert) {
+        self.bulk_inserts.lock().unwrap().insert(id, bulk);
+    }
+
     /// Close all tables.
     #[inline(always)]
     pub async fn close_all_tables(&self) {
-        self.tables.write().await.clear();
+        let tables = std::mem::take(&mut *self.tables.write().await);
+
+        for table in tables.values() {
+            table.close().await.ok();
+        }
     }
 
     /// Close the runtime.
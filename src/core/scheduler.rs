@@ -0,0 +1,330 @@
+use futures::Future;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, Once,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+use super::Runtime;
+use crate::{structs::table::TableId, utils::*};
+
+/// The interval of checking all opened tables for background jobs.
+pub const SCHEDULE_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Kind of background jobs, ordered by priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum JobKind {
+    Flush,
+    Compaction,
+}
+
+impl JobKind {
+    const ALL: [JobKind; 2] = [JobKind::Flush, JobKind::Compaction];
+
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+pub type Job = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+#[derive(Default)]
+struct TableJobs {
+    queues: [VecDeque<Job>; 2],
+    running: [bool; 2],
+}
+
+impl TableJobs {
+    #[inline]
+    fn is_idle(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty()) && self.running.iter().all(|r| !r)
+    }
+}
+
+/// The scheduler of background jobs.
+///
+/// Jobs are queued per table and run by a bounded worker pool.
+/// For each table, there is at most one running job of each kind,
+/// and flush jobs are always picked before compaction jobs.
+pub struct Scheduler {
+    tables: Mutex<BTreeMap<TableId, TableJobs>>,
+    notify: Notify,
+    started: Once,
+    /// The number of workers to run jobs, the others are parked.
+    workers: AtomicUsize,
+    /// The number of workers spawned, never shrinks.
+    spawned: Mutex<usize>,
+    resized: Notify,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            tables: Mutex::new(BTreeMap::new()),
+            notify: Notify::new(),
+            started: Once::new(),
+            workers: AtomicUsize::new(0),
+            spawned: Mutex::new(0),
+            resized: Notify::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("tables", &self.tables.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl Scheduler {
+    /// Spawn the workers and the periodic trigger, only once.
+    fn ensure_started(&'static self) {
+        self.started.call_once(|| {
+            let workers = Runtime::global().options().background_workers;
+            self.spawn_workers(workers);
+            super::spawn(self.ticker());
+            debug!("Scheduler started   : {} workers", workers);
+        });
+    }
+
+    /// Set the number of workers, the extra ones are parked after
+    /// finishing their jobs. Applied when the scheduler is started.
+    pub fn set_workers(&'static self, workers: usize) {
+        if self.started.is_completed() && self.workers.load(Ordering::Relaxed) != workers {
+            self.spawn_workers(workers);
+            debug!("Scheduler resized   : {} workers", workers);
+        }
+    }
+
+    /// Get the number of workers to run jobs.
+    #[inline]
+    pub fn workers(&self) -> usize {
+        self.workers.load(Ordering::Relaxed)
+    }
+
+    fn spawn_workers(&'static self, workers: usize) {
+        let workers = workers.max(1);
+        self.workers.store(workers, Ordering::Relaxed);
+
+        let mut spawned = self.spawned.lock().unwrap();
+        for worker in *spawned..workers {
+            super::spawn(self.worker(worker));
+        }
+        *spawned = workers.max(*spawned);
+
+        self.resized.notify_waiters();
+    }
+
+    /// Submit a job for the table.
+    pub fn submit(&'static self, table: TableId, kind: JobKind, job: Job) {
+        self.ensure_started();
+
+        self.tables.lock().unwrap().entry(table).or_default().queues[kind.index()].push_back(job);
+
+        trace!("Job submitted       : {:?} @{:x}", kind, table);
+        self.notify.notify_one();
+    }
+
+    /// Submit a job for the table, unless there is already one
+    /// of the same kind waiting in the queue.
+    pub fn submit_unique(&'static self, table: TableId, kind: JobKind, job: impl FnOnce() -> Job) {
+        let queued = self
+            .tables
+            .lock()
+            .unwrap()
+            .get(&table)
+            .is_some_and(|jobs| !jobs.queues[kind.index()].is_empty());
+
+        if !queued {
+            self.submit(table, kind, job());
+        }
+    }
+
+    /// Get the number of queued and running jobs of the table.
+    pub fn pending_jobs(&self, table: TableId, kind: JobKind) -> usize {
+        self.tables.lock().unwrap().get(&table).map_or(0, |jobs| {
+            jobs.queues[kind.index()].len() + jobs.running[kind.index()] as usize
+        })
+    }
+
+    /// Drop the queued jobs of the given kind.
+    pub fn cancel(&self, table: TableId, kind: JobKind) {
+        if let Some(jobs) = self.tables.lock().unwrap().get_mut(&table) {
+            jobs.queues[kind.index()].clear();
+        }
+    }
+
+    /// Wait until all the jobs of the table are finished.
+    pub async fn wait_idle(&self, table: TableId) {
+        while self
+            .tables
+            .lock()
+            .unwrap()
+            .get(&table)
+            .is_some_and(|jobs| !jobs.is_idle())
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Wait until the jobs of the given kind of the table are finished.
+    pub async fn wait_jobs(&self, table: TableId, kind: JobKind) {
+        while self.pending_jobs(table, kind) > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Pick the job with the highest priority.
+    fn pop_job(&self) -> Option<(TableId, JobKind, Job)> {
+        let mut tables = self.tables.lock().unwrap();
+
+        for kind in JobKind::ALL {
+            for (id, jobs) in tables.iter_mut() {
+                if jobs.running[kind.index()] {
+                    continue;
+                }
+
+                if let Some(job) = jobs.queues[kind.index()].pop_front() {
+                    jobs.running[kind.index()] = true;
+                    return Some((*id, kind, job));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn finish_job(&self, table: TableId, kind: JobKind) {
+        let mut tables = self.tables.lock().unwrap();
+
+        if let Some(jobs) = tables.get_mut(&table) {
+            jobs.running[kind.index()] = false;
+
+            if jobs.is_idle() {
+                tables.remove(&table);
+            }
+        }
+
+        // other jobs of this table may be waiting for us
+        self.notify.notify_one();
+    }
+
+    async fn worker(&'static self, worker: usize) {
+        loop {
+            // registered before the check, to not miss a resize
+            let resized = self.resized.notified();
+            if worker >= self.workers() {
+                // pass on the job we may be woken for
+                self.notify.notify_one();
+                resized.await;
+                continue;
+            }
+
+            match self.pop_job() {
+                Some((table, kind, job)) => {
+                    trace!("Job started         : {:?} @{:x} #{}", kind, table, worker);
+
+                    match job.await {
+                        Err(DbError::MissChecksum) => {
+                            error!("Job failed          : {:?} @{:x} corrupted", kind, table);
+                            self.cancel(table, JobKind::Compaction);
+                            Runtime::global().mark_corrupted(&table).await;
+                        }
+                        Err(err) => {
+                            error!("Job failed          : {:?} @{:x} {:?}", kind, table, err);
+                        }
+                        Ok(()) => (),
+                    }
+
+                    self.finish_job(table, kind);
+                }
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    /// Check all opened tables periodically, so idle tables
+    /// can also be flushed and compacted.
+    async fn ticker(&'static self) {
+        let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let tables = Runtime::global().tables().await;
+            for table in tables {
+                table.schedule_background_jobs().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let scheduler = crate::core::Runtime::global().scheduler();
+        let table = TableId::new("helper/scheduler_test");
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicUsize::new(0));
+
+        crate::core::block_on(async move {
+            for i in 0..4 {
+                let (order, running) = (order.clone(), running.clone());
+                scheduler.submit(
+                    table,
+                    JobKind::Compaction,
+                    Box::pin(async move {
+                        // only one compaction for a table at the same time
+                        assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        order.lock().unwrap().push((JobKind::Compaction, i));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    }),
+                );
+            }
+
+            let flush_order = order.clone();
+            scheduler.submit(
+                table,
+                JobKind::Flush,
+                Box::pin(async move {
+                    flush_order.lock().unwrap().push((JobKind::Flush, 0));
+                    Ok(())
+                }),
+            );
+
+            scheduler.wait_idle(table).await;
+            assert_eq!(scheduler.pending_jobs(table, JobKind::Compaction), 0);
+
+            let order = order.lock().unwrap();
+            assert_eq!(order.len(), 5);
+
+            // the flush job should not wait for all compactions
+            assert_ne!(order[4].0, JobKind::Flush);
+
+            let compactions = order
+                .iter()
+                .filter(|(kind, _)| *kind == JobKind::Compaction)
+                .map(|(_, i)| *i)
+                .collect::<Vec<_>>();
+            assert_eq!(compactions, vec![0, 1, 2, 3]);
+        });
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::structs::lsm::SSTableLevel;
+
+/// The counters of the engine since it started.
+///
+/// The order is the one of the status variables in the handler,
+/// new counters are appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Counter {
+    Puts,
+    Deletes,
+    Gets,
+    /// The lookups of SSTables skipped by their bloom filters.
+    BloomNegatives,
+    Flushes,
+    BytesFlushed,
+    Compactions,
+    BytesCompacted,
+    CompactionMicros,
+    StallMicros,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 10] = [
+        Counter::Puts,
+        Counter::Deletes,
+        Counter::Gets,
+        Counter::BloomNegatives,
+        Counter::Flushes,
+        Counter::BytesFlushed,
+        Counter::Compactions,
+        Counter::BytesCompacted,
+        Counter::CompactionMicros,
+        Counter::StallMicros,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::Puts => "puts",
+            Counter::Deletes => "deletes",
+            Counter::Gets => "gets",
+            Counter::BloomNegatives => "bloom_negatives",
+            Counter::Flushes => "flushes",
+            Counter::BytesFlushed => "bytes_flushed",
+            Counter::Compactions => "compactions",
+            Counter::BytesCompacted => "bytes_compacted",
+            Counter::CompactionMicros => "compaction_micros",
+            Counter::StallMicros => "stall_micros",
+        }
+    }
+}
+
+/// The counters of all the tables, held by `Runtime`.
+#[derive(Debug, Default)]
+pub struct Statistics {
+    counters: [AtomicU64; Counter::ALL.len()],
+}
+
+impl Statistics {
+    #[inline]
+    pub fn add(&self, counter: Counter, value: u64) {
+        self.counters[counter as usize].fetch_add(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn incr(&self, counter: Counter) {
+        self.add(counter, 1);
+    }
+
+    #[inline]
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.counters[counter as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// The number of the levels in the status variables, the deeper
+/// ones are counted in the last.
+pub const STATUS_LEVELS: usize = 8;
+
+/// The number of the status variables, `YYDB_STATUS_VALUES` of the handler.
+pub const STATUS_VALUES: usize = Counter::ALL.len() + 2 + STATUS_LEVELS;
+
+/// A snapshot of the status of the engine, see `Runtime::status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineStatus {
+    pub counters: [u64; Counter::ALL.len()],
+    pub open_tables: usize,
+    pub memtable_bytes: usize,
+    /// The SSTables of the opened tables at every level.
+    pub sstables: BTreeMap<SSTableLevel, usize>,
+}
+
+impl EngineStatus {
+    #[inline]
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.counters[counter as usize]
+    }
+
+    /// Get the values of the status variables, the counters, `open_tables`,
+    /// `memtable_bytes`, and the SSTables of `STATUS_LEVELS` levels.
+    pub fn values(&self) -> Vec<u64> {
+        let mut levels = [0; STATUS_LEVELS];
+        for (level, count) in self.sstables.iter() {
+            levels[(*level as usize).min(STATUS_LEVELS - 1)] += *count as u64;
+        }
+
+        self.counters
+            .iter()
+            .copied()
+            .chain([self.open_tables as u64, self.memtable_bytes as u64])
+            .chain(levels)
+            .collect()
+    }
+}
+
+impl Display for EngineStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Open tables         : {}", self.open_tables)?;
+        writeln!(f, "Memtable bytes      : {}", self.memtable_bytes)?;
+        for counter in Counter::ALL {
+            writeln!(f, "{:<20}: {}", counter.name(), self.get(counter))?;
+        }
+
+        let sstables = self
+            .sstables
+            .iter()
+            .map(|(level, count)| format!("L{}={}", level, count))
+            .collect::<Vec<_>>();
+        writeln!(f, "SSTables per level  : {}", sstables.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let statistics = Statistics::default();
+        statistics.incr(Counter::Puts);
+        statistics.add(Counter::BytesFlushed, 0x100);
+        assert_eq!(statistics.get(Counter::Puts), 1);
+        assert_eq!(statistics.get(Counter::BytesFlushed), 0x100);
+
+        let status = EngineStatus {
+            counters: Counter::ALL.map(|counter| statistics.get(counter)),
+            open_tables: 1,
+            memtable_bytes: 0x20,
+            sstables: BTreeMap::from([(0, 2), (1, 1), (9, 1), (12, 2)]),
+        };
+
+        let values = status.values();
+        assert_eq!(values.len(), STATUS_VALUES);
+        assert_eq!(values[Counter::BytesFlushed as usize], 0x100);
+        assert_eq!(
+            values[Counter::ALL.len()..Counter::ALL.len() + 4],
+            [1, 0x20, 2, 1]
+        );
+        // the deeper levels are in the last one
+        assert_eq!(values.last(), Some(&3));
+
+        assert!(status
+            .to_string()
+            .contains("SSTables per level  : L0=2 L1=1 L9=1 L12=2"));
+    }
+
+    #[test]
+    fn handler_values_works() {
+        // the handler sizes its status variables by the header
+        let count = include_str!("../../include/yydb.h")
+            .lines()
+            .find_map(|line| line.strip_prefix("#define YYDB_STATUS_VALUES"))
+            .and_then(|count| count.trim().parse::<usize>().ok());
+        assert_eq!(count, Some(STATUS_VALUES));
+    }
+}
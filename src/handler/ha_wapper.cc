@@ -101,6 +101,10 @@
 #include "sql/sql_plugin.h"
 #include "typelib.h"
 #include "sql/field.h"
+#include "my_bitmap.h"
+
+#include <string>
+#include <vector>
 
 static SERVICE_TYPE(registry)* reg_srv = nullptr;
 SERVICE_TYPE(log_builtins)* log_bi = nullptr;
@@ -111,6 +115,15 @@ static handler* yydb_create_handler(handlerton* hton, TABLE_SHARE* table,
 
 handlerton* yydb_hton;
 
+/* The global options of the engine, `name=value` pairs read at startup */
+static char* srv_options = nullptr;
+
+static int yydb_init_sysvars();
+
+/* SHOW ENGINE YYDB STATUS */
+static bool yydb_show_status(handlerton* hton, THD* thd,
+    stat_print_fn* stat_print, enum ha_stat_type stat_type);
+
 /* Interface to mysqld, to check system tables supported by SE */
 static bool yydb_is_supported_system_table(const char* db,
     const char* table_name,
@@ -130,8 +143,9 @@ static int yydb_init_func(void* p) {
     yydb_hton->create = yydb_create_handler;
     yydb_hton->flags = HTON_CAN_RECREATE;
     yydb_hton->is_supported_system_table = yydb_is_supported_system_table;
+    yydb_hton->show_status = yydb_show_status;
 
-    if(yydb::ha_yydb_core_init()) return 1;
+    if(yydb_init_sysvars()) return 1;
 
     return 0;
 }
@@ -267,6 +281,10 @@ int ha_yydb::close(void) {
     DBUG_TRACE;
 
     //__mysql_log(SYSTEM_LEVEL, "[Inf] Mysql closing table        ");
+    if(this->bulk_id) {
+        yydb::ha_yydb_end_bulk_insert(this->bulk_id, true);
+        this->bulk_id = 0;
+    }
     if(!this->table_id) {
         yydb::ha_yydb_close_table(this->table_id);
     }
@@ -324,9 +342,52 @@ int ha_yydb::write_row(uchar* data) {
 
     std::uint64_t row_pk = get_row_pk();
 
-    yydb::ha_yydb_insert_row(this->table_id, row_pk, data, this->table->s->rec_buff_length);
+    if(this->bulk_id) {
+        int ret = yydb::ha_yydb_bulk_insert_row(this->bulk_id, row_pk, data, this->table->s->rec_buff_length);
+        if(ret) this->bulk_failed = true;
+        return ret;
+    }
 
-    return 0;
+    return yydb::ha_yydb_insert_row(this->table_id, row_pk, data, this->table->s->rec_buff_length);
+}
+
+/**
+  @brief
+  Called before inserting many rows by LOAD DATA, a multi-row INSERT or
+  INSERT ... SELECT. The rows are buffered by the bulk insert of the core,
+  and written to SSTables directly if there are many.
+
+  @details
+  rows is the number of rows to insert, 0 if unknown. A single row goes
+  through write_row() as usual.
+
+  @see
+  handler::ha_start_bulk_insert() in handler.cc
+*/
+void ha_yydb::start_bulk_insert(ha_rows rows) {
+    DBUG_TRACE;
+    if(rows == 1) return;
+
+    this->bulk_id = yydb::ha_yydb_begin_bulk_insert(this->table_id);
+    this->bulk_failed = false;
+}
+
+/**
+  @brief
+  Called after the rows of a bulk insert, all of them are visible at once.
+  The buffered rows are dropped if one of them failed.
+
+  @see
+  handler::ha_end_bulk_insert() in handler.cc
+*/
+int ha_yydb::end_bulk_insert() {
+    DBUG_TRACE;
+    if(!this->bulk_id) return 0;
+
+    int ret = yydb::ha_yydb_end_bulk_insert(this->bulk_id, this->bulk_failed);
+    this->bulk_id = 0;
+    this->bulk_failed = false;
+    return ret;
 }
 
 /**
@@ -360,9 +421,7 @@ int ha_yydb::update_row(const uchar* old_data, uchar* new_data) {
 
     std::uint64_t row_pk = get_row_pk();
 
-    yydb::ha_yydb_update_row(this->table_id, row_pk, old_data, new_data, this->table->s->rec_buff_length);
-
-    return 0;
+    return yydb::ha_yydb_update_row(this->table_id, row_pk, old_data, new_data, this->table->s->rec_buff_length);
 }
 
 /**
@@ -389,8 +448,7 @@ int ha_yydb::delete_row(const uchar*) {
     DBUG_TRACE;
     //__mysql_log(SYSTEM_LEVEL, "[Inf] Mysql deleting row        ");
     std::uint64_t row_pk = get_row_pk();
-    yydb::delete_row(this->table_id, row_pk);
-    return 0;
+    return yydb::delete_row(this->table_id, row_pk);
 }
 
 /**
@@ -484,6 +542,20 @@ int ha_yydb::rnd_init(bool) {
     DBUG_TRACE;
     //__mysql_log(SYSTEM_LEVEL, "[Inf] Mysql init to read        ");
     ref_length = sizeof(long long);
+
+    // only the fields in the read set are decoded from the columnar tables.
+    // an update writes back the whole row image, and the read set is not
+    // widened for statement based binlogs, so the scans to write are full.
+    if(bitmap_is_clear_all(table->write_set)) {
+        std::vector<uint32_t> fields;
+        for(Field** field = table->field; *field; field++) {
+            if(bitmap_is_set(table->read_set, (*field)->field_index())) {
+                fields.push_back((*field)->field_index());
+            }
+        }
+        yydb::ha_yydb_set_projection(this->table_id, fields.data(), fields.size());
+    }
+
     yydb::rnd_init(this->table_id);
     return 0;
 }
@@ -514,10 +586,13 @@ int ha_yydb::rnd_next(uchar* buf) {
     DBUG_TRACE;
     //__mysql_log(SYSTEM_LEVEL, "[Inf] Mysql reading next        ");
     ha_statistic_increment(&System_status_var::ha_read_rnd_next_count);
-    if(yydb::ha_yydb_rnd_next(this->table_id, buf, table->s->rec_buff_length)) {
+    int ret = yydb::ha_yydb_rnd_next(this->table_id, buf, table->s->rec_buff_length);
+    if(ret > 0) {
         return 0;
-    } else {
+    } else if(ret == 0) {
         return HA_ERR_END_OF_FILE;
+    } else {
+        return -ret;
     }
 }
 
@@ -724,6 +799,49 @@ THR_LOCK_DATA** ha_yydb::store_lock(THD*, THR_LOCK_DATA** to,
     return to;
 }
 
+/**
+  @brief
+  Used by CHECK TABLE. Verifies the checksums, the key ordering and the
+  counters of every SSTable against the manifest.
+
+  @details
+  A table failing the check is marked as corrupted, the writes are refused
+  until it is repaired.
+
+  @see
+  mysql_admin_table() in sql_admin.cc
+*/
+int ha_yydb::check(THD*, HA_CHECK_OPT*) {
+    DBUG_TRACE;
+    return yydb::ha_yydb_check_table(this->table_id);
+}
+
+/**
+  @brief
+  Used by REPAIR TABLE. Rebuilds the manifest from the SSTables passing the
+  check, the entries in the readable blocks of a damaged SSTable are kept.
+
+  @see
+  mysql_admin_table() in sql_admin.cc
+*/
+int ha_yydb::repair(THD*, HA_CHECK_OPT*) {
+    DBUG_TRACE;
+    return yydb::ha_yydb_repair_table(this->table_id);
+}
+
+/**
+  @brief
+  Used by OPTIMIZE TABLE. Flushes the memtable and merges all the SSTables
+  into the bottom level, the deleted and overwritten rows are dropped.
+
+  @see
+  mysql_admin_table() in sql_admin.cc
+*/
+int ha_yydb::optimize(THD*, HA_CHECK_OPT*) {
+    DBUG_TRACE;
+    return yydb::ha_yydb_compact_table(this->table_id);
+}
+
 /**
   @brief
   Used to delete a table. By the time delete_table() has been called all
@@ -790,12 +908,6 @@ ha_rows ha_yydb::records_in_range(uint, key_range*, key_range*) {
     return 10;  // low number to force index usage
 }
 
-static MYSQL_THDVAR_STR(last_create_thdvar, PLUGIN_VAR_MEMALLOC, nullptr,
-    nullptr, nullptr, nullptr);
-
-static MYSQL_THDVAR_UINT(create_count_thdvar, 0, nullptr, nullptr, nullptr, 0,
-    0, 1000, 0);
-
 /**
   @brief
   create() is called to create a database. The variable name will have the name
@@ -815,12 +927,41 @@ static MYSQL_THDVAR_UINT(create_count_thdvar, 0, nullptr, nullptr, nullptr, 0,
   ha_create_table() in handle.cc
 */
 
-int ha_yydb::create(const char* name, TABLE*, HA_CREATE_INFO*, dd::Table*) {
+/* If the comment of the table has the columnar layout, e.g. COMMENT='layout=columnar' */
+static bool is_columnar(const LEX_STRING& comment) {
+    std::string items(comment.str ? comment.str : "", comment.str ? comment.length : 0);
+    size_t start = 0;
+    while(start <= items.size()) {
+        size_t end = items.find(',', start);
+        if(end == std::string::npos) end = items.size();
+
+        std::string item = items.substr(start, end - start);
+        item.erase(0, item.find_first_not_of(' '));
+        item.erase(item.find_last_not_of(' ') + 1);
+        if(item == "layout=columnar") return true;
+
+        start = end + 1;
+    }
+    return false;
+}
+
+int ha_yydb::create(const char* name, TABLE* form, HA_CREATE_INFO* create_info, dd::Table*) {
     DBUG_TRACE;
 
     //__mysql_log(SYSTEM_LEVEL, "[Inf] Mysql creating table        ");
 
     this->table_id = yydb::ha_yydb_open_table(name);
+
+    // the rows are stored as whole images unless the columnar layout is asked,
+    // then the offsets of the fields in the row image split it into columns
+    if(is_columnar(create_info->comment)) {
+        std::vector<uint32_t> offsets;
+        for(Field** field = form->field; *field; field++) {
+            offsets.push_back((*field)->offset(form->record[0]));
+        }
+        yydb::ha_yydb_set_columns(this->table_id, form->s->rec_buff_length, offsets.data(), offsets.size());
+    }
+
     this->table_id = 1;
 
     if(this->table_id == 0) {
@@ -835,127 +976,268 @@ int ha_yydb::create(const char* name, TABLE*, HA_CREATE_INFO*, dd::Table*) {
 struct st_mysql_storage_engine yydb_storage_engine = {
     MYSQL_HANDLERTON_INTERFACE_VERSION };
 
-static ulong srv_enum_var = 0;
-static ulong srv_ulong_var = 0;
-static double srv_double_var = 0;
-static int srv_signed_int_var = 0;
-static long srv_signed_long_var = 0;
-static longlong srv_signed_longlong_var = 0;
+static MYSQL_SYSVAR_STR(options, srv_options,
+    PLUGIN_VAR_READONLY | PLUGIN_VAR_RQCMDARG,
+    "The global options of YYDB, e.g. memtable_size=8M,compression=lz4",
+    nullptr, nullptr, "");
+
+/* 0 if not set, the ones of yydb_options or the defaults are used */
+static ulonglong srv_memtable_size = 0;
+static uint srv_compaction_threads = 0;
+static ulonglong srv_block_cache_size = 0;
+static ulong srv_wal_sync_mode = 0;
+static ulong srv_log_level = 2;
+
+/* The memtable must be flushed before it reaches the memtable_hard_bytes limit */
+static int check_memtable_size(MYSQL_THD, SYS_VAR*, void* save, st_mysql_value* value) {
+    long long size;
+    if(value->val_int(value, &size) || size < (64 << 10)) return 1;
+    if(yydb::ha_yydb_check_option("memtable_size", std::to_string(size).c_str())) return 1;
+
+    *static_cast<ulonglong*>(save) = size;
+    return 0;
+}
+
+static void update_memtable_size(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    ulonglong size = *static_cast<const ulonglong*>(save);
+    if(!yydb::ha_yydb_set_option("memtable_size", std::to_string(size).c_str()))
+        *static_cast<ulonglong*>(var_ptr) = size;
+}
+
+static MYSQL_SYSVAR_ULONGLONG(memtable_size, srv_memtable_size,
+    PLUGIN_VAR_RQCMDARG,
+    "The size in bytes of a memtable before it is flushed, "
+    "below memtable_hard_bytes, unless overridden by a table. "
+    "0 to use the memtable_size of yydb_options, 4M by default",
+    check_memtable_size, update_memtable_size, 0, 0, ULLONG_MAX, 0);
+
+static int check_compaction_threads(MYSQL_THD, SYS_VAR*, void* save, st_mysql_value* value) {
+    long long threads;
+    if(value->val_int(value, &threads) || threads < 1 || threads > 64) return 1;
+
+    *static_cast<uint*>(save) = threads;
+    return 0;
+}
+
+static void update_compaction_threads(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    uint threads = *static_cast<const uint*>(save);
+    if(!yydb::ha_yydb_set_option("background_workers", std::to_string(threads).c_str()))
+        *static_cast<uint*>(var_ptr) = threads;
+}
+
+static MYSQL_SYSVAR_UINT(compaction_threads, srv_compaction_threads,
+    PLUGIN_VAR_RQCMDARG,
+    "The number of workers running the flushes and compactions. "
+    "0 to use the background_workers of yydb_options, 4 by default",
+    check_compaction_threads, update_compaction_threads, 0, 0, 64, 0);
+
+static int check_block_cache_size(MYSQL_THD, SYS_VAR*, void* save, st_mysql_value* value) {
+    long long size;
+    if(value->val_int(value, &size) || size < 0) return 1;
+    if(yydb::ha_yydb_check_option("block_cache_size", std::to_string(size).c_str())) return 1;
+
+    *static_cast<ulonglong*>(save) = size;
+    return 0;
+}
+
+static void update_block_cache_size(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    ulonglong size = *static_cast<const ulonglong*>(save);
+    if(!yydb::ha_yydb_set_option("block_cache_size", std::to_string(size).c_str()))
+        *static_cast<ulonglong*>(var_ptr) = size;
+}
+
+static MYSQL_SYSVAR_ULONGLONG(block_cache_size, srv_block_cache_size,
+    PLUGIN_VAR_RQCMDARG,
+    "The size in bytes of the cache of the data blocks of all the tables, "
+    "0 to disable it. 0 at startup to use the block_cache_size of "
+    "yydb_options, 8M by default",
+    check_block_cache_size, update_block_cache_size, 0, 0, ULLONG_MAX, 0);
 
-const char* enum_var_names[] = { "e1", "e2", NullS };
+const char* wal_sync_mode_names[] = { "off", "write", "fsync", NullS };
 
-TYPELIB enum_var_typelib = { array_elements(enum_var_names) - 1,
-                            "enum_var_typelib", enum_var_names, nullptr };
+TYPELIB wal_sync_mode_typelib = { array_elements(wal_sync_mode_names) - 1,
+                                "wal_sync_mode_typelib", wal_sync_mode_names, nullptr };
+
+static void update_wal_sync_mode(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    ulong mode = *static_cast<const ulong*>(save);
+    if(!yydb::ha_yydb_set_option("wal_sync_mode", wal_sync_mode_names[mode]))
+        *static_cast<ulong*>(var_ptr) = mode;
+}
 
-static MYSQL_SYSVAR_ENUM(enum_var,                        // name
-    srv_enum_var,                    // varname
-    PLUGIN_VAR_RQCMDARG,             // opt
-    "Sample ENUM system variable.",  // comment
-    nullptr,                         // check
-    nullptr,                         // update
-    0,                               // def
-    &enum_var_typelib);              // typelib
+static MYSQL_SYSVAR_ENUM(wal_sync_mode, srv_wal_sync_mode,
+    PLUGIN_VAR_RQCMDARG,
+    "How the writes to the memtables are logged: off, write to the file, "
+    "or fsync on every write. off at startup to use the wal_sync_mode of "
+    "yydb_options, off by default",
+    nullptr, update_wal_sync_mode, 0, &wal_sync_mode_typelib);
 
-static MYSQL_SYSVAR_ULONG(ulong_var, srv_ulong_var, PLUGIN_VAR_RQCMDARG,
-    "0..1000", nullptr, nullptr, 8, 0, 1000, 0);
+const char* log_level_names[] = { "error", "warn", "info", "debug", "trace", NullS };
 
-static MYSQL_SYSVAR_DOUBLE(double_var, srv_double_var, PLUGIN_VAR_RQCMDARG,
-    "0.500000..1000.500000", nullptr, nullptr, 8.5, 0.5,
-    1000.5,
-    0);  // reserved always 0
+TYPELIB log_level_typelib = { array_elements(log_level_names) - 1,
+                            "log_level_typelib", log_level_names, nullptr };
 
-static MYSQL_THDVAR_DOUBLE(double_thdvar, PLUGIN_VAR_RQCMDARG,
-    "0.500000..1000.500000", nullptr, nullptr, 8.5, 0.5,
-    1000.5, 0);
+static void update_log_level(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    ulong level = *static_cast<const ulong*>(save);
+    if(!yydb::ha_yydb_set_log_level(log_level_names[level]))
+        *static_cast<ulong*>(var_ptr) = level;
+}
 
-static MYSQL_SYSVAR_INT(signed_int_var, srv_signed_int_var, PLUGIN_VAR_RQCMDARG,
-    "INT_MIN..INT_MAX", nullptr, nullptr, -10, INT_MIN,
-    INT_MAX, 0);
+static MYSQL_SYSVAR_ENUM(log_level, srv_log_level,
+    PLUGIN_VAR_RQCMDARG,
+    "The default level of the logs of YYDB",
+    nullptr, update_log_level, 2, &log_level_typelib);
 
-static MYSQL_THDVAR_INT(signed_int_thdvar, PLUGIN_VAR_RQCMDARG,
-    "INT_MIN..INT_MAX", nullptr, nullptr, -10, INT_MIN,
-    INT_MAX, 0);
+static char* srv_log_filters = nullptr;
+static char* srv_log_file = nullptr;
+static ulonglong srv_log_file_size = 64 << 20;
+static uint srv_log_files = 4;
 
-static MYSQL_SYSVAR_LONG(signed_long_var, srv_signed_long_var,
-    PLUGIN_VAR_RQCMDARG, "LONG_MIN..LONG_MAX", nullptr,
-    nullptr, -10, LONG_MIN, LONG_MAX, 0);
+static void update_log_filters(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    const char* filters = *static_cast<const char* const*>(save);
+    if(!yydb::ha_yydb_set_log_filters(filters))
+        *static_cast<const char**>(var_ptr) = filters;
+}
 
-static MYSQL_THDVAR_LONG(signed_long_thdvar, PLUGIN_VAR_RQCMDARG,
-    "LONG_MIN..LONG_MAX", nullptr, nullptr, -10, LONG_MIN,
-    LONG_MAX, 0);
+static MYSQL_SYSVAR_STR(log_filters, srv_log_filters,
+    PLUGIN_VAR_RQCMDARG | PLUGIN_VAR_MEMALLOC,
+    "The levels of the modules, e.g. structs::lsm=trace,core=debug",
+    nullptr, update_log_filters, "");
 
-static MYSQL_SYSVAR_LONGLONG(signed_longlong_var, srv_signed_longlong_var,
-    PLUGIN_VAR_RQCMDARG, "LLONG_MIN..LLONG_MAX",
-    nullptr, nullptr, -10, LLONG_MIN, LLONG_MAX, 0);
+static void update_log_file(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    const char* path = *static_cast<const char* const*>(save);
+    if(!yydb::ha_yydb_set_log_file(path, srv_log_file_size, srv_log_files))
+        *static_cast<const char**>(var_ptr) = path;
+}
 
-static MYSQL_THDVAR_LONGLONG(signed_longlong_thdvar, PLUGIN_VAR_RQCMDARG,
-    "LLONG_MIN..LLONG_MAX", nullptr, nullptr, -10,
-    LLONG_MIN, LLONG_MAX, 0);
+static MYSQL_SYSVAR_STR(log_file, srv_log_file,
+    PLUGIN_VAR_RQCMDARG | PLUGIN_VAR_MEMALLOC,
+    "Write the logs to a rotating file instead of the error log if not empty",
+    nullptr, update_log_file, "");
+
+static void update_log_file_size(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    ulonglong size = *static_cast<const ulonglong*>(save);
+    if(!yydb::ha_yydb_set_log_file(srv_log_file, size, srv_log_files))
+        *static_cast<ulonglong*>(var_ptr) = size;
+}
+
+static MYSQL_SYSVAR_ULONGLONG(log_file_size, srv_log_file_size,
+    PLUGIN_VAR_RQCMDARG,
+    "The size in bytes of the log file before it is rotated",
+    nullptr, update_log_file_size, 64 << 20, 1 << 20, 1ULL << 40, 0);
+
+static void update_log_files(MYSQL_THD, SYS_VAR*, void* var_ptr, const void* save) {
+    uint files = *static_cast<const uint*>(save);
+    if(!yydb::ha_yydb_set_log_file(srv_log_file, srv_log_file_size, files))
+        *static_cast<uint*>(var_ptr) = files;
+}
+
+static MYSQL_SYSVAR_UINT(log_files, srv_log_files,
+    PLUGIN_VAR_RQCMDARG,
+    "The number of rotated log files kept",
+    nullptr, update_log_files, 4, 0, 100, 0);
+
+/* Apply the variables set at startup, they take precedence over yydb_options */
+static int yydb_init_sysvars() {
+    std::string options = srv_options ? srv_options : "";
+    if(srv_memtable_size)
+        options += ",memtable_size=" + std::to_string(srv_memtable_size);
+    if(srv_compaction_threads)
+        options += ",background_workers=" + std::to_string(srv_compaction_threads);
+    if(srv_block_cache_size)
+        options += ",block_cache_size=" + std::to_string(srv_block_cache_size);
+    if(srv_wal_sync_mode)
+        options += std::string(",wal_sync_mode=") + wal_sync_mode_names[srv_wal_sync_mode];
+
+    if(yydb::ha_yydb_core_init(options.c_str())) return 1;
+
+    if(yydb::ha_yydb_set_log_file(srv_log_file, srv_log_file_size, srv_log_files)) return 1;
+    if(yydb::ha_yydb_set_log_level(log_level_names[srv_log_level])) return 1;
+    return yydb::ha_yydb_set_log_filters(srv_log_filters);
+}
 
 static SYS_VAR* yydb_system_variables[] = {
-    MYSQL_SYSVAR(enum_var),
-    MYSQL_SYSVAR(ulong_var),
-    MYSQL_SYSVAR(double_var),
-    MYSQL_SYSVAR(double_thdvar),
-    MYSQL_SYSVAR(last_create_thdvar),
-    MYSQL_SYSVAR(create_count_thdvar),
-    MYSQL_SYSVAR(signed_int_var),
-    MYSQL_SYSVAR(signed_int_thdvar),
-    MYSQL_SYSVAR(signed_long_var),
-    MYSQL_SYSVAR(signed_long_thdvar),
-    MYSQL_SYSVAR(signed_longlong_var),
-    MYSQL_SYSVAR(signed_longlong_thdvar),
+    MYSQL_SYSVAR(options),
+    MYSQL_SYSVAR(memtable_size),
+    MYSQL_SYSVAR(compaction_threads),
+    MYSQL_SYSVAR(block_cache_size),
+    MYSQL_SYSVAR(wal_sync_mode),
+    MYSQL_SYSVAR(log_level),
+    MYSQL_SYSVAR(log_filters),
+    MYSQL_SYSVAR(log_file),
+    MYSQL_SYSVAR(log_file_size),
+    MYSQL_SYSVAR(log_files),
     nullptr };
 
-// this is an yydb of SHOW_FUNC
-static int show_func_yydb(MYSQL_THD, SHOW_VAR* var, char* buf) {
-    var->type = SHOW_CHAR;
-    var->value = buf;  // it's of SHOW_VAR_FUNC_BUFF_SIZE bytes
-    snprintf(buf, SHOW_VAR_FUNC_BUFF_SIZE,
-        "enum_var is %lu, ulong_var is %lu, "
-        "double_var is %f, signed_int_var is %d, "
-        "signed_long_var is %ld, signed_longlong_var is %lld",
-        srv_enum_var, srv_ulong_var, srv_double_var, srv_signed_int_var,
-        srv_signed_long_var, srv_signed_longlong_var);
-    return 0;
-}
+/*
+  The names of the status variables, in the order of
+  `EngineStatus::values` of the Rust core.
+*/
+static const char* yydb_status_names[] = {
+    "puts",
+    "deletes",
+    "gets",
+    "bloom_negatives",
+    "flushes",
+    "bytes_flushed",
+    "compactions",
+    "bytes_compacted",
+    "compaction_micros",
+    "stall_micros",
+    "open_tables",
+    "memtable_bytes",
+    "sstables_l0",
+    "sstables_l1",
+    "sstables_l2",
+    "sstables_l3",
+    "sstables_l4",
+    "sstables_l5",
+    "sstables_l6",
+    "sstables_l7",  // and the deeper levels
+};
+static_assert(array_elements(yydb_status_names) == YYDB_STATUS_VALUES,
+    "a name for every status value");
 
-struct yydb_vars_t {
-    ulong var1;
-    double var2;
-    char var3[64];
-    bool var4;
-    bool var5;
-    ulong var6;
+/* The status variables and their values, in the buffer of a call */
+struct yydb_status_buffer {
+    SHOW_VAR vars[YYDB_STATUS_VALUES + 1];
+    ulonglong values[YYDB_STATUS_VALUES];
 };
+static_assert(sizeof(yydb_status_buffer) <= SHOW_VAR_FUNC_BUFF_SIZE,
+    "the status variables fit in the buffer of SHOW_FUNC");
 
-yydb_vars_t yydb_vars = { 100, 20.01, "three hundred", true, false, 8250 };
+/*
+  Fetch the values from the Rust core every time the variables are shown,
+  into the buffer passed by the caller, so the concurrent SHOW STATUS
+  don't share them.
+*/
+static int show_yydb_vars(MYSQL_THD, SHOW_VAR* var, char* buf) {
+    yydb_status_buffer* status = reinterpret_cast<yydb_status_buffer*>(buf);
+    uint count = yydb::ha_yydb_status_values((uint64_t*)status->values, YYDB_STATUS_VALUES);
 
-static SHOW_VAR show_status_yydb[] = {
-    {"var1", (char*)&yydb_vars.var1, SHOW_LONG, SHOW_SCOPE_GLOBAL},
-    {"var2", (char*)&yydb_vars.var2, SHOW_DOUBLE, SHOW_SCOPE_GLOBAL},
-    {nullptr, nullptr, SHOW_UNDEF,
-     SHOW_SCOPE_UNDEF}  // null terminator required
-};
+    for(uint i = 0; i < count; i++) {
+        status->vars[i] = {yydb_status_names[i], (char*)&status->values[i],
+            SHOW_LONGLONG, SHOW_SCOPE_GLOBAL};
+    }
+    status->vars[count] = {nullptr, nullptr, SHOW_UNDEF, SHOW_SCOPE_UNDEF};
 
-static SHOW_VAR show_array_yydb[] = {
-    {"array", (char*)show_status_yydb, SHOW_ARRAY, SHOW_SCOPE_GLOBAL},
-    {"var3", (char*)&yydb_vars.var3, SHOW_CHAR, SHOW_SCOPE_GLOBAL},
-    {"var4", (char*)&yydb_vars.var4, SHOW_BOOL, SHOW_SCOPE_GLOBAL},
-    {nullptr, nullptr, SHOW_UNDEF, SHOW_SCOPE_UNDEF} };
+    var->type = SHOW_ARRAY;
+    var->value = (char*)status->vars;
+    var->scope = SHOW_SCOPE_GLOBAL;
+    return 0;
+}
+
+static bool yydb_show_status(handlerton*, THD* thd,
+    stat_print_fn* stat_print, enum ha_stat_type stat_type) {
+    if(stat_type != HA_ENGINE_STATUS) return false;
+
+    std::string status = yydb::ha_yydb_engine_status();
+    return stat_print(thd, "YYDB", 4, "", 0, status.c_str(), status.size());
+}
 
 static SHOW_VAR func_status[] = {
-    {"yydb_func_yydb", (char*)show_func_yydb, SHOW_FUNC,
-     SHOW_SCOPE_GLOBAL},
-    {"yydb_status_var5", (char*)&yydb_vars.var5, SHOW_BOOL,
-     SHOW_SCOPE_GLOBAL},
-    {"yydb_status_var6", (char*)&yydb_vars.var6, SHOW_LONG,
-     SHOW_SCOPE_GLOBAL},
-    {"yydb_status", (char*)show_array_yydb, SHOW_ARRAY,
-     SHOW_SCOPE_GLOBAL},
+    {"yydb", (char*)show_yydb_vars, SHOW_FUNC, SHOW_SCOPE_GLOBAL},
     {nullptr, nullptr, SHOW_UNDEF, SHOW_SCOPE_UNDEF} };
 
-
 mysql_declare_plugin(yydb) {
     MYSQL_STORAGE_ENGINE_PLUGIN,
         & yydb_storage_engine,
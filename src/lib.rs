@@ -8,7 +8,7 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
-mod utils;
+pub mod utils;
 
 #[cfg(feature = "mysql")]
 mod bridge;
@@ -21,12 +21,24 @@ pub mod structs;
 
 /// Init the rust part of the library, should be called
 /// from C++ code by Mysql plugin init function.
+///
+/// The global options are parsed from `name=value` pairs, see `Options`,
+/// returns false if they are invalid.
 #[inline]
-pub fn rust_init() {
+pub fn rust_init(options: &str) -> bool {
     utils::logger::init();
-    core::runtime::init();
+
+    let options = match core::Options::parse(options) {
+        Ok(options) => options,
+        Err(err) => {
+            error!("Invalid options     : {:?} {:?}", options, err);
+            return false;
+        }
+    };
+    core::runtime::init(options);
 
     info!("YYDB Version: {} Initialized.", env!("CARGO_PKG_VERSION"));
+    true
 }
 
 #[inline]
@@ -0,0 +1,236 @@
+use std::{cmp::Reverse, path::PathBuf, sync::Arc};
+
+use super::{
+    lsm::{SSTableIter, SSTableWriter},
+    table::Table,
+    AsyncIterator, AsyncKvStoreWrite,
+};
+use crate::core::Runtime;
+use crate::utils::*;
+
+/// A bulk insert of `LOAD DATA` or a multi-row `INSERT`.
+///
+/// The rows are buffered without touching the table. Every full batch is
+/// sorted and written to a SSTable in a directory of the session, and the
+/// batches are merged and ingested by `finish` at once. A few rows go
+/// through the memtable instead.
+#[derive(Debug)]
+pub struct BulkInsert {
+    table: Arc<Table>,
+    dir: PathBuf,
+    rows: Vec<(Key, DataInner)>,
+    bytes: usize,
+    batch_size: usize,
+    count: usize,
+    /// The sorted batches, the later ones overwrite the earlier ones.
+    runs: Vec<PathBuf>,
+}
+
+impl BulkInsert {
+    pub fn new(table: Arc<Table>, id: u64) -> Self {
+        let dir = PathBuf::from(table.name()).join(format!(".bulk-{}", id));
+        // left by a crash
+        std::fs::remove_dir_all(&dir).ok();
+
+        Self {
+            table,
+            dir,
+            rows: Vec::new(),
+            bytes: 0,
+            batch_size: Runtime::global().options().bulk_insert_batch_size,
+            count: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Buffer a row, return true if the batch is full and must be spilled.
+    pub fn insert(&mut self, key: Key, row: DataInner) -> bool {
+        self.bytes += std::mem::size_of::<Key>() + row.len();
+        self.count += 1;
+        self.rows.push((key, row));
+
+        self.bytes >= self.batch_size
+    }
+
+    /// Sort the buffered rows and write them to a SSTable.
+    pub async fn spill(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut rows = std::mem::take(&mut self.rows);
+        self.bytes = 0;
+        // the sort is stable, the last row of a key wins
+        rows.sort_by_key(|(key, _)| *key);
+
+        let options = self.table.sstable_options().await;
+        let mut writer = SSTableWriter::new(&self.dir, options)?.with_file_size(usize::MAX);
+        let mut rows = rows.into_iter().peekable();
+        while let Some((key, row)) = rows.next() {
+            if rows.peek().is_some_and(|(next, _)| *next == key) {
+                continue;
+            }
+            writer.put(key, row).await?;
+        }
+        self.runs.extend(writer.finish().await?);
+
+        debug!(
+            "Bulk insert spilled : run {} to {:?} @{:x}",
+            self.runs.len(),
+            self.dir,
+            self.table.id()
+        );
+
+        Ok(())
+    }
+
+    /// Write all the rows to the table, get the count of the rows.
+    pub async fn finish(mut self) -> Result<usize> {
+        let ret = self.write().await;
+        std::fs::remove_dir_all(&self.dir).ok();
+        ret?;
+
+        info!(
+            "Bulk insert done    : {} rows in {} runs @{:x}",
+            self.count,
+            self.runs.len(),
+            self.table.id()
+        );
+
+        Ok(self.count)
+    }
+
+    /// Drop the buffered rows and the spilled batches.
+    pub fn abort(self) {
+        warn!(
+            "Bulk insert aborted : {} rows @{:x}",
+            self.count,
+            self.table.id()
+        );
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+
+    async fn write(&mut self) -> Result<()> {
+        if self.runs.is_empty() && self.bytes < self.table.options().await.memtable_size {
+            for (key, row) in self.rows.drain(..) {
+                self.table.set(key, row).await;
+            }
+            return Ok(());
+        }
+
+        self.spill().await?;
+        let files = match self.runs.len() {
+            1 => self.runs.clone(),
+            _ => self.merge_runs().await?,
+        };
+        self.table.ingest(&files).await?;
+
+        Ok(())
+    }
+
+    /// Merge the batches into the SSTables without overlapping keys.
+    async fn merge_runs(&self) -> Result<Vec<PathBuf>> {
+        let mut iters = Vec::with_capacity(self.runs.len());
+        let mut heads = Vec::with_capacity(self.runs.len());
+        for run in self.runs.iter() {
+            let mut iter = SSTableIter::new(IOHandler::new(run).await?).await?;
+            iter.init_iter().await?;
+            heads.push(iter.next().await?);
+            iters.push(iter);
+        }
+
+        let options = self.table.sstable_options().await;
+        let mut writer = SSTableWriter::new(&self.dir, options)?;
+
+        loop {
+            // the smallest key, from the latest batch if duplicated
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(i, head)| head.as_ref().map(|(key, _)| (*key, Reverse(i))))
+                .min();
+            let Some((key, Reverse(latest))) = next else {
+                break;
+            };
+
+            let (_, value) = heads[latest].take().unwrap();
+            for (i, (head, iter)) in heads.iter_mut().zip(iters.iter_mut()).enumerate() {
+                if i == latest || head.as_ref().is_some_and(|(other, _)| *other == key) {
+                    *head = iter.next().await?;
+                }
+            }
+
+            writer.add((key, value)).await?;
+        }
+
+        writer.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::AsyncKvStoreRead;
+
+    #[test]
+    fn it_works() {
+        crate::core::runtime::block_on(async {
+            it_works_async().await.unwrap();
+        });
+    }
+
+    async fn it_works_async() -> Result<()> {
+        let test_dir = "helper/bulk_insert_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let table = Arc::new(Table::open(test_dir.to_string()).await?);
+        for i in 0..0x100u64 {
+            table.set(i, vec![0; 0x20]).await;
+        }
+        table.flush().await?;
+
+        // three overlapping batches, the later rows win
+        let mut bulk = BulkInsert::new(table.clone(), 1);
+        for i in (0x80..0x180u64).rev() {
+            bulk.insert(i, vec![1; 0x20]);
+        }
+        bulk.spill().await?;
+        for i in 0x100..0x200u64 {
+            bulk.insert(i, vec![2; 0x20]);
+        }
+        bulk.insert(0x1ff, vec![3; 0x20]);
+        bulk.spill().await?;
+        bulk.insert(0x10, vec![4; 0x20]);
+
+        let dir = bulk.dir.clone();
+        assert_eq!(bulk.finish().await?, 0x202);
+        assert!(!dir.exists());
+        assert!(table.check().await?.is_ok());
+
+        for (key, value) in [(0x10, 4), (0x7f, 0), (0x80, 1), (0x100, 2), (0x1ff, 3)] {
+            assert_eq!(
+                table.get(key).await?,
+                DataStore::Value(Arc::new(vec![value; 0x20]))
+            );
+        }
+
+        // a few rows go through the memtable
+        let mut bulk = BulkInsert::new(table.clone(), 2);
+        bulk.insert(0x300, vec![5; 0x20]);
+        bulk.finish().await?;
+        assert!(table.stats().await.memtable_bytes > 0);
+
+        let mut bulk = BulkInsert::new(table.clone(), 3);
+        bulk.insert(0x10, vec![6; 0x20]);
+        bulk.spill().await?;
+        let dir = bulk.dir.clone();
+        bulk.abort();
+        assert!(!dir.exists());
+        assert_eq!(
+            table.get(0x10).await?,
+            DataStore::Value(Arc::new(vec![4; 0x20]))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use bincode::{Decode, Encode};
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{lsm::SSTableKey, REMOVED_TABLES_LOG_SIZE};
+use crate::utils::*;
+
+/// The files of a backup, see `Table::backup_since`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupDelta {
+    /// The generation of the previous backup, `None` for a full checkpoint.
+    pub base: Option<u64>,
+    /// The generation of the backup, the base of the next one.
+    pub generation: u64,
+    /// The SSTable files written to the backup.
+    pub added: Vec<String>,
+    /// The SSTable files of the previous backup no longer in the table.
+    pub removed: Vec<String>,
+}
+
+/// The removal of a table, kept for the incremental backups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct RemovedTable {
+    pub key: SSTableKey,
+    /// The generation when the table was added.
+    pub added: u64,
+    /// The generation when the table was removed.
+    pub removed: u64,
+}
+
+/// The generation of the tables in a manifest, bumped on every change.
+///
+/// The generation when every table was added and a log of the recent
+/// removals are kept, so the changes since the generation of a previous
+/// backup can be listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Generations {
+    current: u64,
+    added: BTreeMap<SSTableKey, u64>,
+    /// The recent removals, the oldest first.
+    removed: VecDeque<RemovedTable>,
+    /// The log misses the removals before this generation.
+    complete_since: u64,
+}
+
+impl Generations {
+    /// The generations of the tables loaded from an older manifest, the
+    /// changes before can't be listed.
+    pub fn legacy(keys: impl IntoIterator<Item = SSTableKey>) -> Self {
+        Self {
+            current: 1,
+            added: keys.into_iter().map(|key| (key, 1)).collect(),
+            removed: VecDeque::new(),
+            complete_since: 1,
+        }
+    }
+
+    #[inline]
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Get the generation when the table was added.
+    #[inline]
+    pub fn of(&self, key: &SSTableKey) -> u64 {
+        self.added.get(key).copied().unwrap_or_default()
+    }
+
+    pub fn add(&mut self, key: SSTableKey) {
+        self.current += 1;
+        self.added.insert(key, self.current);
+    }
+
+    pub fn remove(&mut self, keys: impl IntoIterator<Item = SSTableKey>) {
+        self.current += 1;
+
+        for key in keys {
+            let added = self.added.remove(&key).unwrap_or_default();
+            self.removed.push_back(RemovedTable {
+                key,
+                added,
+                removed: self.current,
+            });
+        }
+
+        while self.removed.len() > REMOVED_TABLES_LOG_SIZE {
+            let dropped = self.removed.pop_front().unwrap();
+            self.complete_since = dropped.removed;
+        }
+    }
+
+    /// Get the tables added and removed since the generation.
+    pub fn changes_since(&self, base: u64) -> Result<(Vec<SSTableKey>, Vec<SSTableKey>)> {
+        if base < self.complete_since || base > self.current {
+            return Err(DbError::Other(format!(
+                "Generation {} out of [{},{}], a full checkpoint is needed",
+                base, self.complete_since, self.current
+            )));
+        }
+
+        let added = self
+            .added
+            .iter()
+            .filter(|(_, generation)| **generation > base)
+            .map(|(key, _)| *key)
+            .collect();
+
+        // the tables added after the base are never in the backup
+        let removed = self
+            .removed
+            .iter()
+            .filter(|table| table.added <= base && base < table.removed)
+            .map(|table| table.key)
+            .collect();
+
+        Ok((added, removed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() -> Result<()> {
+        let keys = (0..4u64)
+            .map(|i| SSTableKey((1 << 60) + i))
+            .collect::<Vec<_>>();
+
+        let mut generations = Generations::legacy([keys[0]]);
+        assert!(generations.changes_since(0).is_err());
+
+        generations.add(keys[1]);
+        let base = generations.current();
+
+        generations.add(keys[2]);
+        generations.remove([keys[0], keys[2]]);
+        generations.add(keys[3]);
+
+        let (added, removed) = generations.changes_since(base)?;
+        assert_eq!(added, vec![keys[3]]);
+        assert_eq!(removed, vec![keys[0]]);
+
+        let (added, removed) = generations.changes_since(generations.current())?;
+        assert!(added.is_empty() && removed.is_empty());
+
+        let bytes = bincode::encode_to_vec(&generations, BIN_CODE_CONF)?;
+        let decoded: Generations = bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0;
+        assert_eq!(decoded, generations);
+
+        // the base is older than the log
+        for _ in 0..REMOVED_TABLES_LOG_SIZE {
+            generations.add(keys[1]);
+            generations.remove([keys[1]]);
+        }
+        assert!(generations.changes_since(base).is_err());
+
+        Ok(())
+    }
+}
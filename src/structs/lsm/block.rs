@@ -0,0 +1,421 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use bincode::{Decode, Encode};
+use crc32fast::Hasher;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{structs::SSTABLE_FOOTER_MAGIC_NUMBER, utils::*};
+
+/// The size of raw data in a block before it is compressed.
+pub const DATA_BLOCK_SIZE: usize = 16 << 10;
+
+/// The number of entries between the restart points in a block.
+pub const BLOCK_RESTART_INTERVAL: u32 = 16;
+
+/// `dict_offset`, `dict_len`, `filter_offset`, `filter_len`,
+/// `index_offset`, `index_len`, `magic_number`
+pub const FOOTER_SIZE: u64 = 40;
+
+/// The location of a data block in the SSTable file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct BlockHandle {
+    pub first_key: Key,
+    pub last_key: Key,
+    pub offset: u64,
+    pub len: u32,
+    pub entries: u32,
+    /// The checksum of the block on disk.
+    pub checksum: u32,
+}
+
+impl BlockHandle {
+    /// Verify the block read from disk by the checksum.
+    pub fn verify(&self, bytes: &[u8]) -> Result<()> {
+        if crc32fast::hash(bytes) != self.checksum {
+            return Err(DbError::MissChecksum);
+        }
+        Ok(())
+    }
+}
+
+/// Decode the compressed block index in the footer.
+pub async fn decode_index(bytes: &[u8]) -> Result<Vec<BlockHandle>> {
+    let bytes = decompress(bytes).await?;
+    Ok(bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0)
+}
+
+/// Encode the entries of a data block.
+///
+/// The key of an entry is the delta from the previous one, except the
+/// restart points every `BLOCK_RESTART_INTERVAL` entries which have the
+/// full key. The offsets of the restart points and their count are
+/// appended at the end, to search the block by the restart points.
+#[derive(Debug)]
+pub struct BlockBuilder<V = DataStore> {
+    buf: Vec<u8>,
+    restarts: Vec<u32>,
+    last_key: Key,
+    entries: u32,
+    _value: PhantomData<V>,
+}
+
+impl<V> Default for BlockBuilder<V> {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            restarts: Vec::new(),
+            last_key: 0,
+            entries: 0,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V: Encode> BlockBuilder<V> {
+    /// Add an entry, the keys must be sorted. Return the encoded size.
+    pub fn add(&mut self, key: Key, value: &V) -> Result<usize> {
+        let encoded_key = if self.entries.is_multiple_of(BLOCK_RESTART_INTERVAL) {
+            self.restarts.push(self.buf.len() as u32);
+            key
+        } else {
+            key - self.last_key
+        };
+
+        let size =
+            bincode::encode_into_std_write((encoded_key, value), &mut self.buf, BIN_CODE_CONF)?;
+        self.last_key = key;
+        self.entries += 1;
+        Ok(size)
+    }
+
+    /// The size of the block if it is finished now.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len() + 4 * (self.restarts.len() + 1)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Append the restart points and reset the builder for the next block.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.buf);
+        for offset in self.restarts.iter() {
+            block.extend(offset.to_le_bytes());
+        }
+        block.extend((self.restarts.len() as u32).to_le_bytes());
+
+        self.restarts.clear();
+        self.entries = 0;
+        block
+    }
+}
+
+/// A cursor over the entries of a decompressed data block.
+///
+/// Blocks written by older versions have every entry with the full key
+/// and no restart points, read them with a `restart_interval` of 1.
+/// Columnar blocks are decoded as a whole, see [`BlockCursor::decoded`].
+#[derive(Debug, Clone)]
+pub struct BlockCursor<V = DataStore> {
+    block: Arc<Vec<u8>>,
+    pos: usize,
+    index: u32,
+    entries: u32,
+    last_key: Key,
+    restart_interval: u32,
+    decoded: Option<Arc<Vec<(Key, V)>>>,
+    hasher: Option<Hasher>,
+}
+
+impl<V: Decode<()> + Clone> BlockCursor<V> {
+    pub fn new(block: Arc<Vec<u8>>, entries: u32, restart_interval: u32) -> Self {
+        Self {
+            block,
+            pos: 0,
+            index: 0,
+            entries,
+            last_key: 0,
+            restart_interval,
+            decoded: None,
+            hasher: None,
+        }
+    }
+
+    /// A cursor over the decoded entries, with the checksum of the raw
+    /// data if the block is fully decoded.
+    pub fn decoded(entries: Vec<(Key, V)>, hasher: Option<Hasher>) -> Self {
+        Self {
+            entries: entries.len() as u32,
+            decoded: Some(Arc::new(entries)),
+            hasher,
+            ..Self::new(Arc::default(), 0, 1)
+        }
+    }
+
+    #[inline]
+    pub fn block(&self) -> &[u8] {
+        &self.block
+    }
+
+    /// The offset of the next entry in the block.
+    #[inline]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// The index of the next entry in the block.
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Update the checksum of the raw data with this block, the checksum
+    /// can't be verified if the block is decoded partially.
+    pub fn update_hasher(&self, hasher: &mut Option<Hasher>) {
+        match (&self.decoded, &self.hasher, hasher.as_mut()) {
+            (_, _, None) => {}
+            (None, _, Some(hasher)) => hasher.update(&self.block),
+            (Some(_), Some(block_hasher), Some(hasher)) => hasher.combine(block_hasher),
+            (Some(_), None, Some(_)) => *hasher = None,
+        }
+    }
+
+    /// Decode the next entry, `None` if there is no more.
+    pub fn next_entry(&mut self) -> Result<Option<(Key, V)>> {
+        if self.index >= self.entries {
+            return Ok(None);
+        }
+
+        if let Some(decoded) = &self.decoded {
+            self.index += 1;
+            return Ok(decoded.get(self.index as usize - 1).cloned());
+        }
+
+        let ((encoded_key, value), offset): ((Key, V), usize) =
+            bincode::decode_from_slice(&self.block[self.pos..], BIN_CODE_CONF)?;
+
+        let key = if self.index.is_multiple_of(self.restart_interval) {
+            encoded_key
+        } else {
+            self.last_key + encoded_key
+        };
+
+        self.pos += offset;
+        self.index += 1;
+        self.last_key = key;
+        Ok(Some((key, value)))
+    }
+
+    /// Move to the last restart point before the key by binary search,
+    /// blocks without restart points are scanned from the start.
+    pub fn seek(&mut self, key: Key) -> Result<()> {
+        if let Some(decoded) = &self.decoded {
+            self.index = decoded.partition_point(|(k, _)| *k < key) as u32;
+            return Ok(());
+        }
+
+        if self.restart_interval <= 1 || self.block.len() < 4 {
+            return Ok(());
+        }
+
+        let count_at = self.block.len() - 4;
+        let count = u32::from_le_bytes(self.block[count_at..].try_into().unwrap()) as usize;
+        let restarts_at = count_at
+            .checked_sub(count * 4)
+            .ok_or(DbError::InvalidData)?;
+
+        let restart = |i: usize| {
+            let at = restarts_at + i * 4;
+            u32::from_le_bytes(self.block[at..at + 4].try_into().unwrap()) as usize
+        };
+        let restart_key = |i: usize| -> Result<Key> {
+            Ok(bincode::decode_from_slice(&self.block[restart(i)..], BIN_CODE_CONF)?.0)
+        };
+
+        // the first restart point after the key
+        let (mut left, mut right) = (0, count);
+        while left < right {
+            let mid = (left + right) / 2;
+            if restart_key(mid)? <= key {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+
+        if left > 0 {
+            self.pos = restart(left - 1);
+            self.index = (left - 1) as u32 * self.restart_interval;
+        }
+
+        Ok(())
+    }
+}
+
+/// The filters of a SSTable, stored in the footer and loaded lazily.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct SSTableFilters {
+    pub bloom_filter: BloomFilter,
+    pub range_filter: Option<RangeFilter>,
+}
+
+impl SSTableFilters {
+    /// Build the filters for the sorted keys.
+    pub fn build(keys: &[Key], range_filter: bool) -> Self {
+        let mut filters = Self {
+            bloom_filter: BloomFilter::new(keys.len()),
+            range_filter: range_filter.then(|| RangeFilter::new(keys.len())),
+        };
+
+        for key in keys {
+            filters.bloom_filter.insert(key);
+            if let Some(range_filter) = filters.range_filter.as_mut() {
+                range_filter.insert(*key);
+            }
+        }
+
+        filters
+    }
+}
+
+/// The fixed size footer at the end of a SSTable file.
+///
+/// The file is laid out as `header`, `data blocks`, `dictionary`,
+/// `filter block`, `index block`, `footer`, the dictionary is empty
+/// if the blocks are compressed without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SSTableFooter {
+    pub dict_offset: u64,
+    pub dict_len: u32,
+    pub filter_offset: u64,
+    pub filter_len: u32,
+    pub index_offset: u64,
+    pub index_len: u32,
+}
+
+impl SSTableFooter {
+    pub async fn write_to(&self, io: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        io.write_u64(self.dict_offset).await?;
+        io.write_u32(self.dict_len).await?;
+        io.write_u64(self.filter_offset).await?;
+        io.write_u32(self.filter_len).await?;
+        io.write_u64(self.index_offset).await?;
+        io.write_u32(self.index_len).await?;
+        io.write_u32(SSTABLE_FOOTER_MAGIC_NUMBER).await?;
+        Ok(())
+    }
+
+    /// Decode the footer from the tail of the file, `None` if it is not one.
+    pub async fn decode(bytes: &[u8]) -> Result<Option<Self>> {
+        let footer_at = match bytes.len().checked_sub(FOOTER_SIZE as usize) {
+            Some(footer_at) => footer_at,
+            None => return Ok(None),
+        };
+
+        let mut bytes = &bytes[footer_at..];
+        let footer = Self {
+            dict_offset: bytes.read_u64().await?,
+            dict_len: bytes.read_u32().await?,
+            filter_offset: bytes.read_u64().await?,
+            filter_len: bytes.read_u32().await?,
+            index_offset: bytes.read_u64().await?,
+            index_len: bytes.read_u32().await?,
+        };
+
+        match bytes.read_u32().await? {
+            SSTABLE_FOOTER_MAGIC_NUMBER => Ok(Some(footer)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The end of the data blocks.
+    #[inline]
+    pub fn data_end(&self) -> u64 {
+        if self.dict_len > 0 {
+            self.dict_offset
+        } else {
+            self.filter_offset
+        }
+    }
+}
+
+pub async fn compress(raw: &[u8]) -> Result<Vec<u8>> {
+    let mut writer = CompressionEncoder::with_quality(Vec::new(), compression_level());
+    writer.write_all(raw).await?;
+    writer.shutdown().await?;
+    Ok(writer.into_inner())
+}
+
+pub async fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = CompressionDecoder::new(bytes);
+    let mut raw = Vec::new();
+    reader.read_to_end(&mut raw).await?;
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_works() -> Result<()> {
+        let footer = SSTableFooter {
+            dict_offset: 0,
+            dict_len: 0,
+            filter_offset: 0x1000,
+            filter_len: 0x20,
+            index_offset: 0x1020,
+            index_len: 0x10,
+        };
+
+        let mut bytes = vec![0xff; 8];
+        footer.write_to(&mut bytes).await?;
+        assert_eq!(bytes.len() as u64, 8 + FOOTER_SIZE);
+        assert_eq!(SSTableFooter::decode(&bytes).await?, Some(footer));
+        assert_eq!(SSTableFooter::decode(&bytes[9..]).await?, None);
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(SSTableFooter::decode(&bytes).await?, None);
+
+        // delta encoded keys with restart points
+        let mut builder = BlockBuilder::default();
+        let data = (0..0x100u64)
+            .map(|i| (i * 3 + 7, DataStore::Value(Arc::new(vec![i as u8; 4]))))
+            .collect::<Vec<_>>();
+        for (key, value) in data.iter() {
+            builder.add(*key, value)?;
+        }
+        let raw = builder.len();
+        let block = Arc::new(builder.finish());
+        assert_eq!(block.len(), raw);
+        assert!(builder.is_empty());
+
+        let mut cursor: BlockCursor =
+            BlockCursor::new(block.clone(), data.len() as u32, BLOCK_RESTART_INTERVAL);
+        for kvstore in data.iter() {
+            assert_eq!(cursor.next_entry()?.as_ref(), Some(kvstore));
+        }
+        assert_eq!(cursor.next_entry()?, None);
+
+        for (i, (key, _)) in data.iter().enumerate() {
+            let mut cursor: BlockCursor =
+                BlockCursor::new(block.clone(), data.len() as u32, BLOCK_RESTART_INTERVAL);
+            cursor.seek(*key)?;
+            assert_eq!(
+                cursor.index(),
+                i as u32 / BLOCK_RESTART_INTERVAL * BLOCK_RESTART_INTERVAL
+            );
+        }
+
+        let keys = (0..0x100).collect::<Vec<_>>();
+        let filters = SSTableFilters::build(&keys, true);
+        let raw = bincode::encode_to_vec(&filters, BIN_CODE_CONF)?;
+        assert_eq!(decompress(&compress(&raw).await?).await?, raw);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use super::BlockHandle;
+
+type BlockId = (Arc<PathBuf>, u64);
+
+/// The decompressed data blocks shared by the SSTables of all the tables,
+/// the least recently used ones are evicted first.
+///
+/// A block is found by its file and offset, and only if its checksum is
+/// the one of the handle, as a repaired SSTable is written again under
+/// the same name.
+#[derive(Debug, Default)]
+pub struct BlockCache {
+    inner: Mutex<LruBlocks>,
+}
+
+#[derive(Debug, Default)]
+struct LruBlocks {
+    capacity: usize,
+    used: usize,
+    tick: u64,
+    blocks: HashMap<BlockId, CachedBlock>,
+    /// The blocks by the tick they were last used.
+    lru: BTreeMap<u64, BlockId>,
+}
+
+#[derive(Debug)]
+struct CachedBlock {
+    checksum: u32,
+    block: Arc<Vec<u8>>,
+    tick: u64,
+}
+
+impl BlockCache {
+    /// Set the bytes of the cache, the blocks over it are evicted.
+    pub fn set_capacity(&self, bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = bytes;
+        inner.evict();
+    }
+
+    pub fn get(&self, file: &Arc<PathBuf>, handle: &BlockHandle) -> Option<Arc<Vec<u8>>> {
+        let mut inner = self.inner.lock().unwrap();
+        let id = (file.clone(), handle.offset);
+
+        let cached = inner.blocks.get(&id)?;
+        if cached.checksum != handle.checksum {
+            inner.remove(&id);
+            return None;
+        }
+
+        let last_tick = cached.tick;
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.lru.remove(&last_tick);
+        inner.lru.insert(tick, id.clone());
+
+        let cached = inner.blocks.get_mut(&id).unwrap();
+        cached.tick = tick;
+        Some(cached.block.clone())
+    }
+
+    /// Cache a block verified by the checksum of its handle.
+    pub fn insert(&self, file: &Arc<PathBuf>, handle: &BlockHandle, block: Arc<Vec<u8>>) {
+        let mut inner = self.inner.lock().unwrap();
+        if block.len() > inner.capacity {
+            return;
+        }
+
+        let id = (file.clone(), handle.offset);
+        inner.remove(&id);
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.used += block.len();
+        inner.lru.insert(tick, id.clone());
+        inner.blocks.insert(
+            id,
+            CachedBlock {
+                checksum: handle.checksum,
+                block,
+                tick,
+            },
+        );
+        inner.evict();
+    }
+
+    /// Get the bytes of the cached blocks.
+    pub fn used(&self) -> usize {
+        self.inner.lock().unwrap().used
+    }
+}
+
+impl LruBlocks {
+    fn remove(&mut self, id: &BlockId) {
+        if let Some(cached) = self.blocks.remove(id) {
+            self.used -= cached.block.len();
+            self.lru.remove(&cached.tick);
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.capacity {
+            let Some((_, id)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(cached) = self.blocks.remove(&id) {
+                self.used -= cached.block.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let cache = BlockCache::default();
+        cache.set_capacity(32);
+
+        let file = Arc::new(PathBuf::from("helper/cache_test/0.l0"));
+        let handle = |offset, checksum| BlockHandle {
+            first_key: 0,
+            last_key: 0,
+            offset,
+            len: 16,
+            entries: 1,
+            checksum,
+        };
+
+        cache.insert(&file, &handle(0, 1), Arc::new(vec![0; 16]));
+        cache.insert(&file, &handle(16, 2), Arc::new(vec![1; 16]));
+        assert_eq!(cache.used(), 32);
+
+        // the block at 16 is the least recently used one now
+        assert_eq!(cache.get(&file, &handle(0, 1)), Some(Arc::new(vec![0; 16])));
+        cache.insert(&file, &handle(32, 3), Arc::new(vec![2; 16]));
+        assert_eq!(cache.get(&file, &handle(16, 2)), None);
+        assert!(cache.get(&file, &handle(0, 1)).is_some());
+        assert!(cache.get(&file, &handle(32, 3)).is_some());
+
+        // the file was written again
+        assert_eq!(cache.get(&file, &handle(0, 4)), None);
+        assert_eq!(cache.used(), 16);
+
+        // too large to be cached
+        cache.insert(&file, &handle(48, 5), Arc::new(vec![3; 64]));
+        assert_eq!(cache.get(&file, &handle(48, 5)), None);
+
+        cache.set_capacity(0);
+        assert_eq!(cache.used(), 0);
+        assert_eq!(cache.get(&file, &handle(32, 3)), None);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+
+use crc32fast::Hasher;
+use tokio::fs;
+
+use crate::utils::*;
+
+use super::*;
+
+/// A problem found in the file of a SSTable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SSTableProblem {
+    /// The file in the manifest doesn't exist or is empty.
+    Missing,
+    /// The size of the file changed since it is opened.
+    FileSize { expected: u64, actual: u64 },
+    /// The header, the footer or the block index can't be decoded.
+    Unreadable(String),
+    /// A data block doesn't match its checksum or can't be decoded.
+    BadBlock {
+        offset: u64,
+        first_key: Key,
+        last_key: Key,
+    },
+    /// The raw data doesn't match the checksum in the header.
+    RawChecksum { expected: u32, actual: u32 },
+    /// The first key not greater than the previous one or out of its block.
+    KeyOrder { key: Key },
+    /// The keys are out of the range in the meta.
+    KeyRange { min_key: Key, max_key: Key },
+    /// The entries don't match the count in the meta.
+    EntriesCount { expected: usize, actual: usize },
+    /// The deleted entries don't match the count in the meta.
+    DeletedCount { expected: usize, actual: usize },
+}
+
+impl Display for SSTableProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "file missing or empty"),
+            Self::FileSize { expected, actual } => {
+                write!(f, "file size {} != {}", actual, expected)
+            }
+            Self::Unreadable(err) => write!(f, "unreadable, {}", err),
+            Self::BadBlock {
+                offset,
+                first_key,
+                last_key,
+            } => write!(
+                f,
+                "bad block at {} with keys [{},{}]",
+                offset, first_key, last_key
+            ),
+            Self::RawChecksum { expected, actual } => {
+                write!(f, "raw checksum {:08x} != {:08x}", actual, expected)
+            }
+            Self::KeyOrder { key } => write!(f, "key [{}] out of order", key),
+            Self::KeyRange { min_key, max_key } => {
+                write!(f, "keys [{},{}] out of the meta", min_key, max_key)
+            }
+            Self::EntriesCount { expected, actual } => {
+                write!(f, "{} entries != {}", actual, expected)
+            }
+            Self::DeletedCount { expected, actual } => {
+                write!(f, "{} deleted != {}", actual, expected)
+            }
+        }
+    }
+}
+
+/// The result of checking a SSTable.
+#[derive(Debug, Clone)]
+pub struct SSTableCheck {
+    pub key: SSTableKey,
+    pub file_name: String,
+    /// The entries in the readable blocks.
+    pub entries: usize,
+    pub problems: Vec<SSTableProblem>,
+}
+
+impl SSTableCheck {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// The result of `Table::check`.
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub tables: Vec<SSTableCheck>,
+    /// The SSTable files in the directory but not in the manifest, they
+    /// are left by an interrupted compaction and never read.
+    pub orphans: Vec<String>,
+}
+
+impl CheckReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.tables.iter().all(SSTableCheck::is_ok)
+    }
+
+    /// The checks of the damaged SSTables.
+    pub fn damaged(&self) -> impl Iterator<Item = &SSTableCheck> {
+        self.tables.iter().filter(|check| !check.is_ok())
+    }
+}
+
+/// The result of `Table::repair`.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// The SSTables kept as they are.
+    pub kept: usize,
+    /// The damaged SSTables rewritten with the entries in the readable blocks.
+    pub salvaged: Vec<SSTableCheck>,
+    /// The damaged SSTables without any readable entry.
+    pub dropped: Vec<SSTableCheck>,
+    /// The manifest is lost, the SSTables are adopted from the directory.
+    pub adopted: bool,
+}
+
+impl SSTable {
+    /// Verify the header, the block checksums, the key ordering and the
+    /// counters of the file against the meta.
+    pub async fn check(&self) -> SSTableCheck {
+        self.scan(false).await.0
+    }
+
+    /// Scan the file like `check`, and collect the entries in the readable
+    /// blocks if `salvage` is set, sorted and without duplicated keys.
+    pub async fn scan(&self, salvage: bool) -> (SSTableCheck, Vec<KvStore>) {
+        let meta = self.meta();
+        let mut check = SSTableCheck {
+            key: meta.key,
+            file_name: self.file_name().to_string(),
+            entries: 0,
+            problems: Vec::new(),
+        };
+        let mut salvaged = Vec::new();
+
+        let file_size = fs::metadata(self.file_name())
+            .await
+            .map_or(0, |metadata| metadata.len());
+        if file_size == 0 {
+            check.problems.push(SSTableProblem::Missing);
+            return (check, salvaged);
+        }
+        if file_size != meta.file_size {
+            check.problems.push(SSTableProblem::FileSize {
+                expected: meta.file_size,
+                actual: file_size,
+            });
+        }
+
+        let (mut iter, index) = match self.scan_index().await {
+            Ok(index) => index,
+            Err(err) => {
+                check
+                    .problems
+                    .push(SSTableProblem::Unreadable(format!("{:?}", err)));
+                return (check, salvaged);
+            }
+        };
+
+        let mut hasher = Some(Hasher::new());
+        let mut last_key = None;
+        let mut bad_blocks = false;
+        let mut key_order = None;
+        let mut deleted = 0;
+
+        for handle in index.iter() {
+            let bad_block = SSTableProblem::BadBlock {
+                offset: handle.offset,
+                first_key: handle.first_key,
+                last_key: handle.last_key,
+            };
+
+            let mut cursor = match iter.block_cursor(handle, &None).await {
+                Ok(cursor) => cursor,
+                Err(_) => {
+                    check.problems.push(bad_block);
+                    bad_blocks = true;
+                    hasher = None;
+                    continue;
+                }
+            };
+            cursor.update_hasher(&mut hasher);
+
+            loop {
+                let (key, value) = match cursor.next_entry() {
+                    Ok(Some(kvstore)) => kvstore,
+                    Ok(None) => break,
+                    Err(_) => {
+                        check.problems.push(bad_block);
+                        bad_blocks = true;
+                        break;
+                    }
+                };
+
+                if last_key >= Some(key) || key < handle.first_key || key > handle.last_key {
+                    key_order.get_or_insert(key);
+                }
+                last_key = last_key.max(Some(key));
+
+                check.entries += 1;
+                if value.is_deleted() {
+                    deleted += 1;
+                }
+                if salvage {
+                    salvaged.push((key, value));
+                }
+            }
+        }
+
+        if let Some(key) = key_order {
+            check.problems.push(SSTableProblem::KeyOrder { key });
+        }
+
+        if let Some(hasher) = hasher {
+            let actual = hasher.finalize();
+            if actual != iter.raw_checksum() {
+                check.problems.push(SSTableProblem::RawChecksum {
+                    expected: iter.raw_checksum(),
+                    actual,
+                });
+            }
+        }
+
+        // the counters can't match if some blocks are lost
+        if !bad_blocks {
+            if check.entries != meta.entries_count {
+                check.problems.push(SSTableProblem::EntriesCount {
+                    expected: meta.entries_count,
+                    actual: check.entries,
+                });
+            }
+            if deleted != meta.deleted_count {
+                check.problems.push(SSTableProblem::DeletedCount {
+                    expected: meta.deleted_count,
+                    actual: deleted,
+                });
+            }
+
+            let min_key = index.first().map(|handle| handle.first_key);
+            if let (Some(min_key), Some(max_key)) = (min_key, last_key) {
+                if min_key < meta.min_key || max_key > meta.max_key {
+                    check
+                        .problems
+                        .push(SSTableProblem::KeyRange { min_key, max_key });
+                }
+            }
+        }
+
+        if key_order.is_some() {
+            // keep the first of the duplicated keys
+            salvaged.sort_by_key(|(key, _)| *key);
+            salvaged.dedup_by_key(|(key, _)| *key);
+        }
+
+        (check, salvaged)
+    }
+
+    /// Open the file again with a new iterator, and load the block index.
+    async fn scan_index(&self) -> Result<(SSTableIter, Arc<Vec<BlockHandle>>)> {
+        let mut iter = self.new_iter().await?;
+        let index = iter.index().await?;
+        Ok((iter, index))
+    }
+}
@@ -0,0 +1,323 @@
+use std::{ops::Range, sync::Arc};
+
+use crc32fast::Hasher;
+
+use crate::utils::*;
+
+use super::block::*;
+
+/// The columns needed by a scan indexed by the column, `None` for all.
+pub type Projection = Option<Arc<Vec<bool>>>;
+
+/// How the row images of a table are split into columns.
+///
+/// The columns are the byte ranges of the row image between the field
+/// offsets supplied by the handler. The bytes before the first field,
+/// e.g. the null bitmap, are the first column which is always read.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ColumnLayout {
+    row_size: u32,
+    /// The start of every column in the row image, sorted.
+    starts: Vec<u32>,
+    /// The column of every field.
+    fields: Vec<u32>,
+}
+
+impl ColumnLayout {
+    pub fn new(row_size: u32, field_offsets: &[u32]) -> Result<Self> {
+        if let Some(offset) = field_offsets.iter().find(|offset| **offset >= row_size) {
+            return Err(DbError::Other(format!(
+                "Field offset {} out of the row size {}",
+                offset, row_size
+            )));
+        }
+
+        let mut starts = field_offsets.to_vec();
+        starts.push(0);
+        starts.sort_unstable();
+        starts.dedup();
+
+        let fields = field_offsets
+            .iter()
+            .map(|offset| starts.binary_search(offset).unwrap() as u32)
+            .collect();
+
+        Ok(Self {
+            row_size,
+            starts,
+            fields,
+        })
+    }
+
+    #[inline]
+    pub fn row_size(&self) -> u32 {
+        self.row_size
+    }
+
+    #[inline]
+    pub fn columns(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The byte range of a column in the row image.
+    #[inline]
+    pub fn column(&self, index: usize) -> Range<usize> {
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.row_size);
+        self.starts[index] as usize..end as usize
+    }
+
+    /// The columns needed to read the fields, unknown fields are ignored.
+    pub fn projection(&self, fields: &[u32]) -> Arc<Vec<bool>> {
+        let mut needed = vec![false; self.columns()];
+        needed[0] = true;
+
+        for field in fields {
+            if let Some(column) = self.fields.get(*field as usize) {
+                needed[*column as usize] = true;
+            }
+        }
+
+        Arc::new(needed)
+    }
+}
+
+/// The value of an entry in the key chunk of a columnar block.
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum ColumnRow {
+    /// The row image is split into the column chunks.
+    Columns,
+    /// The deleted rows, and the rows not matching the layout.
+    Row(DataStore),
+}
+
+/// Split the rows of a data block into a key chunk and a chunk per column.
+#[derive(Debug)]
+pub struct ColumnBlockBuilder {
+    layout: Arc<ColumnLayout>,
+    keys: BlockBuilder<ColumnRow>,
+    columns: Vec<Vec<u8>>,
+}
+
+impl ColumnBlockBuilder {
+    pub fn new(layout: Arc<ColumnLayout>) -> Self {
+        Self {
+            columns: vec![Vec::new(); layout.columns()],
+            keys: BlockBuilder::default(),
+            layout,
+        }
+    }
+
+    /// Add an entry, the keys must be sorted. Return the encoded size.
+    pub fn add(&mut self, (key, value): &KvStore) -> Result<usize> {
+        match value {
+            DataStore::Value(row) if row.len() == self.layout.row_size as usize => {
+                for (index, column) in self.columns.iter_mut().enumerate() {
+                    column.extend_from_slice(&row[self.layout.column(index)]);
+                }
+                Ok(self.keys.add(*key, &ColumnRow::Columns)? + row.len())
+            }
+            _ => self.keys.add(*key, &ColumnRow::Row(value.clone())),
+        }
+    }
+
+    /// The size of the raw chunks if it is finished now.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.keys.len() + self.columns.iter().map(Vec::len).sum::<usize>()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Get the raw key chunk and column chunks, and reset the builder.
+    pub fn finish(&mut self) -> Vec<Vec<u8>> {
+        let mut chunks = vec![self.keys.finish()];
+        for column in self.columns.iter_mut() {
+            chunks.push(std::mem::take(column));
+        }
+        chunks
+    }
+
+    /// Compress the chunks into a columnar block.
+    ///
+    /// The block starts with the count of the columns, the size of the
+    /// key chunk and the width and size of every column chunk, followed
+    /// by the chunks compressed separately, so a scan only decompresses
+    /// the columns it needs.
+    pub async fn encode(&self, chunks: &[Vec<u8>], compression: Compression) -> Result<Vec<u8>> {
+        let mut compressed = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            compressed.push(compression.compress(chunk).await?);
+        }
+
+        let mut block = Vec::new();
+        block.extend((self.columns.len() as u32).to_le_bytes());
+        block.extend((compressed[0].len() as u32).to_le_bytes());
+        for (index, chunk) in compressed[1..].iter().enumerate() {
+            block.extend((self.layout.column(index).len() as u32).to_le_bytes());
+            block.extend((chunk.len() as u32).to_le_bytes());
+        }
+
+        for chunk in compressed {
+            block.extend(chunk);
+        }
+
+        Ok(block)
+    }
+}
+
+/// Decode a columnar block, the columns out of the projection are zeroed.
+///
+/// Return the entries, and the checksum of the raw chunks if all the
+/// columns are decoded.
+pub async fn decode_columnar(
+    block: &[u8],
+    entries: u32,
+    compression: Compression,
+    projection: &Projection,
+) -> Result<(Vec<KvStore>, Option<Hasher>)> {
+    let read_u32 = |at: usize| -> Result<u32> {
+        block
+            .get(at..at + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(DbError::InvalidData)
+    };
+
+    let columns = read_u32(0)? as usize;
+    let mut offset = 8 + columns * 8;
+    let keys_len = read_u32(4)? as usize;
+
+    let keys = compression
+        .decompress(
+            block
+                .get(offset..offset + keys_len)
+                .ok_or(DbError::InvalidData)?,
+        )
+        .await?;
+    offset += keys_len;
+
+    let mut hasher = projection.is_none().then(Hasher::new);
+    if let Some(hasher) = hasher.as_mut() {
+        hasher.update(&keys);
+    }
+
+    // the width and the raw data of every column, `None` if not needed
+    let mut chunks = Vec::with_capacity(columns);
+    for index in 0..columns {
+        let width = read_u32(8 + index * 8)? as usize;
+        let len = read_u32(12 + index * 8)? as usize;
+        let chunk = block
+            .get(offset..offset + len)
+            .ok_or(DbError::InvalidData)?;
+        offset += len;
+
+        let needed = projection
+            .as_ref()
+            .is_none_or(|needed| needed.get(index).copied().unwrap_or(true));
+        if !needed {
+            chunks.push((width, None));
+            continue;
+        }
+
+        let chunk = compression.decompress(chunk).await?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&chunk);
+        }
+        chunks.push((width, Some(chunk)));
+    }
+
+    let row_size = chunks.iter().map(|(width, _)| width).sum::<usize>();
+    let mut cursor = BlockCursor::<ColumnRow>::new(Arc::new(keys), entries, BLOCK_RESTART_INTERVAL);
+    let mut decoded = Vec::with_capacity(entries as usize);
+    let mut rows = 0;
+
+    while let Some((key, value)) = cursor.next_entry()? {
+        let value = match value {
+            ColumnRow::Row(value) => value,
+            ColumnRow::Columns => {
+                let mut row = vec![0; row_size];
+                let mut start = 0;
+                for (width, chunk) in chunks.iter() {
+                    if let Some(chunk) = chunk {
+                        let value = chunk
+                            .get(rows * width..(rows + 1) * width)
+                            .ok_or(DbError::InvalidData)?;
+                        row[start..start + width].copy_from_slice(value);
+                    }
+                    start += width;
+                }
+                rows += 1;
+                DataStore::Value(Arc::new(row))
+            }
+        };
+
+        decoded.push((key, value));
+    }
+
+    Ok((decoded, hasher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_works() -> Result<()> {
+        // a null byte, a 4 bytes field and a 8 bytes field
+        let layout = Arc::new(ColumnLayout::new(13, &[5, 1])?);
+        assert_eq!(layout.columns(), 3);
+        assert_eq!(layout.column(1), 1..5);
+        assert_eq!(layout.column(2), 5..13);
+        assert_eq!(*layout.projection(&[0]), vec![true, false, true]);
+        assert!(ColumnLayout::new(13, &[13]).is_err());
+
+        let data = (0..0x100u64)
+            .map(|i| {
+                let value = match i % 7 {
+                    0 => DataStore::Deleted,
+                    1 => DataStore::Value(Arc::new(vec![i as u8; 3])),
+                    _ => DataStore::Value(Arc::new(vec![i as u8; 13])),
+                };
+                (i * 3, value)
+            })
+            .collect::<Vec<_>>();
+
+        let mut builder = ColumnBlockBuilder::new(layout.clone());
+        for kvstore in data.iter() {
+            builder.add(kvstore)?;
+        }
+        let chunks = builder.finish();
+        assert!(builder.is_empty());
+        assert_eq!(chunks.len(), 4);
+
+        let compression = Compression::default();
+        let block = builder.encode(&chunks, compression).await?;
+
+        let (decoded, hasher) = decode_columnar(&block, 0x100, compression, &None).await?;
+        assert_eq!(decoded, data);
+
+        let mut expected = Hasher::new();
+        chunks.iter().for_each(|chunk| expected.update(chunk));
+        assert_eq!(hasher.unwrap().finalize(), expected.finalize());
+
+        // only the null byte and the first field
+        let projection = Some(layout.projection(&[1]));
+        let (decoded, hasher) = decode_columnar(&block, 0x100, compression, &projection).await?;
+        assert!(hasher.is_none());
+
+        for ((key, value), (expected_key, expected_value)) in decoded.iter().zip(data.iter()) {
+            assert_eq!(key, expected_key);
+            match (value, expected_value) {
+                (DataStore::Value(row), DataStore::Value(expected)) if row.len() == 13 => {
+                    assert_eq!(row[..5], expected[..5]);
+                    assert_eq!(row[5..], [0; 8]);
+                }
+                _ => assert_eq!(value, expected_value),
+            }
+        }
+
+        Ok(())
+    }
+}
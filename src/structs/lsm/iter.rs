@@ -9,6 +9,8 @@ use super::*;
 pub struct LsmTreeIterator {
     tables: DequeIterator<Arc<SSTable>>,
     cur: Option<Arc<SSTable>>,
+    range: Option<(Key, Key)>,
+    projection: Projection,
 }
 
 impl LsmTreeIterator {
@@ -16,8 +18,24 @@ impl LsmTreeIterator {
         Self {
             tables: DequeIterator::new(tables),
             cur: None,
+            range: None,
+            projection: None,
         }
     }
+
+    /// Only yield the keys in `[start, end]`.
+    pub fn with_range(tables: VecDeque<Arc<SSTable>>, start: Key, end: Key) -> Self {
+        Self {
+            range: Some((start, end)),
+            ..Self::new(tables)
+        }
+    }
+
+    /// Only decode the columns in the projection from columnar tables.
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
 }
 
 impl AsyncIterator<KvStore> for LsmTreeIterator {
@@ -29,14 +47,33 @@ impl AsyncIterator<KvStore> for LsmTreeIterator {
                 if let Some(cur) = &mut self.cur {
                     let mut iter = cur.iter().await;
 
-                    if let Some(kvstore) = iter.next().await? {
-                        return Ok(Some(kvstore));
+                    while let Some(kvstore) = iter.next().await? {
+                        match self.range {
+                            Some((start, _)) if kvstore.0 < start => continue,
+                            // the keys are sorted, skip the rest of this table
+                            Some((_, end)) if kvstore.0 > end => break,
+                            _ => return Ok(Some(kvstore)),
+                        }
                     }
                 }
 
                 match self.tables.next() {
                     Some(table) => {
-                        table.init_iter().await?;
+                        if let Some((start, end)) = self.range {
+                            if !table.may_contain_range(start, end).await? {
+                                trace!("Skip table for range: {:?}", table.meta().key);
+                                self.cur = None;
+                                continue;
+                            }
+                        }
+
+                        let mut iter = table.iter().await;
+                        iter.set_projection(self.projection.clone());
+                        // seek to the block of the start key
+                        let start = self.range.map_or(0, |(start, _)| start);
+                        iter.init_iter_for_key(start).await?;
+                        drop(iter);
+
                         self.cur = Some(table);
                     }
                     None => break,
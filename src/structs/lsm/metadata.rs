@@ -1,12 +1,18 @@
-use crate::utils::BloomFilter;
+use crate::utils::{BloomFilter, Key};
 
-use super::sstable::SSTableKey;
+use super::{block::SSTableFilters, sstable::SSTableKey};
 
+/// The file-level meta of a SSTable, stored in the manifest.
+///
+/// The filters and the block index are in the footer of the file.
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct SSTableMeta {
     pub key: SSTableKey,
     pub entries_count: usize,
-    pub bloom_filter: BloomFilter,
+    pub deleted_count: usize,
+    pub min_key: Key,
+    pub max_key: Key,
+    pub file_size: u64,
 }
 
 impl SSTableMeta {
@@ -14,13 +20,35 @@ impl SSTableMeta {
         Self {
             key,
             entries_count: 0,
-            bloom_filter: BloomFilter::new(key.level()),
+            deleted_count: 0,
+            min_key: 0,
+            max_key: 0,
+            file_size: 0,
         }
     }
+}
+
+/// The meta written by older versions, with the bloom filter.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct LegacySSTableMeta {
+    pub key: SSTableKey,
+    pub entries_count: usize,
+    pub bloom_filter: BloomFilter,
+}
+
+impl LegacySSTableMeta {
+    /// Split into the meta and the filters.
+    pub fn into_parts(self) -> (SSTableMeta, SSTableFilters) {
+        let meta = SSTableMeta {
+            entries_count: self.entries_count,
+            ..SSTableMeta::new(self.key)
+        };
+        let filters = SSTableFilters {
+            bloom_filter: self.bloom_filter,
+            range_filter: None,
+        };
 
-    #[inline]
-    pub fn set_entries_count(&mut self, count: usize) {
-        self.entries_count = count;
+        (meta, filters)
     }
 }
 
@@ -31,7 +59,8 @@ mod tests {
     #[test]
     fn it_works() {
         let mut meta = SSTableMeta::new(SSTableKey::new(0u64));
-        meta.bloom_filter.insert(&[1, 2, 3]);
+        meta.entries_count = 0x80;
+        meta.max_key = 0x100;
 
         let config = bincode::config::standard();
 
@@ -44,5 +73,7 @@ mod tests {
         let decoded: SSTableMeta = bincode::decode_from_slice(&bytes, config).unwrap().0;
 
         assert_eq!(meta.key, decoded.key);
+        assert_eq!(meta.entries_count, decoded.entries_count);
+        assert_eq!(meta.max_key, decoded.max_key);
     }
 }
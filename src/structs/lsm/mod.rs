@@ -1,12 +1,22 @@
+pub mod block;
+pub mod cache;
+pub mod check;
+pub mod column;
 pub mod iter;
 pub mod metadata;
 pub mod sstable;
 pub mod sstable_iter;
+pub mod writer;
 
+pub use block::*;
+pub use cache::*;
+pub use check::*;
+pub use column::*;
 pub use iter::*;
 pub use metadata::*;
 pub use sstable::*;
 pub use sstable_iter::*;
+pub use writer::*;
 
 use std::sync::Arc;
 
@@ -1,5 +1,4 @@
 use std::{
-    collections::BTreeMap,
     fmt::{Debug, LowerHex},
     path::PathBuf,
     sync::{
@@ -13,36 +12,65 @@ use chrono::TimeZone;
 use tokio::{
     fs,
     io::AsyncWriteExt,
-    sync::{Mutex, MutexGuard},
+    sync::{Mutex, MutexGuard, OnceCell},
 };
 
 use crate::{structs::*, utils::*};
 
 use super::*;
 
+/// Options for writing new SSTables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SSTableOptions {
+    pub range_filter: bool,
+    pub compression: Compression,
+    /// Train a zstd dictionary for the blocks, for tables with small rows
+    /// sharing values across the blocks. Dropped if it doesn't help.
+    pub zstd_dictionary: bool,
+    /// Split the row images into columns, set by the handler.
+    pub columns: Option<Arc<ColumnLayout>>,
+}
+
+impl Default for SSTableOptions {
+    fn default() -> Self {
+        Self {
+            range_filter: true,
+            compression: Compression::default(),
+            zstd_dictionary: false,
+            columns: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SSTable {
     meta: SSTableMeta,
     iter: Mutex<SSTableIter>,
     locked: AtomicBool,
     file_name: Arc<PathBuf>,
-    data_size: u32,
+    filters: OnceCell<Arc<SSTableFilters>>,
 }
 
 impl SSTable {
-    pub async fn new(
-        meta: SSTableMeta,
-        factory: &IOHandlerFactory,
-        data_size: u32,
-    ) -> Result<Self> {
+    pub async fn new(mut meta: SSTableMeta, factory: &IOHandlerFactory) -> Result<Self> {
         let key = meta.key;
         let io = factory.create(key).await?;
+        let iter = SSTableIter::new(io).await?;
+
+        // the header is the source of truth
+        if iter.file_size() > 0 {
+            (meta.min_key, meta.max_key) = iter.key_range();
+            meta.entries_count = iter.entries_count() as usize;
+            meta.deleted_count = iter.deleted_count() as usize;
+        }
+        meta.file_size = iter.file_size();
+
         Ok(Self {
             meta,
-            data_size,
             locked: AtomicBool::new(false),
-            file_name: io.file_path.clone(),
-            iter: Mutex::new(SSTableIter::new(io, data_size).await?),
+            file_name: iter.clone_io().await?.file_path.clone(),
+            iter: Mutex::new(iter),
+            filters: OnceCell::new(),
         })
     }
 
@@ -56,6 +84,12 @@ impl SSTable {
         self.file_name.to_str().unwrap()
     }
 
+    /// Get the cached size of the file.
+    #[inline]
+    pub fn file_size(&self) -> u64 {
+        self.meta.file_size
+    }
+
     #[inline]
     pub async fn iter(&self) -> MutexGuard<SSTableIter> {
         self.iter.lock().await
@@ -66,6 +100,85 @@ impl SSTable {
         self.iter.lock().await.init_iter_for_key(0).await
     }
 
+    /// Use the filters from the manifest written by older versions.
+    pub fn preload_filters(&self, filters: SSTableFilters) {
+        self.filters.set(Arc::new(filters)).ok();
+    }
+
+    /// Get the filters, loaded from the footer on first access.
+    pub async fn filters(&self) -> Result<Arc<SSTableFilters>> {
+        self.filters
+            .get_or_try_init(|| async {
+                let iter = self.iter.lock().await;
+
+                let filters = match iter.footer() {
+                    Some(footer) => {
+                        let bytes = iter
+                            .read_at(footer.filter_offset, footer.filter_len)
+                            .await?;
+                        bincode::decode_from_slice(&decompress(&bytes).await?, BIN_CODE_CONF)?.0
+                    }
+                    None => {
+                        drop(iter);
+                        self.rebuild_filters().await?
+                    }
+                };
+
+                trace!("Load filters        : {:?}", self.meta.key);
+                Ok(Arc::new(filters))
+            })
+            .await
+            .cloned()
+    }
+
+    /// Files written by older versions have no footer, scan them for the keys.
+    async fn rebuild_filters(&self) -> Result<SSTableFilters> {
+        warn!("Rebuild filters     : {:?}", self.file_name);
+
+        let mut iter = self.new_iter().await?;
+        iter.init_iter().await?;
+
+        let mut keys = Vec::with_capacity(self.meta.entries_count);
+        while let Some((key, _)) = iter.next().await? {
+            keys.push(key);
+        }
+
+        Ok(SSTableFilters::build(&keys, true))
+    }
+
+    /// Get the block index, loaded from the footer on first access.
+    #[inline]
+    pub async fn index(&self) -> Result<Arc<Vec<BlockHandle>>> {
+        self.iter.lock().await.index().await
+    }
+
+    /// Get the codec of the data blocks.
+    #[inline]
+    pub async fn compression(&self) -> Compression {
+        self.iter.lock().await.compression()
+    }
+
+    /// Check if the key may be in this table by the bloom filter.
+    pub async fn may_contain(&self, key: Key) -> Result<bool> {
+        Ok(self.filters().await?.bloom_filter.contains(key))
+    }
+
+    /// Check if there may be any key in `[start, end]`,
+    /// by the key range and the range filter.
+    pub async fn may_contain_range(&self, start: Key, end: Key) -> Result<bool> {
+        let (min_key, max_key) = (self.meta.min_key, self.meta.max_key);
+        if self.meta.entries_count == 0 || max_key < start || end < min_key {
+            return Ok(false);
+        }
+
+        Ok(self
+            .filters()
+            .await?
+            .range_filter
+            .as_ref()
+            .is_none_or(|filter| filter.may_contain_range(start.max(min_key), end.min(max_key))))
+    }
+
     #[inline]
     pub fn is_locked(&self) -> bool {
         self.locked.load(Ordering::Relaxed)
@@ -87,37 +200,114 @@ impl SSTable {
     pub async fn new_iter(&self) -> Result<SSTableIter> {
         trace!("New iter for sstable: {:?}", self.file_name);
         let io = self.iter.lock().await.clone_io().await?;
-        SSTableIter::new(io, self.data_size).await
+        SSTableIter::new(io).await
     }
 
     /// archive data to disk
     ///
     /// # Arguments
     /// * `data` - data to be archived, must be sorted by key
-    pub async fn archive(&self, data: &BTreeMap<Key, DataStore>) -> Result<()> {
-        let min_key = *data.iter().next().unwrap().0;
-        let max_key = *data.iter().next_back().unwrap().0;
-
-        let entries_count = data.len() as u32;
+    /// * `options` - the filters and the codec of the table
+    pub async fn archive(
+        &mut self,
+        data: impl IntoIterator<Item = KvStore>,
+        options: SSTableOptions,
+    ) -> Result<()> {
+        let mut keys = Vec::new();
         let mut deleted_count = 0;
         let mut raw_hasher = crc32fast::Hasher::new();
 
-        let mut bytes_read = 0;
-        let bytes = {
-            let mut writer = CompressionEncoder::with_quality(Vec::new(), COMPRESSION_LEVEL);
-            for kvstore in data.iter() {
-                if kvstore.1.is_deleted() {
-                    deleted_count += 1;
+        // split the data into blocks of about `DATA_BLOCK_SIZE`
+        let mut raw_blocks = Vec::new();
+        let mut builder = BlockBuilder::default();
+        let mut columns = options.columns.clone().map(ColumnBlockBuilder::new);
+        let mut block_start = 0;
+        let mut entry_sizes = Vec::new();
+
+        // the raw chunks of a block, the rows or the keys and the columns
+        let finish_block =
+            |builder: &mut BlockBuilder, columns: &mut Option<ColumnBlockBuilder>| match columns {
+                Some(columns) => columns.finish(),
+                None => vec![builder.finish()],
+            };
+
+        for kvstore in data {
+            if kvstore.1.is_deleted() {
+                deleted_count += 1;
+            }
+            keys.push(kvstore.0);
+
+            let len = match columns.as_mut() {
+                Some(columns) => {
+                    entry_sizes.push(columns.add(&kvstore)?);
+                    columns.len()
+                }
+                None => {
+                    entry_sizes.push(builder.add(kvstore.0, &kvstore.1)?);
+                    builder.len()
                 }
-                let row = bincode::encode_to_vec(kvstore, BIN_CODE_CONF).unwrap();
-                bytes_read += row.len();
-                raw_hasher.update(&row);
-                writer.write_all(&row).await?;
+            };
+
+            if len >= DATA_BLOCK_SIZE {
+                let chunks = finish_block(&mut builder, &mut columns);
+                raw_blocks.push((block_start..keys.len(), chunks));
+                block_start = keys.len();
             }
-            writer.shutdown().await?;
-            writer.into_inner()
+        }
+
+        if block_start < keys.len() {
+            let chunks = finish_block(&mut builder, &mut columns);
+            raw_blocks.push((block_start..keys.len(), chunks));
+        }
+
+        let (min_key, max_key) = match (keys.first(), keys.last()) {
+            (Some(min_key), Some(max_key)) => (*min_key, *max_key),
+            _ => return Err(DbError::EmptyFile),
+        };
+
+        let dictionary = match options.compression {
+            // the columns are compressed without the dictionary
+            Compression::Zstd(level) if options.zstd_dictionary && columns.is_none() => {
+                Self::train_dictionary(&raw_blocks, &entry_sizes, level)
+            }
+            _ => None,
         };
 
+        let mut bytes_read = 0;
+        let mut bytes = Vec::new();
+        let mut index = Vec::with_capacity(raw_blocks.len());
+        for (entries, chunks) in raw_blocks {
+            for chunk in chunks.iter() {
+                bytes_read += chunk.len();
+                raw_hasher.update(chunk);
+            }
+
+            let block = match (&columns, &dictionary) {
+                (Some(columns), _) => columns.encode(&chunks, options.compression).await?,
+                (None, Some(dictionary)) => dictionary.compress(&chunks[0])?,
+                (None, None) => options.compression.compress(&chunks[0]).await?,
+            };
+
+            index.push(BlockHandle {
+                first_key: keys[entries.start],
+                last_key: keys[entries.end - 1],
+                offset: HEADER_SIZE + bytes.len() as u64,
+                len: block.len() as u32,
+                entries: entries.len() as u32,
+                checksum: crc32fast::hash(&block),
+            });
+            bytes.extend_from_slice(&block);
+        }
+
+        let dict_bytes = dictionary
+            .as_ref()
+            .map_or(&[][..], |dictionary| dictionary.as_bytes());
+
+        let entries_count = keys.len() as u32;
+        let filters = SSTableFilters::build(&keys, options.range_filter);
+        let filter_bytes = compress(&bincode::encode_to_vec(&filters, BIN_CODE_CONF)?).await?;
+        let index_bytes = compress(&bincode::encode_to_vec(&index, BIN_CODE_CONF)?).await?;
+
         let raw_checksum = raw_hasher.finalize();
         let mut compressed_hasher = crc32fast::Hasher::new();
         compressed_hasher.update(&bytes);
@@ -125,9 +315,11 @@ impl SSTable {
 
         if self.meta.key.level() > 0 {
             debug!(
-                "Encoded ({}/{}) bytes with checksum ({:08x}/{:08x}), key range: [{}, {}]",
+                "Encoded ({}/{}) bytes by {} (dictionary {}) with checksum ({:08x}/{:08x}), key range: [{}, {}]",
                 bytes_read,
                 bytes.len(),
+                options.compression,
+                dict_bytes.len(),
                 raw_checksum,
                 compressed_checksum,
                 min_key,
@@ -135,10 +327,24 @@ impl SSTable {
             );
         }
 
-        let io = self.iter.lock().await.clone_io().await?;
+        let dict_offset = HEADER_SIZE + bytes.len() as u64;
+        let filter_offset = dict_offset + dict_bytes.len() as u64;
+        let footer = SSTableFooter {
+            dict_offset,
+            dict_len: dict_bytes.len() as u32,
+            filter_offset,
+            filter_len: filter_bytes.len() as u32,
+            index_offset: filter_offset + filter_bytes.len() as u64,
+            index_len: index_bytes.len() as u32,
+        };
+
+        let io = self.iter.get_mut().clone_io().await?;
         let mut file_io = io.inner().await?;
 
         file_io.write_u32(SSTABLE_MAGIC_NUMBER).await?;
+        file_io.write_u32(options.compression.to_u32()).await?;
+        file_io.write_u32(BLOCK_RESTART_INTERVAL).await?;
+        file_io.write_u32(columns.is_some() as u32).await?;
         file_io.write_u32(raw_checksum).await?;
         file_io.write_u32(compressed_checksum).await?;
         file_io.write_u32(entries_count).await?;
@@ -146,9 +352,21 @@ impl SSTable {
         file_io.write_u64(min_key).await?;
         file_io.write_u64(max_key).await?;
         file_io.write_all(&bytes).await?;
+        file_io.write_all(dict_bytes).await?;
+        file_io.write_all(&filter_bytes).await?;
+        file_io.write_all(&index_bytes).await?;
+        footer.write_to(&mut *file_io).await?;
+        file_io.flush().await?;
 
         drop(file_io); // release lock
 
+        self.meta.entries_count = entries_count as usize;
+        self.meta.deleted_count = deleted_count as usize;
+        (self.meta.min_key, self.meta.max_key) = (min_key, max_key);
+        self.meta.file_size = footer.index_offset + footer.index_len as u64 + FOOTER_SIZE;
+
+        self.filters = OnceCell::new_with(Some(Arc::new(filters)));
+
         if self.meta.key.level() > 0 {
             debug!(
                 "Archived {} entries ({} deleted) to {:?}",
@@ -156,21 +374,83 @@ impl SSTable {
             );
         }
 
-        self.iter.lock().await.recreate().await?;
+        self.iter.get_mut().recreate().await?;
 
         Ok(())
     }
+
+    /// Train a zstd dictionary from the entries evenly picked in the blocks,
+    /// no dictionary if there are too few samples to train.
+    fn train_dictionary(
+        raw_blocks: &[(std::ops::Range<usize>, Vec<Vec<u8>>)],
+        entry_sizes: &[usize],
+        level: u8,
+    ) -> Option<ZstdDictionary> {
+        let total = raw_blocks
+            .iter()
+            .map(|(_, raw)| raw[0].len())
+            .sum::<usize>();
+        let step = total.div_ceil(ZSTD_DICT_SAMPLES_SIZE).max(1);
+
+        let mut samples = Vec::new();
+        for (entries, raw) in raw_blocks {
+            let raw = &raw[0];
+            let mut pos = 0;
+            for i in entries.clone() {
+                if i % step == 0 {
+                    samples.push(&raw[pos..pos + entry_sizes[i]]);
+                }
+                pos += entry_sizes[i];
+            }
+        }
+
+        let dictionary = match ZstdDictionary::train(&samples, level) {
+            Ok(dictionary) => dictionary,
+            Err(err) => {
+                debug!("Skip dictionary     : {} samples, {:?}", samples.len(), err);
+                return None;
+            }
+        };
+
+        // the rows similar within a block are compressed well without it,
+        // the keys are delta encoded, so only keep it if it is smaller
+        let block = &raw_blocks.first()?.1[0];
+        let with_dictionary = dictionary.compress(block).ok()?.len();
+        let without = zstd::bulk::compress(block, level as i32).ok()?.len();
+        if with_dictionary >= without {
+            debug!(
+                "Skip dictionary     : {} bytes with it, {} without",
+                with_dictionary, without
+            );
+            return None;
+        }
+
+        Some(dictionary)
+    }
 }
 
 #[async_trait]
 impl AsyncKvStoreRead for SSTable {
     async fn get(&self, key: Key) -> Result<DataStore> {
         let mut iter = self.iter.lock().await;
-        iter.init_iter_for_key(key).await?;
+        let index = iter.index().await?;
+
+        // the first block which may contain the key
+        let pos = index.partition_point(|handle| handle.last_key < key);
+        let handle = match index.get(pos) {
+            Some(handle) if handle.first_key <= key => handle,
+            _ => return Ok(DataStore::NotFound),
+        };
 
-        while let Some(kvstore) = iter.next().await? {
-            if kvstore.0 == key {
-                return Ok(kvstore.1);
+        let mut cursor = iter.block_cursor(handle, &None).await?;
+        drop(iter);
+
+        cursor.seek(key)?;
+        while let Some(kvstore) = cursor.next_entry()? {
+            match kvstore.0.cmp(&key) {
+                std::cmp::Ordering::Less => continue,
+                std::cmp::Ordering::Equal => return Ok(kvstore.1),
+                std::cmp::Ordering::Greater => break,
             }
         }
 
@@ -209,6 +489,22 @@ impl SSTableKey {
         !(0x0F << 60) & !(self.0) as i64
     }
 
+    /// Get the name of the SSTable file, `{key:x}.l{level}`.
+    pub fn file_name(&self) -> String {
+        format!("{:x}.l{}", self, self.level())
+    }
+
+    /// Parse the key from the name of a SSTable file.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (key, level) = file_name.split_once(".l")?;
+        if key.len() != 16 {
+            return None;
+        }
+
+        let key = Self(u64::from_str_radix(key, 16).ok()?);
+        (key.valid() && level.parse() == Ok(key.level())).then_some(key)
+    }
+
     pub fn valid(&self) -> bool {
         let left = chrono::Utc
             .with_ymd_and_hms(2000, 1, 1, 0, 1, 1)
@@ -255,5 +551,188 @@ mod tests {
             assert_eq!(keys[i * 2].level(), i as u32);
             assert_eq!(keys[i * 2 + 1].level(), i as u32);
         }
+
+        let file_name = keys[2].file_name();
+        assert_eq!(SSTableKey::from_file_name(&file_name), Some(keys[2]));
+        assert_eq!(SSTableKey::from_file_name(".meta"), None);
+        assert_eq!(
+            SSTableKey::from_file_name(&file_name.replace(".l1", ".l2")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_works() -> Result<()> {
+        let test_dir = "helper/sstable_archive_test";
+
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let factory = IOHandlerFactory::new(test_dir);
+        // the names are shared by the rows in different blocks
+        let name = |i: u64| stable_hash(&(i % 512).to_le_bytes()) >> 28;
+        let data = (0..0x2000u64)
+            .map(|i| {
+                let row = format!("{:>8}|{:09x}|{:>5}", i, name(i), i % 60);
+                let value = match i % 13 {
+                    0 => DataStore::Deleted,
+                    _ => DataStore::Value(Arc::new(row.into_bytes())),
+                };
+                (i * 2, value)
+            })
+            .collect::<Vec<_>>();
+
+        let layout = Arc::new(ColumnLayout::new(24, &[1, 9, 19])?);
+        let all_options = [
+            SSTableOptions::default(),
+            SSTableOptions {
+                zstd_dictionary: true,
+                ..Default::default()
+            },
+            SSTableOptions {
+                columns: Some(layout.clone()),
+                ..Default::default()
+            },
+        ];
+
+        let mut sizes = Vec::new();
+        for options in all_options.clone() {
+            let mut sstable =
+                SSTable::new(SSTableMeta::new(SSTableKey::new(1u64)), &factory).await?;
+            sstable.archive(data.clone(), options.clone()).await?;
+
+            let footer = sstable.iter().await.footer().unwrap();
+            assert_eq!(footer.dict_len > 0, options.zstd_dictionary);
+            sizes.push(footer.data_end());
+
+            assert_eq!(sstable.get(0x102).await?, data[0x81].1);
+            assert_eq!(sstable.get(0x101).await?, DataStore::NotFound);
+
+            let mut iter = sstable.new_iter().await?;
+            iter.init_iter().await?;
+            for kvstore in data.iter() {
+                assert_eq!(iter.next().await?.as_ref(), Some(kvstore));
+            }
+            assert!(iter.next().await?.is_none());
+
+            super::super::sstable_iter::tests::check_file(sstable.file_name()).await?;
+
+            if options.columns.is_none() {
+                continue;
+            }
+
+            // only the null byte and the second field are decoded
+            iter.set_projection(Some(layout.projection(&[1])));
+            iter.init_iter().await?;
+            for (key, value) in data.iter() {
+                let (decoded_key, decoded) = iter.next().await?.unwrap();
+                assert_eq!(decoded_key, *key);
+
+                match (decoded, value) {
+                    (DataStore::Value(row), DataStore::Value(expected)) => {
+                        assert_eq!(row[..1], expected[..1]);
+                        assert_eq!(row[1..9], [0; 8]);
+                        assert_eq!(row[9..19], expected[9..19]);
+                        assert_eq!(row[19..], [0; 5]);
+                    }
+                    (decoded, value) => assert_eq!(decoded, *value),
+                }
+            }
+        }
+
+        // the blocks are smaller with the dictionary
+        assert!(sizes[1] < sizes[0]);
+
+        // but not if the rows are alike in every block
+        let similar = (0..0x2000u64)
+            .map(|i| {
+                let row = format!("{{\"id\":{},\"name\":\"user_{}\"}}", i, i % 97);
+                (i * 2, DataStore::Value(Arc::new(row.into_bytes())))
+            })
+            .collect::<Vec<_>>();
+        let mut sstable = SSTable::new(SSTableMeta::new(SSTableKey::new(1u64)), &factory).await?;
+        sstable.archive(similar, all_options[1].clone()).await?;
+        assert_eq!(sstable.iter().await.footer().unwrap().dict_len, 0);
+
+        // a flipped byte in the last block fails the reads of it
+        let mut sstable = SSTable::new(SSTableMeta::new(SSTableKey::new(2u64)), &factory).await?;
+        sstable.archive(data.clone(), Default::default()).await?;
+
+        let last = *sstable.iter().await.index().await?.last().unwrap();
+        let mut bytes = std::fs::read(sstable.file_name())?;
+        bytes[last.offset as usize + 1] ^= 0xff;
+        std::fs::write(sstable.file_name(), bytes)?;
+
+        assert_eq!(sstable.get(0).await?, data[0].1);
+        assert!(matches!(
+            sstable.get(last.last_key).await,
+            Err(DbError::MissChecksum)
+        ));
+
+        let mut iter = sstable.new_iter().await?;
+        iter.init_iter().await?;
+        let err = loop {
+            match iter.next().await {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("the corrupted block is read"),
+                Err(err) => break err,
+            }
+        };
+        assert!(matches!(err, DbError::MissChecksum));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn legacy_works() -> Result<()> {
+        let test_dir = "helper/sstable_legacy_test";
+
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let data = (0..0x100u64)
+            .map(|i| match i % 7 {
+                0 => (i * 3, DataStore::Deleted),
+                _ => (i * 3, DataStore::Value(Arc::new(vec![i as u8; 0x10]))),
+            })
+            .collect::<Vec<_>>();
+
+        // an old file with the entries in one compressed stream, no footer
+        let key = SSTableKey::new(1u64);
+        let factory = IOHandlerFactory::new(test_dir);
+        {
+            let mut raw = Vec::new();
+            for kvstore in data.iter() {
+                raw.extend(bincode::encode_to_vec(kvstore, BIN_CODE_CONF)?);
+            }
+            let bytes = compress(&raw).await?;
+
+            let mut file = Vec::new();
+            file.extend(LEGACY_SSTABLE_MAGIC_NUMBER.to_be_bytes());
+            file.extend(crc32fast::hash(&raw).to_be_bytes());
+            file.extend(crc32fast::hash(&bytes).to_be_bytes());
+            file.extend((data.len() as u32).to_be_bytes());
+            file.extend((data.len() as u32).div_ceil(7).to_be_bytes());
+            file.extend(data[0].0.to_be_bytes());
+            file.extend(data[data.len() - 1].0.to_be_bytes());
+            file.extend(bytes);
+            std::fs::write(factory.file_path(key), file)?;
+        }
+
+        let sstable = SSTable::new(SSTableMeta::new(key), &factory).await?;
+        assert_eq!(sstable.meta().entries_count, data.len());
+        assert!(sstable.iter().await.footer().is_none());
+        assert_eq!(sstable.get(0x30).await?, data[0x10].1);
+        assert_eq!(sstable.get(0x31).await?, DataStore::NotFound);
+        assert!(sstable.check().await.is_ok());
+
+        let mut iter = sstable.new_iter().await?;
+        iter.init_iter().await?;
+        for kvstore in data.iter() {
+            assert_eq!(iter.next().await?.as_ref(), Some(kvstore));
+        }
+        assert!(iter.next().await?.is_none());
+
+        Ok(())
     }
 }
@@ -1,53 +1,79 @@
-use bincode::error::DecodeError;
 use crc32fast::Hasher;
 use futures::Future;
-use std::{collections::VecDeque, io::SeekFrom};
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncSeekExt, BufReader},
-};
+use std::{io::SeekFrom, sync::Arc};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 use crate::{
-    structs::{AsyncIterator, SSTABLE_MAGIC_NUMBER},
+    core::Runtime,
+    structs::{AsyncIterator, LEGACY_SSTABLE_MAGIC_NUMBER, SSTABLE_MAGIC_NUMBER},
     utils::*,
 };
 
-pub const SSTABLE_ITER_BUF_SIZE: usize = 0x800;
-const HEADER_SIZE: u64 = 36;
+use super::{block::*, column::*};
+
+/// `magic_number`, `compression`, `restart_interval`, `columnar`,
+/// `raw_checksum`, `compressed_checksum`, `entries_count`,
+/// `deleted_count`, `min_key`, `max_key`
+pub const HEADER_SIZE: u64 = 48;
+
+/// The header written by older versions, without `compression`,
+/// `restart_interval` and `columnar`.
+pub const LEGACY_HEADER_SIZE: u64 = 36;
 
 #[derive(Debug)]
 pub struct SSTableIter {
     io: IOHandler,
+    compression: Compression,
+    header_size: u64,
+    restart_interval: u32,
+    columnar: bool,
+    projection: Projection,
     entries_count: u32,
     deleted_count: u32,
-    entry_cur: u32,
-    last_entry_key: Option<Key>,
-    bytes_read: usize,
-    hasher: Option<Hasher>,
-    buf: VecDeque<u8>,
     raw_checksum: u32,
     compressed_checksum: u32,
     min_key: Key,
     max_key: Key,
-    reader: Option<CompressionDecoder<BufReader<File>>>,
+    file_size: u64,
+    footer: Option<SSTableFooter>,
+    index: Option<Arc<Vec<BlockHandle>>>,
+    dictionary: Option<Arc<ZstdDictionary>>,
+    last_block: Option<(u64, Projection, BlockCursor)>,
+
+    entry_cur: u32,
+    last_entry_key: Option<Key>,
+    bytes_read: usize,
+    hasher: Option<Hasher>,
+    block_cur: usize,
+    block: Option<BlockCursor>,
 }
 
 impl SSTableIter {
-    pub async fn new(io: IOHandler, data_size: u32) -> Result<Self> {
+    pub async fn new(io: IOHandler) -> Result<Self> {
         let mut iter = Self {
             io,
+            compression: Compression::default(),
+            header_size: HEADER_SIZE,
+            restart_interval: BLOCK_RESTART_INTERVAL,
+            columnar: false,
+            projection: None,
             entries_count: 0,
             deleted_count: 0,
-            entry_cur: 0,
             raw_checksum: 0,
-            last_entry_key: None,
             compressed_checksum: 0,
-            bytes_read: 0,
             min_key: 0,
             max_key: 0,
+            file_size: 0,
+            footer: None,
+            index: None,
+            dictionary: None,
+            last_block: None,
+            entry_cur: 0,
+            last_entry_key: None,
+            bytes_read: 0,
             hasher: None,
-            buf: VecDeque::with_capacity(data_size as usize * 2),
-            reader: None,
+            block_cur: 0,
+            block: None,
         };
 
         iter.recreate().await?;
@@ -55,6 +81,192 @@ impl SSTableIter {
         Ok(iter)
     }
 
+    /// Get the min and max key in the header.
+    #[inline]
+    pub fn key_range(&self) -> (Key, Key) {
+        (self.min_key, self.max_key)
+    }
+
+    #[inline]
+    pub fn entries_count(&self) -> u32 {
+        self.entries_count
+    }
+
+    #[inline]
+    pub fn deleted_count(&self) -> u32 {
+        self.deleted_count
+    }
+
+    /// Get the checksum of the raw data in the header.
+    #[inline]
+    pub fn raw_checksum(&self) -> u32 {
+        self.raw_checksum
+    }
+
+    #[inline]
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Get the codec of the data blocks in the header.
+    #[inline]
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Get the number of entries between the restart points of the blocks.
+    #[inline]
+    pub fn restart_interval(&self) -> u32 {
+        self.restart_interval
+    }
+
+    /// If the data blocks are columnar, see `ColumnBlockBuilder`.
+    #[inline]
+    pub fn columnar(&self) -> bool {
+        self.columnar
+    }
+
+    #[inline]
+    pub fn footer(&self) -> Option<SSTableFooter> {
+        self.footer
+    }
+
+    /// The end of the data blocks, files without a footer
+    /// have the data till the end.
+    #[inline]
+    pub fn data_end(&self) -> u64 {
+        self.footer
+            .map_or(self.file_size, |footer| footer.data_end())
+    }
+
+    /// Read the bytes at `offset` of the file.
+    pub async fn read_at(&self, offset: u64, len: u32) -> Result<Vec<u8>> {
+        let mut file_io = self.io.inner().await?;
+        file_io.seek(SeekFrom::Start(offset)).await?;
+
+        let mut bytes = vec![0; len as usize];
+        file_io.read_exact(&mut bytes).await?;
+        Ok(bytes)
+    }
+
+    /// Get the block index, loaded from the footer on first access.
+    pub async fn index(&mut self) -> Result<Arc<Vec<BlockHandle>>> {
+        if let Some(index) = &self.index {
+            return Ok(index.clone());
+        }
+
+        let index = match self.footer {
+            Some(footer) => {
+                let bytes = self.read_at(footer.index_offset, footer.index_len).await?;
+                decode_index(&bytes).await?
+            }
+            // the whole data is one block
+            None if self.entries_count > 0 => vec![BlockHandle {
+                first_key: self.min_key,
+                last_key: self.max_key,
+                offset: self.header_size,
+                len: (self.data_end() - self.header_size) as u32,
+                entries: self.entries_count,
+                // the checksum of the whole data is the one of the block
+                checksum: self.compressed_checksum,
+            }],
+            None => Vec::new(),
+        };
+
+        let index = Arc::new(index);
+        self.index = Some(index.clone());
+        Ok(index)
+    }
+
+    /// Get the zstd dictionary of the data blocks, loaded from the file
+    /// on first access.
+    pub async fn dictionary(&mut self) -> Result<Option<Arc<ZstdDictionary>>> {
+        let footer = match self.footer {
+            Some(footer) if footer.dict_len > 0 => footer,
+            _ => return Ok(None),
+        };
+
+        if self.dictionary.is_none() {
+            let bytes = self.read_at(footer.dict_offset, footer.dict_len).await?;
+            trace!("Load dictionary     : {:?}", self.io.file_path);
+            self.dictionary = Some(Arc::new(ZstdDictionary::from_bytes(bytes)));
+        }
+
+        Ok(self.dictionary.clone())
+    }
+
+    /// Set the columns needed by the next scan.
+    pub fn set_projection(&mut self, projection: Projection) {
+        if self.projection != projection {
+            self.projection = projection;
+            // init the iter again for the new projection
+            self.last_entry_key = None;
+        }
+    }
+
+    /// Get a cursor over the entries of a data block, the columns out of
+    /// the projection are zeroed in the rows of columnar blocks.
+    ///
+    /// The last block is cached for sequential reads, and the row blocks
+    /// for all the tables by the block cache of the runtime. A block not
+    /// matching its checksum fails with `DbError::MissChecksum`.
+    pub async fn block_cursor(
+        &mut self,
+        handle: &BlockHandle,
+        projection: &Projection,
+    ) -> Result<BlockCursor> {
+        // the rows are always decoded as a whole
+        let projection = if self.columnar {
+            projection.clone()
+        } else {
+            None
+        };
+
+        if let Some((offset, cached, cursor)) = &self.last_block {
+            if *offset == handle.offset && *cached == projection {
+                return Ok(cursor.clone());
+            }
+        }
+
+        let block_cache = Runtime::global().block_cache();
+        if !self.columnar {
+            if let Some(block) = block_cache.get(&self.io.file_path, handle) {
+                let cursor = BlockCursor::new(block, handle.entries, self.restart_interval);
+                self.last_block = Some((handle.offset, projection, cursor.clone()));
+                return Ok(cursor);
+            }
+        }
+
+        let bytes = self.read_at(handle.offset, handle.len).await?;
+        if let Err(err) = handle.verify(&bytes) {
+            error!(
+                "Checksum mismatch in file {}, block at {} with keys [{},{}]",
+                self.io.file_path.display(),
+                handle.offset,
+                handle.first_key,
+                handle.last_key
+            );
+            return Err(err);
+        }
+
+        let cursor = if self.columnar {
+            let (entries, hasher) =
+                decode_columnar(&bytes, handle.entries, self.compression, &projection).await?;
+            BlockCursor::decoded(entries, hasher)
+        } else {
+            let block = match self.dictionary().await? {
+                Some(dictionary) => dictionary.decompress(&bytes)?,
+                None => self.compression.decompress(&bytes).await?,
+            };
+            let block = Arc::new(block);
+            block_cache.insert(&self.io.file_path, handle, block.clone());
+            BlockCursor::new(block, handle.entries, self.restart_interval)
+        };
+
+        self.last_block = Some((handle.offset, projection, cursor.clone()));
+        Ok(cursor)
+    }
+
     #[inline]
     pub async fn clone_io(&self) -> Result<IOHandler> {
         self.io.clone().await
@@ -63,7 +275,14 @@ impl SSTableIter {
     pub async fn recreate(&mut self) -> Result<()> {
         let mut file_io = self.io.inner().await?;
 
-        if file_io.metadata().await?.len() < HEADER_SIZE {
+        self.footer = None;
+        self.index = None;
+        self.dictionary = None;
+        self.last_block = None;
+        self.block = None;
+
+        self.file_size = file_io.metadata().await?.len();
+        if self.file_size < LEGACY_HEADER_SIZE {
             trace!("Empty Iter          : {:?}", self.io.file_path);
             return Ok(());
         }
@@ -72,8 +291,25 @@ impl SSTableIter {
 
         let magic_number = file_io.read_u32().await?;
 
-        if magic_number != SSTABLE_MAGIC_NUMBER {
-            return Err(DbError::InvalidMagicNumber);
+        match magic_number {
+            SSTABLE_MAGIC_NUMBER => {
+                self.compression = Compression::from_u32(file_io.read_u32().await?)?;
+                self.restart_interval = file_io.read_u32().await?;
+                self.columnar = file_io.read_u32().await? != 0;
+                self.header_size = HEADER_SIZE;
+
+                if self.restart_interval == 0 {
+                    return Err(DbError::InvalidData);
+                }
+            }
+            // every key is a restart point in the files written by older versions
+            LEGACY_SSTABLE_MAGIC_NUMBER => {
+                self.compression = Compression::default();
+                self.restart_interval = 1;
+                self.columnar = false;
+                self.header_size = LEGACY_HEADER_SIZE;
+            }
+            _ => return Err(DbError::InvalidMagicNumber),
         }
 
         self.raw_checksum = file_io.read_u32().await?;
@@ -84,6 +320,16 @@ impl SSTableIter {
         self.min_key = file_io.read_u64().await?;
         self.max_key = file_io.read_u64().await?;
 
+        // the files written by older versions have no footer
+        if magic_number == SSTABLE_MAGIC_NUMBER && self.file_size >= HEADER_SIZE + FOOTER_SIZE {
+            let mut bytes = vec![0; FOOTER_SIZE as usize];
+            file_io
+                .seek(SeekFrom::Start(self.file_size - FOOTER_SIZE))
+                .await?;
+            file_io.read_exact(&mut bytes).await?;
+            self.footer = SSTableFooter::decode(&bytes).await?;
+        }
+
         trace!("Recreated Iter      : {:?}", self.io.file_path);
         Ok(())
     }
@@ -93,12 +339,10 @@ impl SSTableIter {
         self.hasher.replace(Hasher::new());
         self.last_entry_key = None;
         self.bytes_read = 0;
-        self.buf.clear();
+        self.block_cur = 0;
+        self.block = None;
 
-        let mut file = File::open(self.io.file_path.as_ref()).await?;
-        file.seek(SeekFrom::Start(HEADER_SIZE)).await?;
-        self.reader
-            .replace(CompressionDecoder::new(BufReader::new(file)));
+        self.index().await?;
 
         Ok(())
     }
@@ -112,30 +356,64 @@ impl SSTableIter {
         }
 
         trace!("Init Iter for key   : [{}]: {:?}", key, self.io.file_path);
-        self.init_iter().await
+        self.init_iter().await?;
+
+        // skip the blocks before the key, the checksum can't be verified then
+        let index = self.index().await?;
+        let pos = index.partition_point(|handle| handle.last_key < key);
+        if pos > 0 {
+            self.block_cur = pos;
+            self.entry_cur = index[..pos].iter().map(|handle| handle.entries).sum();
+            self.hasher = None;
+        }
+
+        Ok(())
     }
 
-    async fn fetch_more(&mut self) -> usize {
-        let mut buf = vec![0u8; self.buf.capacity() - self.buf.len()];
-        if let Some(reader) = self.reader.as_mut() {
-            if let Ok(len) = reader.read(&mut buf).await {
-                self.bytes_read += len;
-                if let Some(hasher) = self.hasher.as_mut() {
-                    hasher.update(&buf[..len]);
-                } else {
-                    warn!("Hasher is not initialized");
-                    self.hasher.replace(Hasher::new());
-                    self.hasher.as_mut().unwrap().update(&buf[..len]);
-                }
-                self.buf.extend(&buf[..len]);
-                len
-            } else {
-                0
+    /// Load the next block, return false if there is no more.
+    async fn next_block(&mut self) -> Result<bool> {
+        let index = self.index().await?;
+
+        let handle = match index.get(self.block_cur) {
+            Some(handle) => handle,
+            None => return Ok(false),
+        };
+        self.block_cur += 1;
+
+        let projection = self.projection.clone();
+        let cursor = self.block_cursor(handle, &projection).await?;
+        self.bytes_read += cursor.block().len();
+        cursor.update_hasher(&mut self.hasher);
+
+        self.block = Some(cursor);
+        Ok(true)
+    }
+
+    /// Verify the checksum of the raw data if the whole file is read.
+    fn finish(&mut self) -> Result<()> {
+        trace!(
+            "Decoded {} bytes ({}/{}) with checksum {:08x} from file {}",
+            self.bytes_read,
+            self.entry_cur,
+            self.entries_count,
+            self.raw_checksum,
+            self.io.file_path.display()
+        );
+
+        if let Some(hasher) = self.hasher.take() {
+            let hash = hasher.finalize();
+            if self.raw_checksum != hash {
+                error!(
+                    "Checksum mismatch in file {}, expected {:08x}, got {:08x}",
+                    self.io.file_path.display(),
+                    self.raw_checksum,
+                    hash
+                );
+                return Err(DbError::MissChecksum);
             }
-        } else {
-            warn!("Reader is not initialized");
-            0
         }
+
+        Ok(())
     }
 }
 
@@ -144,80 +422,45 @@ impl AsyncIterator<KvStore> for SSTableIter {
 
     fn next(&mut self) -> Self::NextFuture<'_> {
         async {
-            if self.entry_cur >= self.entries_count {
-                trace!(
-                    "Decoded {} bytes ({}/{}) with checksum {:08x} from file {}",
-                    self.bytes_read,
-                    self.entry_cur,
-                    self.entries_count,
-                    self.raw_checksum,
-                    self.io.file_path.display()
-                );
-                if let Some(hasher) = self.hasher.take() {
-                    let hash = hasher.finalize();
-                    if self.raw_checksum != hash {
-                        error!(
-                            "Checksum mismatch in file {}, expected {:08x}, got {:08x}",
-                            self.io.file_path.display(),
-                            self.raw_checksum,
-                            hash
-                        );
-                    }
-                } else {
-                    warn!(
-                        "No hasher found for file \"{}\"",
-                        self.io.file_path.display()
-                    );
-                }
-
-                return Ok(None);
-            }
-
-            let data_store = loop {
-                // if we have no data in buffer and no more data to fetch, return None
-                if self.fetch_more().await == 0 && self.buf.is_empty() {
-                    return Ok(None);
-                }
-
-                let slice = self.buf.make_contiguous();
-
-                match bincode::decode_from_slice::<KvStore, BincodeConfig>(slice, BIN_CODE_CONF) {
-                    Ok((data_store, offset)) => {
-                        trace!(
-                            "Decoded data        : [{}] -> [{}], {}",
-                            data_store.0,
-                            data_store.1,
-                            hex_view(&slice[..offset])
-                                .or_else(|_| Result::Ok("< cannot format >".to_string()))
-                                .unwrap()
-                        );
+            loop {
+                if let Some(cursor) = self.block.as_mut() {
+                    let pos = cursor.pos();
+
+                    match cursor.next_entry() {
+                        Ok(Some(data_store)) => {
+                            trace!(
+                                "Decoded data        : [{}] -> [{}], {}",
+                                data_store.0,
+                                data_store.1,
+                                hex_view(&cursor.block()[pos..cursor.pos()])
+                                    .or_else(|_| Result::Ok("< cannot format >".to_string()))
+                                    .unwrap()
+                            );
 
-                        self.entry_cur += 1;
-                        self.buf.drain(..offset);
-                        self.last_entry_key.replace(data_store.0);
+                            self.entry_cur += 1;
+                            self.last_entry_key.replace(data_store.0);
 
-                        break data_store;
-                    }
-                    Err(err) => match err {
-                        DecodeError::UnexpectedEnd { .. } => continue,
-                        _ => {
+                            return Ok(Some(data_store));
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
                             error!(
-                                "Error decoding data : {:#?} in file {}, entry {}, offset {}, {}",
+                                "Error decoding data : {:#?} in file {}, entry {}, block {}",
                                 err,
                                 self.io.file_path.display(),
                                 self.entry_cur,
-                                self.bytes_read,
-                                hex_view(slice)
-                                    .or_else(|_| Result::Ok("< cannot format >".to_string()))
-                                    .unwrap()
+                                self.block_cur - 1,
                             );
-                            return Ok(None);
+                            return Err(err);
                         }
-                    },
+                    }
                 }
-            };
 
-            Ok(Some(data_store))
+                if !self.next_block().await? {
+                    self.finish()?;
+                    return Ok(None);
+                }
+            }
         }
     }
 }
@@ -234,11 +477,13 @@ pub mod tests {
         let mut file = File::open(file_name).await?;
 
         let magic_number = file.read_u32().await?;
-
         if magic_number != SSTABLE_MAGIC_NUMBER {
             return Err(DbError::InvalidMagicNumber);
         }
 
+        let compression = Compression::from_u32(file.read_u32().await?)?;
+        let restart_interval = file.read_u32().await?;
+        let columnar = file.read_u32().await? != 0;
         let raw_checksum = file.read_u32().await?;
         let compressed_checksum = file.read_u32().await?;
 
@@ -249,33 +494,72 @@ pub mod tests {
         let max_key = file.read_u64().await?;
 
         let mut bytes = Vec::new();
-        let bytes_total = file.read_to_end(&mut bytes).await?;
+        file.read_to_end(&mut bytes).await?;
+
+        let footer = SSTableFooter::decode(&bytes).await?.unwrap();
+        let dictionary = (footer.dict_len > 0).then(|| {
+            let start = (footer.dict_offset - HEADER_SIZE) as usize;
+            ZstdDictionary::from_bytes(bytes[start..start + footer.dict_len as usize].to_vec())
+        });
+        let bytes_total = (footer.data_end() - HEADER_SIZE) as usize;
+        bytes.truncate(bytes_total);
 
         let mut hasher = Hasher::new();
         hasher.update(&bytes);
         let computed_compressed_checksum = hasher.finalize();
 
-        let mut raw = Vec::new();
-        CompressionDecoder::new(bytes.as_slice())
-            .read_to_end(&mut raw)
-            .await?;
+        let index: Vec<BlockHandle> = {
+            let mut file = File::open(file_name).await?;
+            let mut index_bytes = vec![0; footer.index_len as usize];
+            file.seek(SeekFrom::Start(footer.index_offset)).await?;
+            file.read_exact(&mut index_bytes).await?;
+            decode_index(&index_bytes).await?
+        };
 
+        let mut raw_size = 0;
         let mut hasher = Hasher::new();
-        hasher.update(&raw);
-        let computed_raw_checksum = hasher.finalize();
-
-        let mut bytes_read = 0;
-        for _ in 0..entries_count {
-            let (_, offset) = bincode::decode_from_slice::<KvStore, BincodeConfig>(
-                &raw[bytes_read..],
-                BIN_CODE_CONF,
-            )?;
-            bytes_read += offset;
+        let mut last_key = None;
+        for handle in index.iter() {
+            let start = (handle.offset - HEADER_SIZE) as usize;
+            let block = &bytes[start..start + handle.len as usize];
+            handle.verify(block)?;
+
+            let mut cursor: BlockCursor = if columnar {
+                let (entries, block_hasher) =
+                    decode_columnar(block, handle.entries, compression, &None).await?;
+                hasher.combine(&block_hasher.unwrap());
+                raw_size += entries
+                    .iter()
+                    .map(|(_, value)| match value {
+                        DataStore::Value(row) => row.len(),
+                        _ => 0,
+                    })
+                    .sum::<usize>();
+                BlockCursor::decoded(entries, None)
+            } else {
+                let block = match &dictionary {
+                    Some(dictionary) => dictionary.decompress(block)?,
+                    None => compression.decompress(block).await?,
+                };
+                hasher.update(&block);
+                raw_size += block.len();
+                BlockCursor::new(Arc::new(block), handle.entries, restart_interval)
+            };
+            while let Some((key, _)) = cursor.next_entry()? {
+                assert!(last_key < Some(key));
+                last_key = Some(key);
+            }
+            assert_eq!(cursor.index(), handle.entries);
+            assert_eq!(Some(handle.last_key), last_key);
         }
+        let computed_raw_checksum = hasher.finalize();
 
         assert_eq!(compressed_checksum, computed_compressed_checksum);
         assert_eq!(raw_checksum, computed_raw_checksum);
-        assert_eq!(bytes_read, raw.len());
+        assert_eq!(
+            entries_count,
+            index.iter().map(|handle| handle.entries).sum::<u32>()
+        );
 
         info!(
             "{} File {}, size ({}/{})",
@@ -284,15 +568,15 @@ pub mod tests {
             style(HumanBytes(bytes_total as u64).to_string())
                 .cyan()
                 .bold(),
-            style(HumanBytes(raw.len() as u64).to_string())
-                .cyan()
-                .bold()
+            style(HumanBytes(raw_size as u64).to_string()).cyan().bold()
         );
 
         info!(
-            "  with {} entries ({} deleted), key [{},{}], checksums {:08x}/{:08x}",
+            "  with {} entries ({} deleted) in {} blocks ({}), key [{},{}], checksums {:08x}/{:08x}",
             style(entries_count).cyan().bold(),
             style(deleted).cyan().bold(),
+            style(index.len()).cyan().bold(),
+            style(compression).cyan(),
             style(min_key).green().bold(),
             style(max_key).green().bold(),
             style(compressed_checksum).bold(),
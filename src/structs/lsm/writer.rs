@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use crate::utils::*;
+
+use super::*;
+
+/// The default size of the entries in a SSTable built by `SSTableWriter`.
+pub const WRITER_FILE_SIZE: usize = 64 << 20;
+
+/// Build sorted SSTables outside of a table, which are installed by
+/// `Table::ingest` without going through the memtable and the compaction.
+///
+/// The keys must be added in ascending order, a new file is started once
+/// the entries of the current one reach the file size.
+#[derive(Debug)]
+pub struct SSTableWriter {
+    factory: IOHandlerFactory,
+    options: SSTableOptions,
+    file_size: usize,
+    data: Vec<KvStore>,
+    bytes: usize,
+    last_key: Option<Key>,
+    files: Vec<PathBuf>,
+}
+
+impl SSTableWriter {
+    pub fn new(dir: impl AsRef<Path>, options: SSTableOptions) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            factory: IOHandlerFactory::new(dir.as_ref()),
+            options,
+            file_size: WRITER_FILE_SIZE,
+            data: Vec::new(),
+            bytes: 0,
+            last_key: None,
+            files: Vec::new(),
+        })
+    }
+
+    /// Set the size of the entries in a file before starting a new one.
+    #[inline]
+    pub fn with_file_size(mut self, bytes: usize) -> Self {
+        self.file_size = bytes.max(1);
+        self
+    }
+
+    #[inline]
+    pub async fn put(&mut self, key: Key, value: DataInner) -> Result<()> {
+        self.add((key, DataStore::Value(Arc::new(value)))).await
+    }
+
+    /// Add a tombstone, which hides the key in the older SSTables.
+    #[inline]
+    pub async fn delete(&mut self, key: Key) -> Result<()> {
+        self.add((key, DataStore::Deleted)).await
+    }
+
+    pub async fn add(&mut self, kvstore: KvStore) -> Result<()> {
+        if let Some(last_key) = self.last_key {
+            if kvstore.0 <= last_key {
+                return Err(DbError::Other(format!(
+                    "Key [{}] not greater than [{}]",
+                    kvstore.0, last_key
+                )));
+            }
+        }
+        self.last_key = Some(kvstore.0);
+
+        self.bytes += std::mem::size_of::<Key>()
+            + match &kvstore.1 {
+                DataStore::Value(value) => value.len(),
+                _ => 0,
+            };
+        self.data.push(kvstore);
+
+        if self.bytes >= self.file_size {
+            self.finish_file().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the remaining entries, and get the files in the key order.
+    pub async fn finish(mut self) -> Result<Vec<PathBuf>> {
+        self.finish_file().await?;
+        Ok(self.files)
+    }
+
+    async fn finish_file(&mut self) -> Result<()> {
+        if self.data.is_empty() {
+            return Ok(());
+        }
+
+        // the level is decided by `Table::ingest`
+        let mut key = SSTableKey::new(0u64);
+        while self.factory.file_path(key).exists() {
+            key = SSTableKey(key.0 - 1);
+        }
+
+        let mut sstable = SSTable::new(SSTableMeta::new(key), &self.factory).await?;
+        sstable
+            .archive(self.data.drain(..), self.options.clone())
+            .await?;
+        self.bytes = 0;
+
+        debug!(
+            "SSTable written     : {} entries [{},{}] to {}",
+            sstable.meta().entries_count,
+            sstable.meta().min_key,
+            sstable.meta().max_key,
+            sstable.file_name()
+        );
+        self.files.push(PathBuf::from(sstable.file_name()));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_works() -> Result<()> {
+        let test_dir = "helper/sstable_writer_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let mut writer =
+            SSTableWriter::new(test_dir, SSTableOptions::default())?.with_file_size(0x1000);
+        for i in 0..0x100u64 {
+            writer.put(i * 2, vec![i as u8; 0x20]).await?;
+        }
+        writer.delete(0x200).await?;
+        assert!(writer.put(0x200, vec![0; 0x20]).await.is_err());
+
+        let files = writer.finish().await?;
+        assert_eq!(files.len(), 3);
+
+        let mut last_key = None;
+        let mut entries = 0;
+        for file in files.iter() {
+            let iter = SSTableIter::new(IOHandler::new(file).await?).await?;
+            let (min_key, max_key) = iter.key_range();
+            assert!(last_key < Some(min_key));
+            last_key = Some(max_key);
+            entries += iter.entries_count();
+        }
+        assert_eq!(entries, 0x101);
+        assert_eq!(last_key, Some(0x200));
+
+        Ok(())
+    }
+}
@@ -1,23 +1,38 @@
 use async_trait::async_trait;
 use avl::AvlTreeMap;
-use std::{collections::VecDeque, io::SeekFrom, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, io::SeekFrom, path::PathBuf, sync::Arc};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
-use super::{kvstore::*, lsm::*, tracker::SSTableTracker, META_MAGIC_NUMBER};
-use crate::{structs::table::TableId, utils::*};
+use super::{
+    generation::Generations, kvstore::*, lsm::*, stall::StallUsage, tracker::SSTableTracker,
+    LEGACY_META_MAGIC_NUMBER, META_MAGIC_NUMBER,
+};
+use crate::{
+    core::{Options, TableOptions},
+    structs::table::TableId,
+    utils::*,
+};
 
 #[derive(Debug)]
 pub struct Manifest {
     io: IOHandler,
     tables: AvlTreeMap<SSTableKey, Arc<SSTable>>,
     tracker: SSTableTracker,
+    generations: Generations,
 
     cleanup_files: Vec<String>,
+    /// The checkpoints in progress, the files are not removed meanwhile.
+    pins: usize,
+    filter_counter: FilterCounter,
 
     pub factory: IOHandlerFactory,
     pub table_id: TableId,
     pub row_size: u32,
-    pub bloom_filter: BloomFilter,
+    /// The options overridden by the table.
+    pub options: TableOptions,
+    /// Options for new tables, only the column layout is persisted,
+    /// the others are set by `apply_options`.
+    pub sstable_options: SSTableOptions,
 }
 
 impl Manifest {
@@ -28,19 +43,44 @@ impl Manifest {
 
         debug!("Load Manifest       : {:?}", path);
 
-        Manifest::from_io(&io).await.or_else(|_| {
+        let table_id = TableId::new(table_name.to_str().unwrap());
+
+        let mut manifest = Manifest::from_io(&io).await.or_else(|_| {
             debug!("Create Manifest     : {:?}", path);
-            Ok(Self {
+            Result::Ok(Self {
                 io,
                 factory: IOHandlerFactory::new(&table_name),
-                table_id: TableId::new(table_name.to_str().unwrap()),
+                table_id,
                 row_size: 0,
+                options: TableOptions::default(),
+                sstable_options: SSTableOptions::default(),
                 cleanup_files: Vec::new(),
+                pins: 0,
                 tables: AvlTreeMap::new(),
-                bloom_filter: BloomFilter::new_global(),
+                filter_counter: FilterCounter::default(),
                 tracker: SSTableTracker::default(),
+                generations: Generations::default(),
             })
-        })
+        })?;
+
+        manifest.migrate_table_id(table_id).await?;
+
+        Ok(manifest)
+    }
+
+    /// Manifests written by older versions store an id computed by
+    /// the std `DefaultHasher`, rewrite it with the stable one.
+    async fn migrate_table_id(&mut self, table_id: TableId) -> Result<()> {
+        if self.table_id != table_id {
+            warn!(
+                "Migrate table id    : {:x} -> {:x}",
+                self.table_id, table_id
+            );
+            self.table_id = table_id;
+            self.to_self_io().await?;
+        }
+
+        Ok(())
     }
 
     #[inline]
@@ -50,22 +90,62 @@ impl Manifest {
         }
     }
 
+    /// Apply the options of the table to the new SSTables and the compaction.
+    pub fn apply_options(&mut self, options: &Options) {
+        self.sstable_options.range_filter = options.range_filter;
+        self.sstable_options.compression = options.compression;
+        self.sstable_options.zstd_dictionary = options.zstd_dictionary;
+        self.tracker.set_threshold(options.compact_threshold);
+    }
+
     #[inline]
     pub fn get_compactable_tables(&self) -> Vec<(SSTableLevel, SSTableList)> {
         self.tracker.get_compactable_tables()
     }
 
-    pub fn iter(&self) -> LsmTreeIterator {
-        let mut cache = VecDeque::with_capacity(self.tables.len());
+    /// Get the usage of L0 files and pending compaction bytes.
+    #[inline]
+    pub fn stall_usage(&self) -> StallUsage {
+        StallUsage {
+            memtable_bytes: 0,
+            l0_files: self.tracker.level_len(0),
+            pending_compaction_bytes: self.tracker.pending_compaction_bytes(),
+        }
+    }
 
-        for table in self.tables.values() {
-            cache.push_back(table.clone());
+    /// Get the lookup counters of the per-table bloom filters.
+    #[inline]
+    pub fn filter_stats(&self) -> FilterStats {
+        self.filter_counter.stats()
+    }
+
+    #[inline]
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Get the number of tables at every level.
+    pub fn level_counts(&self) -> BTreeMap<SSTableLevel, usize> {
+        let mut counts = BTreeMap::new();
+        for key in self.tables.keys() {
+            *counts.entry(key.level()).or_default() += 1;
         }
+        counts
+    }
 
-        LsmTreeIterator::new(cache)
+    pub fn iter(&self) -> LsmTreeIterator {
+        LsmTreeIterator::new(self.tables.values().cloned().collect())
+    }
+
+    /// Iterate the keys in `[start, end]`, skipping the tables
+    /// which are filtered out by their key range or range filter.
+    pub fn range_iter(&self, start: Key, end: Key) -> LsmTreeIterator {
+        LsmTreeIterator::with_range(self.tables.values().cloned().collect(), start, end)
     }
 
     pub async fn add_table(&mut self, table: SSTable) {
+        self.generations.add(table.meta().key);
+
         let table = Arc::new(table);
         self.tables.insert(table.meta().key, table.clone());
         self.tracker.push_back(table);
@@ -78,15 +158,166 @@ impl Manifest {
             .collect()
     }
 
+    /// Get all the tables, sorted by the key.
+    #[inline]
+    pub fn tables(&self) -> SSTableList {
+        self.tables.values().cloned().collect()
+    }
+
+    /// Get the tables which may contain the keys in `[start, end]`, with
+    /// the tables overlapping them, so no other table shares a key with
+    /// the result. The newer versions of the keys come first.
+    pub fn overlapping_tables(&self, start: Key, end: Key) -> SSTableList {
+        let mut range = (start, end);
+
+        loop {
+            let tables = self
+                .tables
+                .values()
+                .filter(|table| table.meta().min_key <= range.1 && table.meta().max_key >= range.0)
+                .cloned()
+                .collect::<SSTableList>();
+
+            let expanded = tables.iter().fold(range, |(start, end), table| {
+                (
+                    start.min(table.meta().min_key),
+                    end.max(table.meta().max_key),
+                )
+            });
+
+            if expanded == range {
+                return tables;
+            }
+            range = expanded;
+        }
+    }
+
+    /// Get the deepest level of the tables, at least L1.
+    #[inline]
+    pub fn bottom_level(&self) -> SSTableLevel {
+        self.tables
+            .keys()
+            .map(SSTableKey::level)
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    /// Get the deepest level for a new table with the keys in `[start, end]`,
+    /// above the tables overlapping it, so its keys take precedence. A new
+    /// table at L0 is newer than the others there.
+    pub fn ingest_level(&self, start: Key, end: Key) -> SSTableLevel {
+        self.tables
+            .values()
+            .filter(|table| table.meta().min_key <= end && table.meta().max_key >= start)
+            .map(|table| table.meta().key.level())
+            .min()
+            .map_or(self.bottom_level(), |level| level.saturating_sub(1))
+    }
+
+    /// Get the keys of the SSTable files in the directory of the table.
+    pub fn disk_tables(&self) -> Result<Vec<SSTableKey>> {
+        let mut keys = Vec::new();
+        for entry in std::fs::read_dir(self.factory.base_dir())? {
+            let file_name = entry?.file_name();
+            if let Some(key) = file_name.to_str().and_then(SSTableKey::from_file_name) {
+                keys.push(key);
+            }
+        }
+
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Get the SSTable files in the directory but neither in the manifest
+    /// nor waiting for the cleanup.
+    pub fn orphan_files(&self) -> Result<Vec<String>> {
+        let mut orphans = Vec::new();
+        for key in self.disk_tables()? {
+            let file_name = self.factory.file_path(key);
+            let file_name = file_name.to_str().unwrap();
+
+            if !self.tables.contains_key(&key) && !self.cleanup_files.iter().any(|f| f == file_name)
+            {
+                orphans.push(file_name.to_string());
+            }
+        }
+
+        Ok(orphans)
+    }
+
+    /// Replace all the tables, and save the manifest.
+    pub async fn replace_tables(&mut self, tables: SSTableList) -> Result<()> {
+        let removed = self
+            .tables
+            .values()
+            .filter(|table| !tables.iter().any(|other| Arc::ptr_eq(table, other)))
+            .map(|table| table.meta().key)
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            self.generations.remove(removed);
+        }
+        for table in tables.iter() {
+            if !self.tables.values().any(|other| Arc::ptr_eq(table, other)) {
+                self.generations.add(table.meta().key);
+            }
+        }
+
+        self.tables = AvlTreeMap::new();
+        for table in tables {
+            self.tables.insert(table.meta().key, table);
+        }
+
+        // the newer tables come first in the map, the tracker
+        // keeps the tables of a level from the oldest one
+        self.tracker.clear();
+        for table in self.tables.values() {
+            self.tracker.push_front(table.clone());
+        }
+
+        self.to_self_io().await
+    }
+
     pub fn pop_tables(&mut self, tables: &SSTableList) {
+        self.generations
+            .remove(tables.iter().map(|table| table.meta().key));
+
         for table in tables {
             self.tables.remove(&table.meta().key);
-            self.tracker.pop_front(table.meta().key.level());
+            self.tracker.remove(table);
             self.cleanup_files.push(table.file_name().to_string());
         }
     }
 
+    /// Get the generation of the tables, bumped on every change.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generations.current()
+    }
+
+    /// Get the tables added and removed since the generation of a backup.
+    #[inline]
+    pub fn changes_since(&self, generation: u64) -> Result<(Vec<SSTableKey>, Vec<SSTableKey>)> {
+        self.generations.changes_since(generation)
+    }
+
+    /// Keep the files of the compacted tables until `unpin`.
+    #[inline]
+    pub fn pin(&mut self) {
+        self.pins += 1;
+    }
+
+    #[inline]
+    pub fn unpin(&mut self) {
+        self.pins -= 1;
+    }
+
     pub fn do_cleanup(&mut self) {
+        if self.pins > 0 {
+            trace!("Cleanup deferred    : {} pins", self.pins);
+            return;
+        }
+
         if !self.cleanup_files.is_empty() {
             trace!("Cleanup {} files...", self.cleanup_files.len());
             for file in self.cleanup_files.drain(..) {
@@ -112,12 +343,17 @@ impl AsyncKvStoreRead for Manifest {
                 table.meta().key
             );
 
-            if !table.meta().bloom_filter.contains(key) {
+            if !table.may_contain(key).await? {
                 trace!("Key not found in table {:?}: [{:?}]", table.meta().key, key);
+                self.filter_counter.record_useful();
                 continue;
             }
 
-            return match table.get(key).await? {
+            let ret = table.get(key).await?;
+            self.filter_counter
+                .record_positive(!matches!(ret, DataStore::NotFound));
+
+            return match ret {
                 DataStore::Value(block) => Ok(DataStore::Value(block)),
                 DataStore::Deleted => Ok(DataStore::Deleted),
                 DataStore::NotFound => continue,
@@ -151,8 +387,9 @@ impl SizedOnDisk for Manifest {
 impl AsyncToIO for Manifest {
     /// write the manifest's data to disk
     ///
-    /// the order is `magic_number`, `table_id`,
-    /// `row_size`, `global_add_bloom_filter`, `global_del_bloom_filter`, `tables`
+    /// the order is `magic_number`, `table_id`, `row_size`, `columns`, `generations`,
+    /// `options`, `tables`,
+    /// the filters of every table are in the footer of its file
     async fn to_io(&self, io: &IOHandler) -> Result<()> {
         let mut io = io.inner().await?;
         io.seek(SeekFrom::Start(0)).await?;
@@ -161,24 +398,25 @@ impl AsyncToIO for Manifest {
         io.write_u64(self.table_id.0).await?;
         io.write_u32(self.row_size).await?;
 
-        let bloom_filter_bytes = {
-            let mut writer = CompressionEncoder::with_quality(Vec::new(), COMPRESSION_LEVEL);
-            writer
-                .write_all(&bincode::encode_to_vec(&self.bloom_filter, BIN_CODE_CONF)?)
-                .await?;
-            writer.shutdown().await?;
-            writer.into_inner()
-        };
+        let columns = self.sstable_options.columns.as_deref();
+        let bytes = bincode::encode_to_vec(columns, BIN_CODE_CONF)?;
+        io.write_u32(bytes.len() as u32).await?;
+        io.write_all(&bytes).await?;
 
-        io.write_u32(bloom_filter_bytes.len() as u32).await?;
-        io.write_all(&bloom_filter_bytes).await?;
+        let bytes = bincode::encode_to_vec(&self.generations, BIN_CODE_CONF)?;
+        io.write_u32(bytes.len() as u32).await?;
+        io.write_all(&bytes).await?;
+
+        let bytes = bincode::encode_to_vec(&self.options, BIN_CODE_CONF)?;
+        io.write_u32(bytes.len() as u32).await?;
+        io.write_all(&bytes).await?;
 
         for (key, table) in self.tables.iter() {
             io.write_u64(key.0).await?;
             // write the meta only
             let meta = table.meta();
             let bytes = {
-                let mut writer = CompressionEncoder::with_quality(Vec::new(), COMPRESSION_LEVEL);
+                let mut writer = CompressionEncoder::with_quality(Vec::new(), compression_level());
                 writer
                     .write_all(&bincode::encode_to_vec(meta, BIN_CODE_CONF)?)
                     .await?;
@@ -190,6 +428,11 @@ impl AsyncToIO for Manifest {
         }
 
         io.flush().await?;
+
+        // the manifest may be smaller than the last one
+        let len = io.stream_position().await?;
+        io.set_len(len).await?;
+
         Ok(())
     }
 }
@@ -204,24 +447,43 @@ impl AsyncFromIO for Manifest {
         file_io.seek(SeekFrom::Start(0)).await?;
 
         let magic_number = file_io.read_u32().await?;
-        if magic_number != META_MAGIC_NUMBER {
+        if ![META_MAGIC_NUMBER, LEGACY_META_MAGIC_NUMBER].contains(&magic_number) {
             return Err(DbError::InvalidMagicNumber);
         }
+        let legacy = magic_number == LEGACY_META_MAGIC_NUMBER;
 
         let table_id = TableId(file_io.read_u64().await?);
         let row_size = file_io.read_u32().await?;
 
-        let bloom_filter: BloomFilter = {
+        let mut sstable_options = SSTableOptions::default();
+        let mut generations = None;
+        let mut options = TableOptions::default();
+        if legacy {
+            // skip the global bloom filter, it is dropped on the next save
             let filter_size = file_io.read_u32().await?;
-            let mut bytes = vec![0; filter_size as usize];
+            file_io.seek(SeekFrom::Current(filter_size as i64)).await?;
+            warn!("Drop global filter  : {} bytes", filter_size);
+        } else {
+            let size = file_io.read_u32().await?;
+            let mut bytes = vec![0; size as usize];
+            file_io.read_exact(&mut bytes).await?;
+
+            let columns: Option<ColumnLayout> =
+                bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0;
+            sstable_options.columns = columns.map(Arc::new);
+
+            let size = file_io.read_u32().await?;
+            let mut bytes = vec![0; size as usize];
             file_io.read_exact(&mut bytes).await?;
 
-            let mut reader = CompressionDecoder::new(bytes.as_slice());
-            let mut bytes = Vec::new();
-            reader.read_to_end(&mut bytes).await?;
+            generations = Some(bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0);
+
+            let size = file_io.read_u32().await?;
+            let mut bytes = vec![0; size as usize];
+            file_io.read_exact(&mut bytes).await?;
 
-            bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0
-        };
+            options = bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0;
+        }
 
         let mut tables = AvlTreeMap::new();
 
@@ -234,34 +496,50 @@ impl AsyncFromIO for Manifest {
 
             let size = file_io.read_u32().await?;
 
-            let meta = {
-                let mut bytes = vec![0; size as usize];
-                file_io.read_exact(&mut bytes).await?;
-
-                let mut reader = CompressionDecoder::new(bytes.as_slice());
-                let mut bytes = Vec::new();
-                reader.read_to_end(&mut bytes).await?;
-
-                bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0
+            let mut bytes = vec![0; size as usize];
+            file_io.read_exact(&mut bytes).await?;
+            let bytes = decompress(&bytes).await?;
+
+            // older versions store the bloom filter in the manifest
+            let (meta, filters) = match legacy {
+                true => {
+                    let meta: LegacySSTableMeta =
+                        bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0;
+                    let (meta, filters) = meta.into_parts();
+                    (meta, Some(filters))
+                }
+                false => (bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0, None),
             };
-            let table = SSTable::new(meta, &factory, row_size).await?;
+
+            let table = SSTable::new(meta, &factory).await?;
+            if let Some(filters) = filters {
+                table.preload_filters(filters);
+            }
             tables.insert(key, Arc::new(table));
         }
 
         let mut tracker = SSTableTracker::default();
         for table in tables.values() {
-            tracker.push_back(table.clone());
+            tracker.push_front(table.clone());
         }
 
+        // the changes before an upgrade are unknown
+        let generations =
+            generations.unwrap_or_else(|| Generations::legacy(tables.keys().copied()));
+
         Ok(Self {
             io: io.clone().await?,
             tracker,
+            generations,
             table_id,
             row_size,
+            options,
+            sstable_options,
             tables,
             factory,
-            bloom_filter,
+            filter_counter: FilterCounter::default(),
             cleanup_files: Vec::new(),
+            pins: 0,
         })
     }
 }
@@ -303,13 +581,83 @@ mod tests {
 
                 manifest.tables.insert(
                     meta.key,
-                    Arc::new(SSTable::new(meta, &manifest.factory, row_size).await?),
+                    Arc::new(SSTable::new(meta, &manifest.factory).await?),
                 );
             }
 
             assert_eq!(manifest.tables.len(), 7);
+
+            manifest.sstable_options.columns =
+                Some(Arc::new(ColumnLayout::new(row_size, &[1, 5])?));
+            manifest.options.compression = Some(Compression::Lz4);
+            manifest.to_self_io().await?;
+        }
+
+        let manifest = Manifest::new(test_dir).await?;
+        assert_eq!(manifest.table_count(), 7);
+        assert_eq!(manifest.options.compression, Some(Compression::Lz4));
+        assert_eq!(
+            manifest.sstable_options.columns.as_deref(),
+            Some(&ColumnLayout::new(10, &[1, 5])?)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn legacy_works() -> Result<()> {
+        crate::utils::logger::init();
+
+        let test_dir = "helper/manifest_legacy_test";
+
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let table_id = TableId::new(test_dir);
+        let key = SSTableKey::new(1u32);
+
+        {
+            // an old manifest with a global bloom filter before the tables
+            let mut file = std::fs::File::create(PathBuf::from(test_dir).join(".meta"))?;
+            let meta = LegacySSTableMeta {
+                key,
+                entries_count: 0,
+                bloom_filter: BloomFilter::new(0x80),
+            };
+            let meta = bincode::encode_to_vec(meta, BIN_CODE_CONF)?;
+            let meta = {
+                let mut writer = CompressionEncoder::with_quality(Vec::new(), compression_level());
+                writer.write_all(&meta).await?;
+                writer.shutdown().await?;
+                writer.into_inner()
+            };
+
+            use std::io::Write;
+            file.write_all(&LEGACY_META_MAGIC_NUMBER.to_be_bytes())?;
+            file.write_all(&table_id.0.to_be_bytes())?;
+            file.write_all(&10u32.to_be_bytes())?;
+            file.write_all(&4u32.to_be_bytes())?;
+            file.write_all(&[0xff; 4])?;
+            file.write_all(&key.0.to_be_bytes())?;
+            file.write_all(&(meta.len() as u32).to_be_bytes())?;
+            file.write_all(&meta)?;
         }
 
+        {
+            let manifest = Manifest::new(test_dir).await?;
+            assert_eq!(manifest.row_size, 10);
+            assert_eq!(manifest.table_count(), 1);
+            // the changes before are unknown
+            assert_eq!(manifest.generation(), 1);
+            assert!(manifest.changes_since(0).is_err());
+            manifest.to_self_io().await?;
+        }
+
+        let manifest = Manifest::new(test_dir).await?;
+        assert_eq!(manifest.table_count(), 1);
+        assert!(manifest.tables.contains_key(&key));
+        assert_eq!(manifest.changes_since(1)?, (Vec::new(), Vec::new()));
+
         Ok(())
     }
 }
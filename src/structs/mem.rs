@@ -1,34 +1,77 @@
 use async_trait::async_trait;
 use crc32fast::Hasher;
+use futures::Future;
 use std::collections::btree_map::*;
+use std::collections::VecDeque;
 use std::io::SeekFrom;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio::time::Instant;
 
+use crossbeam_skiplist::SkipMap;
+
 use tokio::sync::RwLock;
 
 use crate::structs::CACHE_MAGIC_NUMBER;
 use crate::utils::*;
 
+use super::kvstore::*;
 use super::lsm::*;
 use super::manifest::Manifest;
-use super::{kvstore::*, MEM_BLOCK_NUM};
+use super::wal::*;
+use crate::core::{Counter, Runtime};
 
 pub type MemStore = BTreeMap<Key, DataStore>;
 pub type MemTableIterator = IntoIter<Key, DataStore>;
 
+/// A lock-free skiplist, allows concurrent writers.
+pub type ConcurrentMemStore = SkipMap<Key, DataStore>;
+
+/// A memtable which is full and waiting to be persisted.
+#[derive(Debug)]
+pub struct ImmutableMemTable {
+    map: ConcurrentMemStore,
+    bytes: usize,
+    /// The key of its L0 SSTable, taken when it is frozen, so the
+    /// older memtables always have the older keys.
+    key: SSTableKey,
+}
+
+/// The memtable of a table.
+///
+/// Writers insert into the mutable skiplist concurrently, and only hold
+/// the read side of `mut_map`, which is locked exclusively when the
+/// skiplist is frozen into an immutable memtable. It is a blocking lock,
+/// as it is never held across an await point.
+///
+/// The writes are logged to the WAL by the sync mode of the options, and
+/// replayed over the cache file when the memtable is loaded.
 #[derive(Debug)]
 pub struct MemTable {
-    mut_map: Arc<RwLock<MemStore>>,
-    lock_map: Arc<RwLock<MemStore>>,
+    mut_map: std::sync::RwLock<ConcurrentMemStore>,
+    immutables: Arc<RwLock<VecDeque<Arc<ImmutableMemTable>>>>,
     manifest: Option<Arc<RwLock<Manifest>>>,
-    lock_map_released: Arc<AtomicBool>,
+    mut_bytes: AtomicUsize,
+    immutable_bytes: Arc<AtomicUsize>,
+    /// The key of the last frozen memtable.
+    last_key: AtomicU64,
+    flush_size: AtomicUsize,
+    wal: Arc<Wal>,
     io: IOHandler,
 }
 
+/// Approximate memory used by an entry of the memtable.
+#[inline]
+fn entry_size(value: &DataStore) -> usize {
+    std::mem::size_of::<Key>()
+        + match value {
+            DataStore::Value(value) => value.len(),
+            _ => 0,
+        }
+}
+
 impl MemTable {
     pub async fn new(
         table_name: impl Into<PathBuf>,
@@ -39,124 +82,280 @@ impl MemTable {
 
         debug!("Load MemTable       : {:?}", path);
 
-        MemTable::from_io(&io)
-            .await
-            .or_else(|_| {
-                debug!("Create MemTable     : {:?}", path);
-                Ok(Self {
-                    manifest: None,
-                    lock_map_released: Arc::new(AtomicBool::new(true)),
-                    mut_map: Arc::new(RwLock::new(BTreeMap::new())),
-                    lock_map: Arc::new(RwLock::new(BTreeMap::new())),
-                    io,
-                })
-            })
-            .map(|mut mem_table| {
-                mem_table.manifest = manifest;
-                mem_table
-            })
+        let mut mem_table = MemTable::from_io(&io).await.unwrap_or_else(|_| {
+            debug!("Create MemTable     : {:?}", path);
+            Self::with_map(io, BTreeMap::new())
+        });
+        mem_table.manifest = manifest;
+
+        // the writes not saved to the cache when the server stopped
+        let mut replayed = MemStore::new();
+        let count = mem_table.wal.replay(&mut replayed)?;
+        if count > 0 {
+            info!("Replay WAL          : {} entries to {:?}", count, path);
+
+            for (key, value) in replayed {
+                mem_table.insert(key, value).await;
+            }
+            mem_table.to_self_io().await?;
+        }
+
+        Ok(mem_table)
+    }
+
+    fn with_map(io: IOHandler, mut_map: MemStore) -> Self {
+        let mut_bytes = mut_map.values().map(entry_size).sum();
+        let wal = Wal::new(io.file_path.parent().unwrap());
+
+        Self {
+            wal: Arc::new(wal),
+            io,
+            manifest: None,
+            mut_map: std::sync::RwLock::new(mut_map.into_iter().collect()),
+            immutables: Arc::new(RwLock::new(VecDeque::new())),
+            mut_bytes: AtomicUsize::new(mut_bytes),
+            immutable_bytes: Arc::new(AtomicUsize::new(0)),
+            last_key: AtomicU64::new(u64::MAX),
+            flush_size: AtomicUsize::new(Runtime::global().options().memtable_size),
+        }
+    }
+
+    /// Set the size in bytes of the memtable before it is flushed.
+    #[inline]
+    pub fn set_flush_size(&self, bytes: usize) {
+        self.flush_size.store(bytes, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn set_wal_sync_mode(&self, mode: WalSyncMode) {
+        self.wal.set_sync_mode(mode);
+    }
+
+    #[inline]
+    pub fn flush_size(&self) -> usize {
+        self.flush_size.load(Ordering::Relaxed)
+    }
+
+    /// Get the bytes used by the memtable, including the immutable
+    /// ones which are not persisted yet.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.mut_bytes.load(Ordering::Relaxed) + self.immutable_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of immutable memtables waiting to be persisted.
+    #[inline]
+    pub async fn immutable_count(&self) -> usize {
+        self.immutables.read().await.len()
+    }
+
+    /// Make the mutable memtable immutable if it has at least `min_bytes`,
+    /// writes will go to a new memtable without waiting for the flush.
+    async fn freeze(&self, min_bytes: usize) -> Option<Arc<ImmutableMemTable>> {
+        // lock the queue first, so readers never miss the frozen data
+        let mut immutables = self.immutables.write().await;
+        let mut mut_map = self.mut_map.write().unwrap();
+
+        if mut_map.is_empty() || self.mut_bytes.load(Ordering::Relaxed) < min_bytes {
+            return None;
+        }
+
+        // the keys of the newer tables are smaller, even in the same microsecond
+        let key = SSTableKey::new(0u64)
+            .0
+            .min(self.last_key.load(Ordering::Relaxed) - 1);
+        self.last_key.store(key, Ordering::Relaxed);
+
+        let immutable = Arc::new(ImmutableMemTable {
+            map: std::mem::replace(&mut *mut_map, SkipMap::new()),
+            bytes: self.mut_bytes.swap(0, Ordering::Relaxed),
+            key: SSTableKey(key),
+        });
+        // the writes are stopped by the lock
+        if let Err(err) = self.wal.rotate(immutable.key) {
+            error!("Rotate WAL failed   : {:?} {:?}", self.io.file_path, err);
+        }
+        drop(mut_map);
+
+        immutables.push_back(immutable.clone());
+        drop(immutables);
+        self.immutable_bytes
+            .fetch_add(immutable.bytes, Ordering::Relaxed);
+
+        Some(immutable)
     }
 
     pub async fn swap(&self) {
-        let mut mut_map = self.mut_map.write().await;
-        let mut lock_map = self.lock_map.write().await;
+        self.freeze(0).await;
+    }
+
+    /// Freeze the memtable if it is large enough, and return the job
+    /// to persist it, which should be run by the scheduler.
+    pub async fn do_persist(&self) -> Option<impl Future<Output = Result<()>> + Send + 'static> {
+        if self.mut_bytes.load(Ordering::Relaxed) < self.flush_size() {
+            return None;
+        }
 
-        self.lock_map_released
-            .store(false, std::sync::atomic::Ordering::Relaxed);
-        std::mem::swap(&mut *mut_map, &mut *lock_map);
+        self.freeze(self.flush_size()).await?;
+        Some(self.persist_job())
     }
 
-    pub async fn do_persist(&self, new_table_added: Arc<AtomicBool>) {
-        if self.mut_map.read().await.len() >= MEM_BLOCK_NUM
-            && self
-                .lock_map_released
-                .load(std::sync::atomic::Ordering::Relaxed)
-        {
-            self.swap().await;
+    /// Freeze the memtable whatever its size, and get the key of the newest
+    /// immutable memtable, `None` if there is nothing to persist.
+    pub async fn force_freeze(&self) -> Option<SSTableKey> {
+        self.freeze(0).await;
+        self.immutables
+            .read()
+            .await
+            .back()
+            .map(|immutable| immutable.key)
+    }
+
+    /// Check if the memtable frozen under the key is not persisted yet.
+    pub async fn is_frozen(&self, key: SSTableKey) -> bool {
+        self.immutables
+            .read()
+            .await
+            .iter()
+            .any(|immutable| immutable.key == key)
+    }
 
-            let locked_map = self.lock_map.clone();
-            let manifest = self.manifest.clone().expect("Manifest is not set");
-            let lock_map_released = self.lock_map_released.clone();
+    /// Get the job to persist the immutable memtables, the oldest first.
+    ///
+    /// A memtable failed to persist is kept at the front of the queue, still
+    /// readable, and retried by the next job. The jobs of a table must not
+    /// run at the same time, see `JobKind::Flush`.
+    pub fn persist_job(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let immutables = self.immutables.clone();
+        let immutable_bytes = self.immutable_bytes.clone();
+        let manifest = self.manifest.clone().expect("Manifest is not set");
+        let wal = self.wal.clone();
+
+        async move {
+            loop {
+                let Some(immutable) = immutables.read().await.front().cloned() else {
+                    return Ok(());
+                };
 
-            crate::core::runtime::spawn(async move {
                 let start = Instant::now();
-                Self::persist(locked_map, lock_map_released, manifest)
+                Self::persist(&immutable, manifest.clone()).await?;
+
+                // the data is visible in the manifest now
+                immutables
+                    .write()
                     .await
-                    .unwrap();
-                new_table_added.store(true, std::sync::atomic::Ordering::Relaxed);
+                    .retain(|table| !Arc::ptr_eq(table, &immutable));
+                immutable_bytes.fetch_sub(immutable.bytes, Ordering::Relaxed);
+                if let Err(err) = wal.remove_segment(immutable.key) {
+                    error!("Remove WAL failed   : {:?} {:?}", immutable.key, err);
+                }
+
                 trace!("Persist to disk in {:?}", start.elapsed());
-            });
+            }
         }
     }
 
-    async fn persist(
-        locked_map: Arc<RwLock<MemStore>>,
-        lock_map_released: Arc<AtomicBool>,
-        manifest: Arc<RwLock<Manifest>>,
-    ) -> Result<()> {
-        // 1. calculate the checksum and bloom filter of the SSTable
-        // 2. create the L0 SSTable
-        // 3. write the magic number to the SSTable
-        // 4. write the checksum to the SSTable
-        // 5. write the compressed data to the SSTable
-        // 6. write the meta data to the manifest
-
-        let key = SSTableKey::new(0u64);
-        let mut meta = SSTableMeta::new(key);
-
-        let data: BTreeMap<Key, DataStore> = locked_map
-            .read()
-            .await
-            .iter()
-            .map(|(k, v)| {
-                meta.bloom_filter.insert(k);
-                (*k, v.clone())
-            })
-            .collect();
+    async fn persist(immutable: &ImmutableMemTable, manifest: Arc<RwLock<Manifest>>) -> Result<()> {
+        // 1. create the L0 SSTable
+        // 2. write the compressed data blocks to the SSTable
+        // 3. write the filters and the block index to the footer
+        // 4. write the meta data to the manifest
 
-        meta.set_entries_count(data.len());
+        let data = &immutable.map;
 
         let gurad_manifest = manifest.read().await;
-        let sstable = SSTable::new(meta, &gurad_manifest.factory, gurad_manifest.row_size).await?;
-        sstable.archive(&data).await?;
+        // the file left by a failed try
+        std::fs::remove_file(gurad_manifest.factory.file_path(immutable.key)).ok();
+        let mut sstable =
+            SSTable::new(SSTableMeta::new(immutable.key), &gurad_manifest.factory).await?;
+        sstable
+            .archive(
+                data.iter()
+                    .map(|entry| (*entry.key(), entry.value().clone())),
+                // the dictionary and the columns are for the compacted tables
+                SSTableOptions {
+                    zstd_dictionary: false,
+                    columns: None,
+                    ..gurad_manifest.sstable_options.clone()
+                },
+            )
+            .await?;
         drop(gurad_manifest);
 
-        manifest.write().await.add_table(sstable).await;
+        let statistics = Runtime::global().statistics();
+        statistics.incr(Counter::Flushes);
+        statistics.add(Counter::BytesFlushed, sstable.file_size());
 
-        locked_map.write().await.clear();
-        lock_map_released.store(true, std::sync::atomic::Ordering::Relaxed);
+        manifest.write().await.add_table(sstable).await;
 
         Ok(())
     }
 
-    pub async fn iter(&self) -> MemTableIterator {
-        let mut new_map = self.lock_map.read().await.clone();
+    /// Merge the immutable memtables and the mutable one.
+    async fn merged(&self) -> MemStore {
+        let mut new_map = MemStore::new();
+
+        for immutable in self.immutables.read().await.iter() {
+            for entry in immutable.map.iter() {
+                new_map.insert(*entry.key(), entry.value().clone());
+            }
+        }
 
-        for (k, v) in self.mut_map.read().await.iter() {
-            new_map.insert(*k, v.clone());
+        for entry in self.mut_map.read().unwrap().iter() {
+            new_map.insert(*entry.key(), entry.value().clone());
         }
 
-        new_map.into_iter()
+        new_map
+    }
+
+    pub async fn iter(&self) -> MemTableIterator {
+        self.merged().await.into_iter()
+    }
+
+    /// Iterate the keys in `[start, end]`.
+    pub async fn range_iter(&self, start: Key, end: Key) -> MemTableIterator {
+        let mut map = self.merged().await;
+        map.retain(|key, _| (start..=end).contains(key));
+        map.into_iter()
     }
 
+    /// Save the memtable to its cache file, and remove the WAL, which must
+    /// not be written at the same time.
     pub async fn to_self_io(&self) -> Result<()> {
-        self.to_io(&self.io).await
+        self.to_io(&self.io).await?;
+        self.wal.clear()
+    }
+
+    async fn insert(&self, key: Key, value: DataStore) {
+        // keep the read lock while inserting, in case of freezing.
+        // overwritten entries are also counted, as the skiplist
+        // does not tell us the old value without another lookup.
+        let mut_map = self.mut_map.read().unwrap();
+
+        self.mut_bytes
+            .fetch_add(entry_size(&value), Ordering::Relaxed);
+        self.wal.write(key, value, |value| {
+            mut_map.insert(key, value);
+        });
     }
 }
 
 #[async_trait]
 impl AsyncKvStoreRead for MemTable {
     async fn get(&self, key: Key) -> Result<DataStore> {
-        if let Some(value) = self.mut_map.read().await.get(&key) {
-            trace!("Get value: [{}] -> [{}]", key, value);
+        if let Some(entry) = self.mut_map.read().unwrap().get(&key) {
+            trace!("Get value: [{}] -> [{}]", key, entry.value());
 
-            return Ok(value.clone());
+            return Ok(entry.value().clone());
         }
 
-        if let Some(value) = self.lock_map.read().await.get(&key) {
-            trace!("Get value: [{}] -> [{}]", key, value);
+        // the newer immutable memtables are at the back
+        for immutable in self.immutables.read().await.iter().rev() {
+            if let Some(entry) = immutable.map.get(&key) {
+                trace!("Get value: [{}] -> [{}]", key, entry.value());
 
-            return Ok(value.clone());
+                return Ok(entry.value().clone());
+            }
         }
 
         trace!("Key not found in MemTable: [{:?}]", key);
@@ -165,28 +364,27 @@ impl AsyncKvStoreRead for MemTable {
     }
 
     async fn len(&self) -> usize {
-        let lock_map_len = self.lock_map.read().await.len();
-        let mut_map_len = self.mut_map.read().await.len();
+        let immutables_len: usize = self
+            .immutables
+            .read()
+            .await
+            .iter()
+            .map(|table| table.map.len())
+            .sum();
+        let mut_map_len = self.mut_map.read().unwrap().len();
 
-        lock_map_len + mut_map_len
+        immutables_len + mut_map_len
     }
 }
 
 #[async_trait]
 impl AsyncKvStoreWrite for MemTable {
     async fn set(&self, key: Key, value: DataInner) {
-        self.mut_map
-            .write()
-            .await
-            .insert(key, DataStore::Value(Arc::new(value)));
-
-        if let Some(manifest) = &self.manifest {
-            manifest.write().await.bloom_filter.insert(key);
-        }
+        self.insert(key, DataStore::Value(Arc::new(value))).await;
     }
 
     async fn delete(&self, key: Key) {
-        self.mut_map.write().await.insert(key, DataStore::Deleted);
+        self.insert(key, DataStore::Deleted).await;
     }
 }
 
@@ -233,30 +431,17 @@ impl AsyncFromIO for MemTable {
             bincode::decode_from_slice(&bytes, BIN_CODE_CONF)?.0
         };
 
-        Ok(Self {
-            io: io.clone().await?,
-            manifest: None,
-            lock_map_released: Arc::new(AtomicBool::new(true)),
-            mut_map: Arc::new(RwLock::new(mut_map)),
-            lock_map: Arc::new(RwLock::new(BTreeMap::new())),
-        })
+        Ok(Self::with_map(io.clone().await?, mut_map))
     }
 }
 
 #[async_trait]
 impl AsyncToIO for MemTable {
     async fn to_io(&self, io: &IOHandler) -> Result<()> {
-        let mut_map = self.mut_map.read().await;
-        let lock_map = self.lock_map.read().await;
-
-        let mut cache_map = lock_map.clone();
-
-        for (key, value) in mut_map.iter() {
-            cache_map.insert(*key, value.clone());
-        }
+        let cache_map = self.merged().await;
 
         let bytes = {
-            let mut writer = CompressionEncoder::with_quality(Vec::new(), COMPRESSION_LEVEL);
+            let mut writer = CompressionEncoder::with_quality(Vec::new(), compression_level());
             writer
                 .write_all(&bincode::encode_to_vec(cache_map, BIN_CODE_CONF)?)
                 .await?;
@@ -278,6 +463,10 @@ impl AsyncToIO for MemTable {
         io.write_all(&bytes).await?;
         io.flush().await?;
 
+        // the cache may be smaller than the last one
+        let len = io.stream_position().await?;
+        io.set_len(len).await?;
+
         Ok(())
     }
 }
@@ -344,6 +533,47 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn immutables_work() -> Result<()> {
+        let test_dir = "helper/memtable_immutables_test";
+
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let mem = MemTable::new(test_dir, None).await?;
+        mem.set_flush_size(16);
+
+        mem.set(1, vec![1; 8]).await;
+        let older = mem.freeze(mem.flush_size()).await.unwrap();
+
+        mem.set(1, vec![2; 8]).await;
+        mem.set(2, vec![3; 8]).await;
+        let newer = mem.freeze(mem.flush_size()).await.unwrap();
+
+        // the newer L0 tables have the smaller keys
+        assert!(newer.key < older.key);
+
+        mem.delete(2).await;
+        assert!(mem.freeze(mem.flush_size()).await.is_none());
+
+        assert_eq!(mem.immutable_count().await, 2);
+        assert_eq!(mem.bytes(), 16 + 32 + 8);
+
+        assert_eq!(mem.get(1).await?, DataStore::Value(Arc::new(vec![2; 8])));
+        assert_eq!(mem.get(2).await?, DataStore::Deleted);
+
+        let merged = mem.iter().await.collect::<Vec<_>>();
+        assert_eq!(
+            merged,
+            vec![
+                (1, DataStore::Value(Arc::new(vec![2; 8]))),
+                (2, DataStore::Deleted)
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn decode_works() -> Result<()> {
         crate::utils::logger::init();
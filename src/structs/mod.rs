@@ -1,17 +1,32 @@
+pub mod bulk;
+pub mod generation;
 pub mod kvstore;
 pub mod lsm;
 pub mod manifest;
 pub mod mem;
+pub mod stall;
 pub mod table;
 pub mod tracker;
+pub mod wal;
 
+pub use bulk::*;
+pub use generation::*;
 pub use kvstore::*;
 pub use mem::*;
+pub use stall::*;
 pub use table::*;
+pub use wal::*;
 
-pub const META_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYMT");
+pub const META_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYM2");
+/// The manifest with a global bloom filter, written by older versions.
+pub const LEGACY_META_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYMT");
 pub const CACHE_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYCA");
-pub const SSTABLE_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYST");
+pub const SSTABLE_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYS2");
+/// The SSTable with the data in one compressed stream and without a footer,
+/// written by older versions.
+pub const LEGACY_SSTABLE_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYST");
+pub const SSTABLE_FOOTER_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYSF");
+pub const WAL_MAGIC_NUMBER: u32 = u32::from_be_bytes(*b"YYWL");
 
-pub const MEM_BLOCK_NUM: usize = 0x80;
-pub const TABLE_COMPACT_THRESHOLD: usize = 3;
+/// The removed tables kept in the manifest for the incremental backups.
+pub const REMOVED_TABLES_LOG_SIZE: usize = 1024;
@@ -0,0 +1,227 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+
+/// The delay for every write when a soft limit is exceeded.
+pub const STALL_SLOWDOWN_DELAY: Duration = Duration::from_millis(1);
+
+/// The interval to check again when a hard limit is exceeded.
+pub const STALL_STOP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The longest time to block the writes, they go on over the hard limits
+/// if the pressure can't be released, e.g. the flushes keep failing.
+pub const STALL_STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Soft and hard limits for write stalls.
+///
+/// Writes are slowed down when any soft limit is exceeded,
+/// and blocked until the usage drops when any hard limit is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct StallLimits {
+    pub memtable_soft_bytes: usize,
+    pub memtable_hard_bytes: usize,
+    pub l0_soft_files: usize,
+    pub l0_hard_files: usize,
+    pub pending_compaction_soft_bytes: u64,
+    pub pending_compaction_hard_bytes: u64,
+}
+
+impl Default for StallLimits {
+    fn default() -> Self {
+        Self {
+            memtable_soft_bytes: 32 << 20,
+            memtable_hard_bytes: 64 << 20,
+            l0_soft_files: 8,
+            l0_hard_files: 16,
+            pending_compaction_soft_bytes: 256 << 20,
+            pending_compaction_hard_bytes: 1 << 30,
+        }
+    }
+}
+
+/// The current resource usage of a table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StallUsage {
+    pub memtable_bytes: usize,
+    pub l0_files: usize,
+    pub pending_compaction_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StallReason {
+    None,
+    MemTable,
+    L0Files,
+    PendingCompaction,
+}
+
+impl From<u8> for StallReason {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => StallReason::MemTable,
+            2 => StallReason::L0Files,
+            3 => StallReason::PendingCompaction,
+            _ => StallReason::None,
+        }
+    }
+}
+
+impl Display for StallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StallReason::None => write!(f, "none"),
+            StallReason::MemTable => write!(f, "memtable"),
+            StallReason::L0Files => write!(f, "l0_files"),
+            StallReason::PendingCompaction => write!(f, "pending_compaction"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallState {
+    Normal,
+    Slowdown(StallReason),
+    Stop(StallReason),
+}
+
+impl StallLimits {
+    pub fn check(&self, usage: &StallUsage) -> StallState {
+        if usage.memtable_bytes >= self.memtable_hard_bytes {
+            StallState::Stop(StallReason::MemTable)
+        } else if usage.l0_files >= self.l0_hard_files {
+            StallState::Stop(StallReason::L0Files)
+        } else if usage.pending_compaction_bytes >= self.pending_compaction_hard_bytes {
+            StallState::Stop(StallReason::PendingCompaction)
+        } else if usage.memtable_bytes >= self.memtable_soft_bytes {
+            StallState::Slowdown(StallReason::MemTable)
+        } else if usage.l0_files >= self.l0_soft_files {
+            StallState::Slowdown(StallReason::L0Files)
+        } else if usage.pending_compaction_bytes >= self.pending_compaction_soft_bytes {
+            StallState::Slowdown(StallReason::PendingCompaction)
+        } else {
+            StallState::Normal
+        }
+    }
+}
+
+/// A snapshot of the stall counters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StallStatus {
+    pub reason: Option<StallReason>,
+    pub slowdowns: u64,
+    pub stops: u64,
+    pub stall_micros: u64,
+    pub memtable_stalls: u64,
+    pub l0_files_stalls: u64,
+    pub pending_compaction_stalls: u64,
+}
+
+/// Write stall limits and counters of a table.
+#[derive(Debug, Default)]
+pub struct WriteStall {
+    limits: RwLock<StallLimits>,
+    reason: AtomicU8,
+    slowdowns: AtomicU64,
+    stops: AtomicU64,
+    stall_micros: AtomicU64,
+    reason_counts: [AtomicU64; 3],
+}
+
+impl WriteStall {
+    #[inline]
+    pub fn limits(&self) -> StallLimits {
+        *self.limits.read().unwrap()
+    }
+
+    #[inline]
+    pub fn set_limits(&self, limits: StallLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    #[inline]
+    pub fn check(&self, usage: &StallUsage) -> StallState {
+        self.limits().check(usage)
+    }
+
+    /// Record a stall, called once for every stalled write.
+    pub fn begin(&self, state: StallState) {
+        let reason = match state {
+            StallState::Normal => return,
+            StallState::Slowdown(reason) => {
+                self.slowdowns.fetch_add(1, Ordering::Relaxed);
+                reason
+            }
+            StallState::Stop(reason) => {
+                self.stops.fetch_add(1, Ordering::Relaxed);
+                reason
+            }
+        };
+
+        self.reason.store(reason as u8, Ordering::Relaxed);
+        self.reason_counts[reason as usize - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Finish a stall with the time spent on it.
+    pub fn end(&self, elapsed: Duration) {
+        self.stall_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.reason
+            .store(StallReason::None as u8, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> StallStatus {
+        let reason = StallReason::from(self.reason.load(Ordering::Relaxed));
+
+        StallStatus {
+            reason: (reason != StallReason::None).then_some(reason),
+            slowdowns: self.slowdowns.load(Ordering::Relaxed),
+            stops: self.stops.load(Ordering::Relaxed),
+            stall_micros: self.stall_micros.load(Ordering::Relaxed),
+            memtable_stalls: self.reason_counts[0].load(Ordering::Relaxed),
+            l0_files_stalls: self.reason_counts[1].load(Ordering::Relaxed),
+            pending_compaction_stalls: self.reason_counts[2].load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let limits = StallLimits::default();
+
+        let mut usage = StallUsage::default();
+        assert_eq!(limits.check(&usage), StallState::Normal);
+
+        usage.l0_files = limits.l0_soft_files;
+        assert_eq!(
+            limits.check(&usage),
+            StallState::Slowdown(StallReason::L0Files)
+        );
+
+        usage.memtable_bytes = limits.memtable_hard_bytes;
+        assert_eq!(
+            limits.check(&usage),
+            StallState::Stop(StallReason::MemTable)
+        );
+
+        let stall = WriteStall::default();
+        stall.begin(limits.check(&usage));
+        assert_eq!(stall.status().reason, Some(StallReason::MemTable));
+
+        stall.end(Duration::from_micros(42));
+        let status = stall.status();
+        assert_eq!(status.reason, None);
+        assert_eq!(status.stops, 1);
+        assert_eq!(status.memtable_stalls, 1);
+        assert_eq!(status.stall_micros, 42);
+    }
+}
@@ -1,26 +1,36 @@
 use async_trait::async_trait;
+use futures::Future;
 use tokio::sync::RwLock;
 
-use super::lsm::LsmTreeIterator;
+use super::generation::BackupDelta;
+use super::lsm::{
+    CheckReport, ColumnLayout, LsmTreeIterator, Projection, RepairReport, SSTable, SSTableCheck,
+    SSTableIter, SSTableKey, SSTableLevel, SSTableList, SSTableMeta, SSTableOptions,
+    SSTableProblem,
+};
 use super::manifest::Manifest;
 use super::mem::MemTable;
-use super::{kvstore::*, MemTableIterator};
+use super::{kvstore::*, stall::*, MemTableIterator};
+use crate::core::{Counter, Job, JobKind, Options, Runtime, TableOptions};
 use crate::utils::*;
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Formatter, LowerHex};
-use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use tokio::time::Instant;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct TableId(pub u64);
 
 impl TableId {
+    /// Create the id of a table from its name.
+    ///
+    /// The id is written into `.meta` and used as the key of the runtime,
+    /// so it must be stable across builds: we use FNV-1a instead of the
+    /// std `DefaultHasher`, whose output may change between Rust releases.
     pub fn new(table_name: &str) -> Self {
-        let mut hasher = DefaultHasher::new();
-        table_name.hash(&mut hasher);
-        Self(hasher.finish())
+        Self(stable_hash(table_name.as_bytes()))
     }
 }
 
@@ -30,15 +40,22 @@ impl LowerHex for TableId {
     }
 }
 
+/// A snapshot of the statistics of a table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableStats {
+    pub memtable_bytes: usize,
+    pub sstables: usize,
+    pub filter: FilterStats,
+    pub stall: StallStatus,
+}
+
 #[derive(Debug)]
 pub struct Table {
     id: TableId,
     name: String,
     memtable: MemTable,
     manifest: Arc<RwLock<Manifest>>,
-
-    // new table added
-    new_table_added: Arc<AtomicBool>,
+    write_stall: WriteStall,
 
     // is iterator in progress
     iter_in_progress: Arc<AtomicBool>,
@@ -47,28 +64,35 @@ pub struct Table {
     memtable_iter: RwLock<Option<MemTableIterator>>,
     lsm_iter: RwLock<Option<LsmTreeIterator>>,
     yielded: RwLock<Option<HashSet<Key>>>,
+    projection: RwLock<Projection>,
 }
 
 impl Table {
     pub async fn open(table_name: String) -> Result<Table> {
-        let table_id = TableId::new(&table_name);
-        debug!("Open table          : \"{}\"@{:x}", table_name, table_id);
-
         let table_name = &table_name;
         std::fs::create_dir_all(table_name)?;
-        let manifest = Arc::new(RwLock::new(Manifest::new(table_name).await?));
+        let manifest = Manifest::new(table_name).await?;
+
+        let table_id = manifest.table_id;
+        debug!("Open table          : \"{}\"@{:x}", table_name, table_id);
+
+        let manifest = Arc::new(RwLock::new(manifest));
 
-        Ok(Table {
+        let table = Table {
             id: table_id,
             name: table_name.to_string(),
             manifest: manifest.clone(),
             memtable: MemTable::new(table_name, Some(manifest)).await?,
-            new_table_added: Arc::new(AtomicBool::new(false)),
+            write_stall: WriteStall::default(),
             iter_in_progress: Arc::new(AtomicBool::new(false)),
             memtable_iter: RwLock::new(None),
             lsm_iter: RwLock::new(None),
             yielded: RwLock::new(None),
-        })
+            projection: RwLock::new(None),
+        };
+        table.apply_options().await;
+
+        Ok(table)
     }
 
     pub fn id(&self) -> TableId {
@@ -84,10 +108,36 @@ impl Table {
             .write()
             .await
             .replace(self.memtable.iter().await);
-        self.lsm_iter
+        let projection = self.projection.read().await.clone();
+        self.lsm_iter.write().await.replace(
+            self.manifest
+                .read()
+                .await
+                .iter()
+                .with_projection(projection),
+        );
+        self.init_yielded().await;
+    }
+
+    /// Init the iterator for the keys in `[start, end]`,
+    /// SSTables without keys in the range are skipped.
+    pub async fn init_range_iter(&self, start: Key, end: Key) {
+        self.memtable_iter
             .write()
             .await
-            .replace(self.manifest.read().await.iter());
+            .replace(self.memtable.range_iter(start, end).await);
+        let projection = self.projection.read().await.clone();
+        self.lsm_iter.write().await.replace(
+            self.manifest
+                .read()
+                .await
+                .range_iter(start, end)
+                .with_projection(projection),
+        );
+        self.init_yielded().await;
+    }
+
+    async fn init_yielded(&self) {
         self.yielded.write().await.replace(HashSet::new());
         self.iter_in_progress.store(true, Ordering::Relaxed);
     }
@@ -96,6 +146,7 @@ impl Table {
         self.memtable_iter.write().await.take();
         self.lsm_iter.write().await.take();
         self.yielded.write().await.take();
+        self.projection.write().await.take();
         self.iter_in_progress.store(false, Ordering::Relaxed);
         self.manifest.write().await.do_cleanup();
     }
@@ -110,34 +161,775 @@ impl Table {
         self.yielded.read().await.as_ref().unwrap().contains(key)
     }
 
+    /// Get the write stall counters of the table.
+    #[inline]
+    pub fn stall_status(&self) -> StallStatus {
+        self.write_stall.status()
+    }
+
+    /// Get the statistics of the table.
+    pub async fn stats(&self) -> TableStats {
+        let manifest = self.manifest.read().await;
+
+        TableStats {
+            memtable_bytes: self.memtable.bytes(),
+            sstables: manifest.table_count(),
+            filter: manifest.filter_stats(),
+            stall: self.write_stall.status(),
+        }
+    }
+
+    /// Get the number of SSTables at every level.
+    #[inline]
+    pub async fn level_counts(&self) -> BTreeMap<SSTableLevel, usize> {
+        self.manifest.read().await.level_counts()
+    }
+
+    /// Get the options for the new SSTables of the table.
+    #[inline]
+    pub async fn sstable_options(&self) -> SSTableOptions {
+        self.manifest.read().await.sstable_options.clone()
+    }
+
+    /// Get the options of the table, the global ones with its overrides.
+    pub async fn options(&self) -> Options {
+        Runtime::global()
+            .options()
+            .merge(&self.manifest.read().await.options)
+    }
+
+    /// Get the options overridden by the table.
+    #[inline]
+    pub async fn table_options(&self) -> TableOptions {
+        self.manifest.read().await.options.clone()
+    }
+
+    /// Override an option by name and save it in the manifest,
+    /// `default` to use the global one again.
+    pub async fn set_option(&self, name: &str, value: &str) -> Result<()> {
+        {
+            let mut manifest = self.manifest.write().await;
+            manifest
+                .options
+                .set(&Runtime::global().options(), name, value)?;
+            manifest.to_self_io().await?;
+        }
+        self.apply_options().await;
+
+        info!("Table option        : {}={} @{:x}", name, value, self.id);
+        Ok(())
+    }
+
+    /// Apply the global options with the overrides of the table,
+    /// called when either of them is changed.
+    pub async fn apply_options(&self) {
+        let mut manifest = self.manifest.write().await;
+        let options = Runtime::global().options().merge(&manifest.options);
+
+        manifest.apply_options(&options);
+        self.memtable.set_flush_size(options.memtable_size);
+        self.memtable.set_wal_sync_mode(options.wal_sync_mode);
+        self.write_stall.set_limits(options.stall_limits);
+    }
+
+    /// Override the options, saved with the manifest next time.
+    async fn update_options(&self, f: impl FnOnce(&mut TableOptions)) {
+        f(&mut self.manifest.write().await.options);
+        self.apply_options().await;
+    }
+
+    /// Enable or disable range filters for new SSTables.
+    pub async fn set_range_filter(&self, enabled: bool) {
+        self.update_options(|options| options.range_filter = Some(enabled))
+            .await;
+    }
+
+    /// Set the codec for new SSTables, the existing ones are
+    /// recompressed when they are compacted.
+    pub async fn set_compression(&self, compression: Compression) {
+        self.update_options(|options| options.compression = Some(compression))
+            .await;
+    }
+
+    /// Enable or disable training a zstd dictionary when the SSTables
+    /// are compacted, it helps the tables with small rows.
+    pub async fn set_zstd_dictionary(&self, enabled: bool) {
+        self.update_options(|options| options.zstd_dictionary = Some(enabled))
+            .await;
+    }
+
+    /// Split the row images into columns by the field offsets when the
+    /// SSTables are compacted, the layout is saved in the manifest.
+    pub async fn set_columns(&self, row_size: u32, field_offsets: &[u32]) -> Result<()> {
+        let layout = ColumnLayout::new(row_size, field_offsets)?;
+
+        let mut manifest = self.manifest.write().await;
+        manifest.with_row_size(row_size);
+        manifest.sstable_options.columns = Some(Arc::new(layout));
+        manifest.to_self_io().await
+    }
+
+    /// Only decode the fields from the columnar SSTables in the next scan,
+    /// the other fields of the rows are zeroed. Reset when the scan ends.
+    pub async fn set_projection(&self, fields: &[u32]) {
+        let projection = self
+            .manifest
+            .read()
+            .await
+            .sstable_options
+            .columns
+            .as_ref()
+            .map(|layout| layout.projection(fields));
+
+        *self.projection.write().await = projection;
+    }
+
+    /// Set the size in bytes of the memtable before it is flushed.
+    pub async fn set_memtable_size(&self, bytes: usize) {
+        self.update_options(|options| options.memtable_size = Some(bytes))
+            .await;
+    }
+
+    pub async fn set_stall_limits(&self, limits: StallLimits) {
+        self.update_options(|options| options.stall_limits = Some(limits))
+            .await;
+    }
+
+    async fn stall_usage(&self) -> StallUsage {
+        StallUsage {
+            memtable_bytes: self.memtable.bytes(),
+            ..self.manifest.read().await.stall_usage()
+        }
+    }
+
+    /// Slow down or block the writes if the table is overloaded.
+    async fn stall_writes(&self) {
+        let state = self.write_stall.check(&self.stall_usage().await);
+
+        if state == StallState::Normal {
+            return;
+        }
+
+        let start = Instant::now();
+        self.write_stall.begin(state);
+
+        if let StallState::Stop(reason) = state {
+            warn!("Writes stopped      : {} @{:x}", reason, self.id);
+
+            // make sure the jobs can release the pressure are scheduled
+            while let StallState::Stop(reason) = self.write_stall.check(&self.stall_usage().await) {
+                if start.elapsed() >= STALL_STOP_TIMEOUT {
+                    error!(
+                        "Writes resumed      : {} not released in {:?} @{:x}",
+                        reason, STALL_STOP_TIMEOUT, self.id
+                    );
+                    break;
+                }
+
+                // the memtable may never reach its flush size
+                if reason == StallReason::MemTable
+                    && Runtime::global()
+                        .scheduler()
+                        .pending_jobs(self.id, JobKind::Flush)
+                        == 0
+                {
+                    self.memtable.swap().await;
+                }
+
+                self.schedule_background_jobs().await;
+                tokio::time::sleep(STALL_STOP_INTERVAL).await;
+            }
+        } else {
+            tokio::time::sleep(STALL_SLOWDOWN_DELAY).await;
+        }
+
+        self.write_stall.end(start.elapsed());
+        Runtime::global()
+            .statistics()
+            .add(Counter::StallMicros, start.elapsed().as_micros() as u64);
+    }
+
     #[inline]
     pub async fn table_files(&self) -> Vec<String> {
         self.manifest.read().await.table_files()
     }
 
     pub async fn close(&self) -> Result<()> {
+        let scheduler = Runtime::global().scheduler();
+        scheduler.cancel(self.id, JobKind::Compaction);
+        scheduler.wait_idle(self.id).await;
+
         self.end_iter().await;
         self.memtable.to_self_io().await.ok();
         self.manifest.read().await.to_self_io().await.ok();
         Ok(())
     }
 
-    async fn compact(&self) {
-        let compactable_tables = self.manifest.read().await.get_compactable_tables();
+    /// Persist the memtable to a new L0 SSTable whatever its size.
+    ///
+    /// The memtable is persisted by a flush job, after the ones queued
+    /// before, so the L0 SSTables are always added in order. Once it
+    /// returns, all the writes before are in the SSTables.
+    pub async fn flush(&self) -> Result<()> {
+        let Some(key) = self.memtable.force_freeze().await else {
+            return Ok(());
+        };
+
+        // the compactions are left to the caller, or the next schedule
+        let scheduler = Runtime::global().scheduler();
+        scheduler.submit(
+            self.id,
+            JobKind::Flush,
+            Box::pin(self.memtable.persist_job()),
+        );
+        scheduler.wait_jobs(self.id, JobKind::Flush).await;
+
+        if self.memtable.is_frozen(key).await {
+            return Err(DbError::Other(format!(
+                "Failed to flush the memtable @{:x}",
+                self.id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Merge the SSTables which may contain the keys in `[start, end]` into
+    /// one at the bottom level, dropping the tombstones and the overwritten
+    /// versions. The memtable is flushed first.
+    ///
+    /// The SSTables overlapping the merged ones are merged as well, so the
+    /// tombstones can't hide the older versions in other SSTables.
+    pub async fn compact_range(&self, start: Key, end: Key) -> Result<()> {
+        let scheduler = Runtime::global().scheduler();
+
+        let (level, tables) = loop {
+            scheduler.cancel(self.id, JobKind::Compaction);
+            scheduler.wait_idle(self.id).await;
+            self.flush().await?;
+
+            let manifest = self.manifest.read().await;
+            let tables = manifest.overlapping_tables(start, end);
+
+            // the background compaction may take some of them first
+            let locked = tables.iter().take_while(|table| table.lock()).count();
+            if locked == tables.len() {
+                break (manifest.bottom_level(), tables);
+            }
+
+            tables[..locked].iter().for_each(|table| table.unlock());
+        };
+
+        if tables.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Compact range       : [{},{}] {} sstables to L{} @{:x}",
+            start,
+            end,
+            tables.len(),
+            level,
+            self.id
+        );
+
+        let ret = super::tracker::merge_tables(
+            level,
+            tables.clone(),
+            true,
+            self.manifest.clone(),
+            self.iter_in_progress.clone(),
+        )
+        .await;
+
+        if ret.is_err() {
+            tables.iter().for_each(|table| table.unlock());
+        }
+        ret?;
+
+        self.manifest.read().await.to_self_io().await
+    }
+
+    /// Merge all the SSTables into one at the bottom level.
+    #[inline]
+    pub async fn compact_all(&self) -> Result<()> {
+        self.compact_range(Key::MIN, Key::MAX).await
+    }
+
+    /// Write a consistent copy of the table to `dest`, which can be opened
+    /// by `Table::open`, without blocking the writes.
+    ///
+    /// The memtable is flushed first, with the older ones still waiting to
+    /// be persisted, then the SSTables in the manifest are hard-linked, or
+    /// copied across file systems, while the compacted files are kept. The
+    /// writes after the flush may not be in the copy.
+    #[inline]
+    pub async fn checkpoint(&self, dest: impl AsRef<Path>) -> Result<BackupDelta> {
+        self.backup(dest.as_ref(), None).await
+    }
+
+    /// Write the SSTables added since the backup of `generation` to `dest`,
+    /// with the manifest of the table.
+    ///
+    /// The returned delta lists the files removed since, apply it on a copy
+    /// of the previous backup with `Table::restore_backup`. A full checkpoint
+    /// is needed if the generation is older than the log of the removals.
+    #[inline]
+    pub async fn backup_since(
+        &self,
+        dest: impl AsRef<Path>,
+        generation: u64,
+    ) -> Result<BackupDelta> {
+        self.backup(dest.as_ref(), Some(generation)).await
+    }
+
+    async fn backup(&self, dest: &Path, base: Option<u64>) -> Result<BackupDelta> {
+        let meta_path = dest.join(".meta");
+        if meta_path.exists() {
+            return Err(DbError::Other(format!("Backup {:?} already exists", dest)));
+        }
+        std::fs::create_dir_all(dest)?;
+
+        // no older memtable is left out, the manifest has all the writes before
+        self.flush().await?;
+
+        // the manifest is moved in place once the tables are linked
+        let tmp_path = dest.join(".meta.tmp");
+        let (tables, delta) = {
+            let mut manifest = self.manifest.write().await;
+            let (tables, removed) = match base {
+                Some(base) => {
+                    let (added, removed) = manifest.changes_since(base)?;
+                    let tables = manifest
+                        .tables()
+                        .into_iter()
+                        .filter(|table| added.contains(&table.meta().key))
+                        .collect::<SSTableList>();
+                    (tables, removed)
+                }
+                None => (manifest.tables(), Vec::new()),
+            };
+
+            manifest.to_io(&IOHandler::new(&tmp_path).await?).await?;
+            manifest.pin();
+
+            let delta = BackupDelta {
+                base,
+                generation: manifest.generation(),
+                added: tables
+                    .iter()
+                    .map(|table| table.meta().key.file_name())
+                    .collect(),
+                removed: removed.iter().map(SSTableKey::file_name).collect(),
+            };
+            (tables, delta)
+        };
+
+        let ret = Self::link_tables(&tables, dest);
+        self.manifest.write().await.unpin();
+        if ret.is_err() {
+            std::fs::remove_file(&tmp_path).ok();
+        }
+        ret?;
+        std::fs::rename(&tmp_path, &meta_path)?;
+
+        info!(
+            "Backup created      : {} sstables to {:?} @{:x}, generation {:?} -> {}",
+            tables.len(),
+            dest,
+            self.id,
+            base,
+            delta.generation
+        );
+
+        Ok(delta)
+    }
+
+    fn link_tables(tables: &SSTableList, dest: &Path) -> Result<()> {
+        for table in tables {
+            let src = Path::new(table.file_name());
+            Self::link_file(src, &dest.join(src.file_name().unwrap()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Hard-link the file, or copy it across file systems.
+    fn link_file(src: &Path, dest: &Path) -> Result<()> {
+        // the SSTables are never modified once written
+        if std::fs::hard_link(src, dest).is_err() {
+            std::fs::copy(src, dest)?;
+        }
+
+        Ok(())
+    }
+
+    /// Install the SSTables built by `SSTableWriter`, the files are
+    /// hard-linked, or copied, and left in place.
+    ///
+    /// The files must not overlap each other. Every file goes to the deepest
+    /// level above the SSTables overlapping its keys, so it takes precedence
+    /// over the data written before. The memtable is flushed first. Get the
+    /// keys of the new SSTables, in the order of the files.
+    pub async fn ingest(&self, files: &[impl AsRef<Path>]) -> Result<Vec<SSTableKey>> {
+        let mut ranges = Vec::with_capacity(files.len());
+        for file in files {
+            let file = file.as_ref();
+            if !file.is_file() {
+                return Err(DbError::Other(format!("{:?} not found", file)));
+            }
+
+            let iter = SSTableIter::new(IOHandler::new(file).await?).await?;
+            if iter.entries_count() == 0 {
+                return Err(DbError::Other(format!("{:?} is empty", file)));
+            }
+            ranges.push(iter.key_range());
+        }
+
+        let mut sorted = ranges.clone();
+        sorted.sort();
+        if let Some(ranges) = sorted.windows(2).find(|ranges| ranges[0].1 >= ranges[1].0) {
+            return Err(DbError::Other(format!(
+                "Ingested key ranges [{},{}] and [{},{}] overlap",
+                ranges[0].0, ranges[0].1, ranges[1].0, ranges[1].1
+            )));
+        }
+
+        self.flush().await?;
 
-        for (level, tables) in compactable_tables {
-            trace!(
-                "Compacting tables at level {} with {:#?}",
-                level,
-                tables.iter().map(|t| t.meta().key).collect::<Vec<_>>()
+        // the tables only move down, the levels stay above the overlapping ones
+        let mut keys: Vec<SSTableKey> = Vec::with_capacity(files.len());
+        {
+            let manifest = self.manifest.read().await;
+            for (start, end) in ranges.iter() {
+                let mut key = SSTableKey::new(manifest.ingest_level(*start, *end));
+                while keys.contains(&key) || manifest.factory.file_path(key).exists() {
+                    key = SSTableKey(key.0 - 1);
+                }
+                keys.push(key);
+            }
+        }
+
+        let tables = self.install_files(files, &keys).await;
+        let tables = match tables {
+            Ok(tables) => tables,
+            Err(err) => {
+                let manifest = self.manifest.read().await;
+                for key in keys.iter() {
+                    std::fs::remove_file(manifest.factory.file_path(*key)).ok();
+                }
+                return Err(err);
+            }
+        };
+
+        let mut manifest = self.manifest.write().await;
+        for table in tables {
+            info!(
+                "Ingest sstable      : {} entries [{},{}] to L{} @{:x}",
+                table.meta().entries_count,
+                table.meta().min_key,
+                table.meta().max_key,
+                table.meta().key.level(),
+                self.id
             );
+            manifest.add_table(table).await;
+        }
+        manifest.to_self_io().await?;
+        drop(manifest);
+
+        self.schedule_compaction();
+
+        Ok(keys)
+    }
+
+    /// Link the files under the keys, and verify them like `check`.
+    async fn install_files(
+        &self,
+        files: &[impl AsRef<Path>],
+        keys: &[SSTableKey],
+    ) -> Result<Vec<SSTable>> {
+        let factory = self.manifest.read().await.factory.clone();
 
-            let manifest = self.manifest.clone();
-            let iter_in_progress = self.iter_in_progress.clone();
+        let mut tables = Vec::with_capacity(files.len());
+        for (file, key) in files.iter().zip(keys) {
+            Self::link_file(file.as_ref(), &factory.file_path(*key))?;
 
-            crate::core::runtime::spawn(async move {
-                super::tracker::compact_worker(level, tables, manifest, iter_in_progress).await
-            });
+            let table = SSTable::new(SSTableMeta::new(*key), &factory).await?;
+            let check = table.check().await;
+            if let Some(problem) = check.problems.first() {
+                return Err(DbError::Other(format!(
+                    "Ingested {:?} {}",
+                    file.as_ref(),
+                    problem
+                )));
+            }
+            tables.push(table);
+        }
+
+        Ok(tables)
+    }
+
+    /// Apply a backup to the copy of the previous one in `dir`, the table
+    /// must not be open.
+    ///
+    /// The SSTables and the manifest of the backup are copied, then the
+    /// files not in the manifest are removed. A full checkpoint can be
+    /// restored into an empty directory.
+    pub async fn restore_backup(
+        dir: impl AsRef<Path>,
+        backup: impl AsRef<Path>,
+    ) -> Result<Vec<String>> {
+        let (dir, backup) = (dir.as_ref(), backup.as_ref());
+        if !backup.join(".meta").is_file() {
+            return Err(DbError::Other(format!("Backup {:?} not found", backup)));
+        }
+        std::fs::create_dir_all(dir)?;
+
+        for entry in std::fs::read_dir(backup)? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_str()
+                .and_then(SSTableKey::from_file_name)
+                .is_some()
+            {
+                std::fs::copy(entry.path(), dir.join(entry.file_name()))?;
+            }
+        }
+        std::fs::copy(backup.join(".meta"), dir.join(".meta"))?;
+
+        // a failure here leaves the files of the base untouched
+        let manifest = Manifest::from_io(&IOHandler::new(dir.join(".meta")).await?).await?;
+        let missing = manifest
+            .tables()
+            .iter()
+            .filter(|table| {
+                std::fs::metadata(table.file_name()).map_or(true, |metadata| metadata.len() == 0)
+            })
+            .map(|table| table.meta().key.file_name())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(DbError::Other(format!(
+                "Backup {:?} doesn't match the base, missing {:?}",
+                backup, missing
+            )));
+        }
+
+        let removed = manifest.orphan_files()?;
+        for file in removed.iter() {
+            std::fs::remove_file(file)?;
+        }
+
+        info!(
+            "Backup restored     : {:?} to {:?}, generation {}, {} files removed",
+            backup,
+            dir,
+            manifest.generation(),
+            removed.len()
+        );
+
+        Ok(removed)
+    }
+
+    /// Verify every SSTable in the manifest, and find the SSTable files
+    /// on disk which are not in the manifest.
+    pub async fn check(&self) -> Result<CheckReport> {
+        let manifest = self.manifest.read().await;
+        let mut report = CheckReport::default();
+
+        for table in manifest.tables() {
+            let check = table.check().await;
+            for problem in check.problems.iter() {
+                warn!("Check failed        : {} {}", check.file_name, problem);
+            }
+            report.tables.push(check);
+        }
+
+        report.orphans = manifest.orphan_files()?;
+        for orphan in report.orphans.iter() {
+            warn!("Orphan file         : {}", orphan);
+        }
+
+        Ok(report)
+    }
+
+    /// Rebuild the manifest from the SSTables passing the check.
+    ///
+    /// A damaged SSTable is rewritten under the same key with the entries
+    /// in its readable blocks, so it keeps its place among the others, or
+    /// dropped if nothing is readable. The damaged files are kept with a
+    /// `.corrupted` suffix. If the manifest is lost, the SSTable files in
+    /// the directory are adopted.
+    pub async fn repair(&self) -> Result<RepairReport> {
+        let scheduler = Runtime::global().scheduler();
+        scheduler.cancel(self.id, JobKind::Compaction);
+        scheduler.wait_idle(self.id).await;
+
+        let mut manifest = self.manifest.write().await;
+        let mut report = RepairReport::default();
+        let mut tables = manifest.tables();
+
+        if tables.is_empty() {
+            for key in manifest.disk_tables()? {
+                report.adopted = true;
+                match SSTable::new(SSTableMeta::new(key), &manifest.factory).await {
+                    Ok(table) => tables.push(Arc::new(table)),
+                    Err(err) => {
+                        let file_name = manifest.factory.file_path(key);
+                        let check = SSTableCheck {
+                            key,
+                            file_name: file_name.to_str().unwrap().to_string(),
+                            entries: 0,
+                            problems: vec![SSTableProblem::Unreadable(format!("{:?}", err))],
+                        };
+                        Self::set_aside(&check)?;
+                        report.dropped.push(check);
+                    }
+                }
+            }
+        }
+
+        let mut repaired = Vec::with_capacity(tables.len());
+        for table in tables {
+            let (check, entries) = table.scan(true).await;
+            if check.is_ok() {
+                report.kept += 1;
+                repaired.push(table);
+                continue;
+            }
+
+            Self::set_aside(&check)?;
+            if entries.is_empty() {
+                report.dropped.push(check);
+                continue;
+            }
+
+            let mut salvaged = SSTable::new(SSTableMeta::new(check.key), &manifest.factory).await?;
+            salvaged
+                .archive(entries, manifest.sstable_options.clone())
+                .await?;
+            info!(
+                "Salvaged table      : {} entries to {}",
+                salvaged.meta().entries_count,
+                salvaged.file_name()
+            );
+
+            repaired.push(Arc::new(salvaged));
+            report.salvaged.push(check);
+        }
+
+        manifest.replace_tables(repaired).await?;
+        drop(manifest);
+
+        Runtime::global().clear_corrupted(&self.id).await;
+        self.schedule_compaction();
+
+        info!(
+            "Repaired table      : {} kept, {} salvaged, {} dropped @{:x}",
+            report.kept,
+            report.salvaged.len(),
+            report.dropped.len(),
+            self.id
+        );
+
+        Ok(report)
+    }
+
+    /// Rename the file of a damaged SSTable, so it is never read again.
+    fn set_aside(check: &SSTableCheck) -> Result<()> {
+        for problem in check.problems.iter() {
+            warn!("Damaged table       : {} {}", check.file_name, problem);
+        }
+
+        std::fs::rename(&check.file_name, format!("{}.corrupted", check.file_name))?;
+        Ok(())
+    }
+
+    /// Schedule the flush and compaction jobs of the table if needed.
+    pub async fn schedule_background_jobs(&self) {
+        self.schedule_flush().await;
+
+        // retry the memtables failed to persist
+        if self.memtable.immutable_count().await > 0
+            && Runtime::global()
+                .scheduler()
+                .pending_jobs(self.id, JobKind::Flush)
+                == 0
+        {
+            self.submit_flush(self.memtable.persist_job());
+        }
+
+        self.schedule_compaction();
+    }
+
+    async fn schedule_flush(&self) {
+        if let Some(persist) = self.memtable.do_persist().await {
+            self.submit_flush(persist);
+        }
+    }
+
+    fn submit_flush(&self, persist: impl Future<Output = Result<()>> + Send + 'static) {
+        let id = self.id;
+        let compaction = self.compaction_job();
+        let scheduler = Runtime::global().scheduler();
+
+        scheduler.submit(
+            id,
+            JobKind::Flush,
+            Box::pin(async move {
+                persist.await?;
+                trace!("New table added, compacting...");
+                scheduler.submit_unique(id, JobKind::Compaction, || compaction);
+                Ok(())
+            }),
+        );
+    }
+
+    fn schedule_compaction(&self) {
+        Runtime::global()
+            .scheduler()
+            .submit_unique(self.id, JobKind::Compaction, || self.compaction_job());
+    }
+
+    fn compaction_job(&self) -> Job {
+        let id = self.id;
+        let compact = Self::compact(self.manifest.clone(), self.iter_in_progress.clone());
+
+        Box::pin(async move {
+            // the damaged blocks would fail it again until the table is repaired
+            if Runtime::global().is_corrupted(&id).await {
+                return Ok(());
+            }
+            compact.await
+        })
+    }
+
+    async fn compact(
+        manifest: Arc<RwLock<Manifest>>,
+        iter_in_progress: Arc<AtomicBool>,
+    ) -> Result<()> {
+        loop {
+            let compactable_tables = manifest.read().await.get_compactable_tables();
+
+            if compactable_tables.is_empty() {
+                return Ok(());
+            }
+
+            for (level, tables) in compactable_tables {
+                trace!(
+                    "Compacting tables at level {} with {:#?}",
+                    level,
+                    tables.iter().map(|t| t.meta().key).collect::<Vec<_>>()
+                );
+
+                super::tracker::compact_worker(
+                    level,
+                    tables,
+                    manifest.clone(),
+                    iter_in_progress.clone(),
+                )
+                .await?;
+            }
         }
     }
 
@@ -189,15 +981,8 @@ impl Drop for Table {
 #[async_trait]
 impl AsyncKvStoreRead for Table {
     async fn get(&self, key: Key) -> Result<DataStore> {
-        let manifest = self.manifest.read().await;
-
-        if !manifest.bloom_filter.contains(key) {
-            return Ok(DataStore::NotFound);
-        }
-
         trace!("Try Get key in table {:x}: [{:?}]", self.id, key);
-
-        drop(manifest); // release lock
+        Runtime::global().statistics().incr(Counter::Gets);
 
         match self.memtable.get(key).await? {
             DataStore::Value(value) => return Ok(DataStore::Value(value)),
@@ -205,9 +990,11 @@ impl AsyncKvStoreRead for Table {
             DataStore::NotFound => (),
         };
 
-        let manifest = self.manifest.read().await;
-
-        let ret = manifest.get(key).await?;
+        let ret = self.manifest.read().await.get(key).await;
+        if let Err(DbError::MissChecksum) = ret {
+            Runtime::global().mark_corrupted(&self.id).await;
+        }
+        let ret = ret?;
         trace!("Get value: [{}] -> [{}]", key, ret);
 
         Ok(ret)
@@ -221,25 +1008,24 @@ impl AsyncKvStoreRead for Table {
 #[async_trait]
 impl AsyncKvStoreWrite for Table {
     async fn set(&self, key: Key, value: DataInner) {
-        self.manifest
-            .write()
-            .await
-            .with_row_size(value.len() as u32);
-        self.memtable.set(key, value).await;
-        self.memtable.do_persist(self.new_table_added.clone()).await;
-
-        if self
-            .new_table_added
-            .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
-        {
-            trace!("New table added, compacting...");
-            self.compact().await;
+        // only lock the manifest exclusively for the first write
+        if self.manifest.read().await.row_size == 0 {
+            self.manifest
+                .write()
+                .await
+                .with_row_size(value.len() as u32);
         }
+        self.stall_writes().await;
+        self.memtable.set(key, value).await;
+        Runtime::global().statistics().incr(Counter::Puts);
+        self.schedule_flush().await;
     }
 
     async fn delete(&self, key: Key) {
+        self.stall_writes().await;
         self.memtable.delete(key).await;
+        Runtime::global().statistics().incr(Counter::Deletes);
+        self.schedule_flush().await;
     }
 }
 
@@ -249,3 +1035,499 @@ impl SizedOnDisk for Table {
         self.manifest.read().await.size_on_disk().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::lsm::SSTableWriter;
+    use super::super::WalSyncMode;
+    use super::*;
+
+    #[test]
+    fn table_id_is_stable() {
+        // FNV-1a test vectors, must never change across releases
+        assert_eq!(TableId::new("").0, 0xcbf29ce484222325);
+        assert_eq!(TableId::new("a").0, 0xaf63dc4c8601ec8c);
+        assert_eq!(TableId::new("foobar").0, 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn compact_works() {
+        crate::core::runtime::block_on(async {
+            compact_works_async().await.unwrap();
+        });
+    }
+
+    async fn compact_works_async() -> Result<()> {
+        let test_dir = "helper/table_compact_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        let value = |i: u64| vec![i as u8; 0x20];
+
+        // three L0 tables, the later ones delete and overwrite the keys
+        for i in 0..0x400 {
+            table.set(i, value(i)).await;
+        }
+        table.flush().await?;
+        for i in 0..0x100 {
+            table.delete(i).await;
+        }
+        table.flush().await?;
+        for i in 0x200..0x300 {
+            table.set(i, value(i + 1)).await;
+        }
+        table.flush().await?;
+
+        // the overlapping tables are merged together
+        table.compact_range(0, 0x10).await?;
+        assert_eq!(table.stats().await.sstables, 1);
+        assert_eq!(
+            table.manifest.read().await.tables()[0].meta().key.level(),
+            1
+        );
+
+        table.delete(0x3ff).await;
+        table.compact_all().await?;
+
+        let manifest = table.manifest.read().await;
+        assert_eq!(manifest.table_count(), 1);
+        assert_eq!(manifest.tables()[0].meta().deleted_count, 0);
+        assert_eq!(manifest.tables()[0].meta().entries_count, 0x2ff);
+        drop(manifest);
+
+        assert_eq!(table.get(0x10).await?, DataStore::NotFound);
+        assert_eq!(table.get(0x3ff).await?, DataStore::NotFound);
+        assert_eq!(
+            table.get(0x100).await?,
+            DataStore::Value(Arc::new(value(0x100)))
+        );
+        assert_eq!(
+            table.get(0x200).await?,
+            DataStore::Value(Arc::new(value(0x201)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_compact_works() {
+        crate::core::runtime::block_on(async {
+            reopen_compact_works_async().await.unwrap();
+        });
+    }
+
+    /// Overwrite a key in three L0 tables, not compacted until the
+    /// table is reopened or repaired.
+    async fn overwrite_in_l0(table: &Table, from: u8) -> Result<()> {
+        table.set_option("compact_threshold", "8").await?;
+        for i in from..from + 3 {
+            table.set(1, vec![i; 8]).await;
+            table.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn reopen_compact_works_async() -> Result<()> {
+        let test_dir = "helper/table_reopen_compact_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let scheduler = Runtime::global().scheduler();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        overwrite_in_l0(&table, 1).await?;
+        table.close().await?;
+        drop(table);
+
+        // the tracker is rebuilt from the manifest
+        let table = Table::open(test_dir.to_string()).await?;
+        table.set_option("compact_threshold", "3").await?;
+        table.schedule_compaction();
+        scheduler.wait_idle(table.id).await;
+        assert_eq!(table.level_counts().await.get(&0), None);
+        assert_eq!(table.get(1).await?, DataStore::Value(Arc::new(vec![3; 8])));
+
+        // and by the repair
+        overwrite_in_l0(&table, 4).await?;
+        table.set_option("compact_threshold", "3").await?;
+        table.repair().await?;
+        scheduler.wait_idle(table.id).await;
+        assert_eq!(table.level_counts().await.get(&0), None);
+        assert_eq!(table.get(1).await?, DataStore::Value(Arc::new(vec![6; 8])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stall_stop_works() {
+        crate::core::runtime::block_on(async {
+            stall_stop_works_async().await.unwrap();
+        });
+    }
+
+    async fn stall_stop_works_async() -> Result<()> {
+        let test_dir = "helper/table_stall_stop_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        // the hard limit is reached before the memtable is full, the options
+        // reject it, so the limits are set under them
+        let table = Table::open(test_dir.to_string()).await?;
+        table.memtable.set_flush_size(1 << 20);
+        table.write_stall.set_limits(StallLimits {
+            memtable_soft_bytes: 0x800,
+            memtable_hard_bytes: 0x1000,
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for i in 0..0x200u64 {
+            table.set(i, vec![i as u8; 0x20]).await;
+        }
+
+        assert!(start.elapsed() < STALL_STOP_TIMEOUT);
+        assert!(table.stall_status().stops > 0);
+        assert!(table.stats().await.sstables > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wal_replay_works() {
+        crate::core::runtime::block_on(async {
+            wal_replay_works_async().await.unwrap();
+        });
+    }
+
+    async fn wal_replay_works_async() -> Result<()> {
+        let test_dir = "helper/table_wal_replay_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        // the table is dropped without closing it, as if the server crashed
+        {
+            let table = Table::open(test_dir.to_string()).await?;
+            table.memtable.set_wal_sync_mode(WalSyncMode::Write);
+
+            table.set(1, vec![1; 8]).await;
+            table.set(2, vec![2; 8]).await;
+            table.memtable.swap().await;
+            table.set(1, vec![3; 8]).await;
+            table.delete(2).await;
+        }
+
+        let table = Table::open(test_dir.to_string()).await?;
+        assert_eq!(table.get(1).await?, DataStore::Value(Arc::new(vec![3; 8])));
+        assert_eq!(table.get(2).await?, DataStore::Deleted);
+
+        // the replayed writes are saved to the cache
+        assert!(std::fs::read_dir(test_dir)?.all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".wal")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn columnar_update_works() {
+        crate::core::runtime::block_on(async {
+            columnar_update_works_async().await.unwrap();
+        });
+    }
+
+    async fn columnar_update_works_async() -> Result<()> {
+        let test_dir = "helper/table_columnar_update_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        table.set_columns(12, &[0, 4, 8]).await?;
+        let row = |fields: [u8; 3]| fields.iter().flat_map(|field| [*field; 4]).collect();
+
+        for i in 0..0x100u64 {
+            table.set(i, row([i as u8, 1, 2])).await;
+        }
+        table.compact_all().await?;
+
+        // a read-only scan only decodes the projected fields
+        table.set_projection(&[1]).await;
+        table.init_iter().await;
+        assert_eq!(
+            table.next().await?,
+            Some((0, DataStore::Value(Arc::new(row([0, 1, 0])))))
+        );
+        table.end_iter().await;
+
+        // an update scans the whole rows, and writes them back
+        table.init_iter().await;
+        let mut rows = Vec::new();
+        while let Some((key, DataStore::Value(value))) = table.next().await? {
+            let mut value = value.to_vec();
+            value[4..8].fill(0xff);
+            rows.push((key, value));
+        }
+        table.end_iter().await;
+        for (key, value) in rows {
+            table.set(key, value).await;
+        }
+        table.compact_all().await?;
+
+        for i in 0..0x100u64 {
+            assert_eq!(
+                table.get(i).await?,
+                DataStore::Value(Arc::new(row([i as u8, 0xff, 2])))
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoint_works() {
+        crate::core::runtime::block_on(async {
+            checkpoint_works_async().await.unwrap();
+        });
+    }
+
+    async fn checkpoint_works_async() -> Result<()> {
+        let test_dir = "helper/table_checkpoint_test";
+        let dest = "helper/table_checkpoint_test_backup";
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::remove_dir_all(dest).ok();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        for i in 0..0x400u64 {
+            table.set(i, vec![i as u8; 0x20]).await;
+        }
+        table.flush().await?;
+        for i in 0x100..0x200u64 {
+            table.delete(i).await;
+        }
+        // frozen, but not persisted yet
+        table.memtable.swap().await;
+
+        table.checkpoint(dest).await?;
+        assert!(table.checkpoint(dest).await.is_err());
+
+        // the source goes on without changing the checkpoint
+        table.set(0, vec![0xff; 0x20]).await;
+        table.compact_all().await?;
+
+        let backup = Table::open(dest.to_string()).await?;
+        assert_eq!(backup.stats().await.sstables, 2);
+        assert!(backup.check().await?.is_ok());
+        assert_eq!(
+            backup.get(0).await?,
+            DataStore::Value(Arc::new(vec![0; 0x20]))
+        );
+        assert_eq!(backup.get(0x100).await?, DataStore::Deleted);
+        assert_eq!(
+            backup.get(0x3ff).await?,
+            DataStore::Value(Arc::new(vec![0xff; 0x20]))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn backup_since_works() {
+        crate::core::runtime::block_on(async {
+            backup_since_works_async().await.unwrap();
+        });
+    }
+
+    async fn backup_since_works_async() -> Result<()> {
+        let test_dir = "helper/table_backup_test";
+        let full = "helper/table_backup_test_full";
+        let incremental = "helper/table_backup_test_incremental";
+        let restored = "helper/table_backup_test_restored";
+        for dir in [test_dir, full, incremental, restored] {
+            std::fs::remove_dir_all(dir).ok();
+        }
+
+        let table = Table::open(test_dir.to_string()).await?;
+        for i in 0..0x400u64 {
+            table.set(i, vec![i as u8; 0x20]).await;
+        }
+        table.flush().await?;
+        for i in 0x1000..0x1100u64 {
+            table.set(i, vec![i as u8; 0x20]).await;
+        }
+        let base = table.checkpoint(full).await?;
+        assert_eq!(base.added.len(), 2);
+
+        // nothing changed since
+        let delta = table.backup_since(incremental, base.generation).await?;
+        assert_eq!(delta.generation, base.generation);
+        assert!(delta.added.is_empty() && delta.removed.is_empty());
+        std::fs::remove_dir_all(incremental)?;
+
+        // the second table is compacted, the first one is kept
+        for i in 0x1000..0x1010u64 {
+            table.delete(i).await;
+        }
+        table.compact_range(0x1000, 0x1000).await?;
+        table.set(0x2000, vec![0xff; 0x20]).await;
+
+        let delta = table.backup_since(incremental, base.generation).await?;
+        assert_eq!(delta.base, Some(base.generation));
+        assert_eq!(delta.added.len(), 2);
+        assert_eq!(delta.removed.len(), 1);
+        assert!(base.added.contains(&delta.removed[0]));
+        assert!(delta.added.iter().all(|file| !base.added.contains(file)));
+        assert!(table
+            .backup_since(incremental, base.generation)
+            .await
+            .is_err());
+        assert!(table
+            .backup_since(restored, delta.generation + 1)
+            .await
+            .is_err());
+
+        // the base is needed for the kept table
+        assert!(Table::restore_backup(restored, incremental).await.is_err());
+        std::fs::remove_dir_all(restored)?;
+
+        Table::restore_backup(restored, full).await?;
+        let removed = Table::restore_backup(restored, incremental).await?;
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].ends_with(&delta.removed[0]));
+
+        let backup = Table::open(restored.to_string()).await?;
+        assert_eq!(backup.stats().await.sstables, 3);
+        assert!(backup.check().await?.is_ok());
+        assert_eq!(
+            backup.get(0x10).await?,
+            DataStore::Value(Arc::new(vec![0x10; 0x20]))
+        );
+        assert_eq!(backup.get(0x1000).await?, DataStore::NotFound);
+        assert_eq!(
+            backup.get(0x2000).await?,
+            DataStore::Value(Arc::new(vec![0xff; 0x20]))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_works() {
+        crate::core::runtime::block_on(async {
+            ingest_works_async().await.unwrap();
+        });
+    }
+
+    async fn ingest_works_async() -> Result<()> {
+        let test_dir = "helper/table_ingest_test";
+        let files_dir = "helper/table_ingest_test_files";
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::remove_dir_all(files_dir).ok();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        for i in 0..0x400u64 {
+            table.set(i, vec![i as u8; 0x20]).await;
+        }
+        table.compact_all().await?;
+
+        let mut writer = SSTableWriter::new(files_dir, SSTableOptions::default())?;
+        for i in 0x1000..0x1100u64 {
+            writer.put(i, vec![0xfe; 0x20]).await?;
+        }
+        let mut files = writer.finish().await?;
+
+        let mut writer = SSTableWriter::new(files_dir, SSTableOptions::default())?;
+        writer.delete(0x10).await?;
+        for i in 0x100..0x200u64 {
+            writer.put(i, vec![0xff; 0x20]).await?;
+        }
+        files.extend(writer.finish().await?);
+
+        // the files overlap each other
+        assert!(table.ingest(&[&files[1], &files[1]]).await.is_err());
+
+        // the newer one goes above the compacted table
+        let keys = table.ingest(&files).await?;
+        assert_eq!(
+            keys.iter().map(SSTableKey::level).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+        assert!(files.iter().all(|file| file.exists()));
+        assert!(table.check().await?.is_ok());
+
+        assert_eq!(table.get(0x10).await?, DataStore::Deleted);
+        assert_eq!(
+            table.get(0x100).await?,
+            DataStore::Value(Arc::new(vec![0xff; 0x20]))
+        );
+        assert_eq!(
+            table.get(0x200).await?,
+            DataStore::Value(Arc::new(vec![0; 0x20]))
+        );
+        assert_eq!(
+            table.get(0x10ff).await?,
+            DataStore::Value(Arc::new(vec![0xfe; 0x20]))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn repair_works() {
+        crate::core::runtime::block_on(async {
+            repair_works_async().await.unwrap();
+        });
+    }
+
+    async fn repair_works_async() -> Result<()> {
+        let test_dir = "helper/table_repair_test";
+        std::fs::remove_dir_all(test_dir).ok();
+
+        let table = Table::open(test_dir.to_string()).await?;
+        let data = (0..0x4000u64)
+            .map(|i| (i, DataStore::Value(Arc::new(vec![i as u8; 0x40]))))
+            .collect::<Vec<_>>();
+
+        let mut files = Vec::new();
+        for level in 1..3u32 {
+            let mut manifest = table.manifest.write().await;
+            let mut sstable =
+                SSTable::new(SSTableMeta::new(SSTableKey::new(level)), &manifest.factory).await?;
+            sstable.archive(data.clone(), Default::default()).await?;
+            files.push(sstable.file_name().to_string());
+            manifest.add_table(sstable).await;
+        }
+        assert!(table.check().await?.is_ok());
+
+        // flip a byte in the first block of the newer table
+        let mut bytes = std::fs::read(&files[0])?;
+        bytes[super::super::lsm::HEADER_SIZE as usize + 1] ^= 0xff;
+        std::fs::write(&files[0], bytes)?;
+
+        let report = table.check().await?;
+        assert_eq!(report.damaged().count(), 1);
+        assert!(matches!(
+            report.damaged().next().unwrap().problems[0],
+            SSTableProblem::BadBlock { offset, .. } if offset == super::super::lsm::HEADER_SIZE
+        ));
+
+        // the reads of the damaged block mark the table as corrupted
+        assert!(matches!(table.get(0).await, Err(DbError::MissChecksum)));
+        assert!(Runtime::global().is_corrupted(&table.id).await);
+
+        let report = table.repair().await?;
+        assert_eq!(report.kept, 1);
+        assert_eq!(report.salvaged.len(), 1);
+        assert!(std::path::Path::new(&format!("{}.corrupted", files[0])).exists());
+        assert!(table.check().await?.is_ok());
+        assert!(!Runtime::global().is_corrupted(&table.id).await);
+
+        // the keys of the lost block are read from the older table
+        for (key, value) in data.iter().step_by(0x100) {
+            assert_eq!(table.get(*key).await?, *value);
+        }
+
+        let manifest = table.manifest.read().await;
+        let salvaged = &manifest.tables()[0];
+        assert_eq!(salvaged.meta().key.level(), 1);
+        assert!(salvaged.meta().entries_count < data.len());
+
+        Ok(())
+    }
+}
@@ -6,15 +6,16 @@ use std::{
     },
 };
 
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, time::Instant};
 
 use crate::utils::*;
 
 use super::{
     lsm::{SSTable, SSTableKey, SSTableLevel, SSTableList, SSTableMeta},
     manifest::Manifest,
-    AsyncIterator, TABLE_COMPACT_THRESHOLD,
+    AsyncIterator,
 };
+use crate::core::{Counter, Options, Runtime};
 
 #[macro_export]
 macro_rules! impl_deque_push {
@@ -39,9 +40,20 @@ macro_rules! impl_deque_pop {
     };
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct SSTableTracker {
     inner: HashMap<SSTableLevel, VecDeque<Arc<SSTable>>>,
+    /// The number of tables at a level to be compacted together.
+    threshold: usize,
+}
+
+impl Default for SSTableTracker {
+    fn default() -> Self {
+        Self {
+            inner: HashMap::new(),
+            threshold: Options::default().compact_threshold,
+        }
+    }
 }
 
 impl SSTableTracker {
@@ -50,11 +62,42 @@ impl SSTableTracker {
     impl_deque_pop!(pop_front);
     impl_deque_pop!(pop_back);
 
+    /// Remove the table, wherever it is in its level.
+    pub fn remove(&mut self, table: &SSTable) {
+        let key = table.meta().key;
+        if let Some(tables) = self.inner.get_mut(&key.level()) {
+            trace!("Remove table from tracker: {:?}", key);
+            tables.retain(|table| table.meta().key != key);
+        }
+    }
+
+    #[inline]
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold.max(2);
+    }
+
     #[inline]
     pub fn clear(&mut self) {
         self.inner.clear();
     }
 
+    /// Get the number of tables at the level.
+    #[inline]
+    pub fn level_len(&self, level: SSTableLevel) -> usize {
+        self.inner.get(&level).map_or(0, |tables| tables.len())
+    }
+
+    /// Get the bytes of tables waiting for compaction, that is,
+    /// the tables at levels with enough tables to be compacted.
+    pub fn pending_compaction_bytes(&self) -> u64 {
+        self.inner
+            .values()
+            .filter(|tables| tables.len() >= self.threshold)
+            .flat_map(|tables| tables.iter())
+            .map(|table| table.file_size())
+            .sum()
+    }
+
     pub fn get_compactable_tables(&self) -> Vec<(SSTableLevel, SSTableList)> {
         let mut ret = Vec::new();
 
@@ -86,17 +129,17 @@ impl SSTableTracker {
                     break;
                 }
 
-                if compactable.len() >= TABLE_COMPACT_THRESHOLD {
+                if compactable.len() >= self.threshold {
                     break;
                 }
             }
 
-            if compactable.len() < TABLE_COMPACT_THRESHOLD {
+            if compactable.len() < self.threshold {
                 trace!(
                     "Not enough tables for L{:?} {}/{}",
                     level,
                     compactable.len(),
-                    TABLE_COMPACT_THRESHOLD
+                    self.threshold
                 );
                 for table in compactable.iter() {
                     table.unlock();
@@ -116,35 +159,71 @@ pub async fn compact_worker(
     tables: SSTableList,
     manifest: Arc<RwLock<Manifest>>,
     iter_in_progress: Arc<AtomicBool>,
+) -> Result<()> {
+    merge_tables(level + 1, tables, false, manifest, iter_in_progress).await
+}
+
+/// Merge the tables into a new table at the level, the newer versions
+/// of the keys come first in `tables`.
+///
+/// The tombstones are dropped if `bottommost`, that is, no other table
+/// may contain the keys of the tables.
+pub async fn merge_tables(
+    level: SSTableLevel,
+    tables: SSTableList,
+    bottommost: bool,
+    manifest: Arc<RwLock<Manifest>>,
+    iter_in_progress: Arc<AtomicBool>,
 ) -> Result<()> {
     // TODO: use async write + merge sort instead of all in memory
 
+    let start = Instant::now();
     let mut data = BTreeMap::new();
-    let key = SSTableKey::new(level + 1);
-    let mut meta = SSTableMeta::new(key);
+    let key = SSTableKey::new(level);
 
     for table in tables.iter() {
-        trace!("Compact table: {:?} -> {:?}", table.meta().key, meta.key);
+        trace!("Compact table: {:?} -> {:?}", table.meta().key, key);
         let mut iter = table.new_iter().await?;
         iter.init_iter().await?;
 
         while let Some((key, value)) = iter.next().await? {
             if let Entry::Vacant(e) = data.entry(key) {
-                meta.bloom_filter.insert(key);
                 e.insert(value);
             }
         }
     }
 
-    meta.set_entries_count(data.len());
+    if bottommost {
+        data.retain(|_, value| !value.is_deleted());
+    }
 
-    let gurad_manifest = manifest.read().await;
-    let sstable = SSTable::new(meta, &gurad_manifest.factory, gurad_manifest.row_size).await?;
-    sstable.archive(&data).await?;
-    drop(gurad_manifest);
+    let sstable = if data.is_empty() {
+        None
+    } else {
+        let gurad_manifest = manifest.read().await;
+        // recompressed with the current options of the table
+        let mut sstable = SSTable::new(SSTableMeta::new(key), &gurad_manifest.factory).await?;
+        sstable
+            .archive(data, gurad_manifest.sstable_options.clone())
+            .await?;
+        Some(sstable)
+    };
+
+    let statistics = Runtime::global().statistics();
+    statistics.incr(Counter::Compactions);
+    statistics.add(
+        Counter::BytesCompacted,
+        sstable.as_ref().map_or(0, |sstable| sstable.file_size()),
+    );
+    statistics.add(
+        Counter::CompactionMicros,
+        start.elapsed().as_micros() as u64,
+    );
 
     let mut gurad_manifest = manifest.write().await;
-    gurad_manifest.add_table(sstable).await;
+    if let Some(sstable) = sstable {
+        gurad_manifest.add_table(sstable).await;
+    }
     gurad_manifest.pop_tables(&tables);
 
     if !iter_in_progress.load(Ordering::Relaxed) {
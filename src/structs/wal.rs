@@ -0,0 +1,320 @@
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use super::lsm::SSTableKey;
+use super::{MemStore, WAL_MAGIC_NUMBER};
+use crate::utils::*;
+
+/// The log of the mutable memtable, the segments of the immutable ones
+/// are named after the keys of their L0 SSTables.
+const WAL_FILE_NAME: &str = ".wal";
+
+/// How the writes to a memtable are logged before they are acknowledged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalSyncMode {
+    /// Not logged, the memtable is only saved when the table is closed.
+    #[default]
+    Off,
+    /// Written to the file on every write, kept if the server crashes.
+    Write,
+    /// Synced to the disk on every write, kept if the machine crashes.
+    Fsync,
+}
+
+impl Display for WalSyncMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalSyncMode::Off => write!(f, "off"),
+            WalSyncMode::Write => write!(f, "write"),
+            WalSyncMode::Fsync => write!(f, "fsync"),
+        }
+    }
+}
+
+impl FromStr for WalSyncMode {
+    type Err = DbError;
+
+    /// Parse `off`, `write` or `fsync`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(WalSyncMode::Off),
+            "write" => Ok(WalSyncMode::Write),
+            "fsync" => Ok(WalSyncMode::Fsync),
+            _ => Err(DbError::Other(format!("Unknown WAL sync mode: {}", s))),
+        }
+    }
+}
+
+impl WalSyncMode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => WalSyncMode::Write,
+            2 => WalSyncMode::Fsync,
+            _ => WalSyncMode::Off,
+        }
+    }
+}
+
+/// The write-ahead log of a memtable.
+///
+/// Every record is `len`, `crc32` and the entry encoded by bincode. The
+/// log is rotated into a segment when the memtable is frozen, which is
+/// removed once the memtable is persisted. All the log is removed when the
+/// memtable is saved to the cache file.
+#[derive(Debug)]
+pub struct Wal {
+    dir: PathBuf,
+    sync_mode: AtomicU8,
+    file: Mutex<Option<File>>,
+}
+
+impl Wal {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            sync_mode: AtomicU8::new(WalSyncMode::Off as u8),
+            file: Mutex::new(None),
+        }
+    }
+
+    #[inline]
+    pub fn set_sync_mode(&self, mode: WalSyncMode) {
+        self.sync_mode.store(mode as u8, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn sync_mode(&self) -> WalSyncMode {
+        WalSyncMode::from_u8(self.sync_mode.load(Ordering::Relaxed))
+    }
+
+    /// Log an entry and run `insert` before the next one is logged, so the
+    /// writes to the same key are replayed in the order they were applied.
+    ///
+    /// The entry is still inserted if it fails to be logged.
+    pub fn write<T>(&self, key: Key, value: DataStore, insert: impl FnOnce(DataStore) -> T) -> T {
+        let mode = self.sync_mode();
+        if mode == WalSyncMode::Off {
+            return insert(value);
+        }
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = self.append(&mut file, mode, key, &value) {
+            error!("Write WAL failed    : {:?} {:?}", self.dir, err);
+            // open the log again for the next write
+            file.take();
+        }
+
+        insert(value)
+    }
+
+    fn append(
+        &self,
+        file: &mut Option<File>,
+        mode: WalSyncMode,
+        key: Key,
+        value: &DataStore,
+    ) -> Result<()> {
+        if file.is_none() {
+            let mut log = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(WAL_FILE_NAME))?;
+            if log.metadata()?.len() == 0 {
+                log.write_all(&WAL_MAGIC_NUMBER.to_be_bytes())?;
+            }
+            *file = Some(log);
+        }
+
+        let entry = bincode::encode_to_vec((key, value), BIN_CODE_CONF)?;
+        let mut record = Vec::with_capacity(entry.len() + 8);
+        record.extend_from_slice(&(entry.len() as u32).to_be_bytes());
+        record.extend_from_slice(&crc32fast::hash(&entry).to_be_bytes());
+        record.extend_from_slice(&entry);
+
+        let log = file.as_mut().unwrap();
+        log.write_all(&record)?;
+        if mode == WalSyncMode::Fsync {
+            log.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the log of the memtable frozen under `key` as its segment,
+    /// must be called without the writes.
+    pub fn rotate(&self, key: SSTableKey) -> Result<()> {
+        self.file.lock().unwrap().take();
+
+        match std::fs::rename(self.dir.join(WAL_FILE_NAME), self.segment_path(key)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the segment of a persisted memtable.
+    pub fn remove_segment(&self, key: SSTableKey) -> Result<()> {
+        match std::fs::remove_file(self.segment_path(key)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the log and all the segments.
+    pub fn clear(&self) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.take();
+
+        for path in self.files()? {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replay the segments from the oldest one, then the log, into `map`.
+    /// A log is read until its first broken record, which was being written
+    /// when the server crashed. Returns the number of the replayed entries.
+    pub fn replay(&self, map: &mut MemStore) -> Result<usize> {
+        let mut count = 0;
+        for path in self.files()? {
+            let mut bytes = Vec::new();
+            File::open(&path)?.read_to_end(&mut bytes)?;
+
+            if bytes.len() < 4 || bytes[..4] != WAL_MAGIC_NUMBER.to_be_bytes() {
+                warn!("Invalid WAL         : {:?}", path);
+                continue;
+            }
+
+            let mut pos = 4;
+            while let Some(entry) = Self::next_record(&bytes, &mut pos) {
+                let (key, value) =
+                    bincode::decode_from_slice::<KvStore, BincodeConfig>(entry, BIN_CODE_CONF)?.0;
+                map.insert(key, value);
+                count += 1;
+            }
+
+            if pos < bytes.len() {
+                warn!(
+                    "WAL truncated       : {:?} at {} of {} bytes",
+                    path,
+                    pos,
+                    bytes.len()
+                );
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn next_record<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+        let header = bytes.get(*pos..*pos + 8)?;
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc32 = u32::from_be_bytes(header[4..].try_into().unwrap());
+
+        let entry = bytes.get(*pos + 8..*pos + 8 + len)?;
+        if crc32fast::hash(entry) != crc32 {
+            return None;
+        }
+
+        *pos += 8 + len;
+        Some(entry)
+    }
+
+    /// Get the segments from the oldest one, then the log if it exists.
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name();
+            if let Some(key) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix(WAL_FILE_NAME)?.strip_prefix('.'))
+                .and_then(|key| u64::from_str_radix(key, 16).ok())
+            {
+                segments.push(SSTableKey(key));
+            }
+        }
+
+        // the older memtables have the larger keys
+        segments.sort_by(|a, b| b.cmp(a));
+
+        let mut files = segments
+            .into_iter()
+            .map(|key| self.segment_path(key))
+            .collect::<Vec<_>>();
+        let log = self.dir.join(WAL_FILE_NAME);
+        if log.exists() {
+            files.push(log);
+        }
+
+        Ok(files)
+    }
+
+    #[inline]
+    fn segment_path(&self, key: SSTableKey) -> PathBuf {
+        self.dir.join(format!("{}.{:x}", WAL_FILE_NAME, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_works() -> Result<()> {
+        let test_dir = "helper/wal_test";
+
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let wal = Wal::new(test_dir);
+        let value = |byte| DataStore::Value(Arc::new(vec![byte; 4]));
+
+        // not logged
+        wal.write(1, value(0), |_| ());
+
+        wal.set_sync_mode(WalSyncMode::Write);
+        wal.write(1, value(1), |_| ());
+        wal.write(2, value(2), |_| ());
+        let older = SSTableKey::new(0u64);
+        wal.rotate(older)?;
+
+        wal.set_sync_mode(WalSyncMode::Fsync);
+        wal.write(1, value(3), |_| ());
+        wal.write(2, DataStore::Deleted, |_| ());
+        wal.rotate(SSTableKey(older.0 - 1))?;
+        wal.write(3, value(4), |_| ());
+
+        let mut map = MemStore::new();
+        assert_eq!(wal.replay(&mut map)?, 5);
+        assert_eq!(map.get(&1), Some(&value(3)));
+        assert_eq!(map.get(&2), Some(&DataStore::Deleted));
+        assert_eq!(map.get(&3), Some(&value(4)));
+
+        // the older memtable is persisted
+        wal.remove_segment(older)?;
+        assert_eq!(wal.replay(&mut MemStore::new())?, 3);
+
+        // the torn record of a crash is skipped
+        let log = Path::new(test_dir).join(WAL_FILE_NAME);
+        let len = std::fs::metadata(&log)?.len();
+        OpenOptions::new()
+            .write(true)
+            .open(&log)?
+            .set_len(len - 1)?;
+        let mut map = MemStore::new();
+        assert_eq!(wal.replay(&mut map)?, 2);
+        assert_eq!(map.get(&3), None);
+
+        wal.clear()?;
+        assert_eq!(wal.replay(&mut MemStore::new())?, 0);
+
+        Ok(())
+    }
+}
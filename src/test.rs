@@ -6,7 +6,7 @@ use rand::{RngCore, SeedableRng};
 
 use crate::{
     structs::{lsm::tests::check_file, *},
-    utils::{error::Result, new_progress_bar, DataStore},
+    utils::{error::Result, new_progress_bar, Compression, DataStore},
 };
 
 #[test]
@@ -44,6 +44,7 @@ async fn it_works_async() -> Result<()> {
     let iter_elapsed = iter_table(&table).await?;
     let seq_elapsed = seq_read_table(&table).await?;
     let rand_elapsed = rand_read_table(&table).await?;
+    range_read_table(&table).await?;
 
     info!(
         "{:=^80}",
@@ -54,6 +55,13 @@ async fn it_works_async() -> Result<()> {
         .green()
     );
 
+    let stats = table.stats().await;
+    info!(
+        "Filter stats: {:?}, false positive rate: {:.4}",
+        stats.filter,
+        stats.filter.false_positive_rate()
+    );
+
     table.close().await?;
 
     let size_on_disk = table.size_on_disk().await?;
@@ -108,6 +116,9 @@ async fn init_table(table: &Table) -> Result<()> {
     info!("{:=^80}", style(" Add More Data ").yellow());
     let start = std::time::Instant::now();
 
+    // files with different codecs should coexist
+    table.set_compression(Compression::Lz4).await;
+
     // add more data
     info!("{}", style(">>> Inserting...").bright().bold());
     let bar = new_progress_bar(TEST_SIZE / 2);
@@ -163,7 +174,7 @@ async fn init_table(table: &Table) -> Result<()> {
 }
 
 fn check_value(key: u64, value: &[u8]) {
-    let mut data = if key % 13 == 0 && key >= TEST_SIZE / 2 {
+    let mut data = if key >= TEST_SIZE / 2 && (key - TEST_SIZE / 2).is_multiple_of(13) {
         vec![((key * 2) % 57 + 65) as u8; NUMBER_SIZE]
     } else {
         vec![(key % 57 + 65) as u8; NUMBER_SIZE]
@@ -172,9 +183,9 @@ fn check_value(key: u64, value: &[u8]) {
     // |      (i % 57 + 65)        |             |             | TEST_SIZE
     // |             |   deleted   |             |             | TEST_SIZE
     // |             |             |      (i % 57 + 65)        | TEST_SIZE
-    // |             |             |    update i % 13 == 0     | TEST_SIZE
+    // |             |             |  update (i-N/2) % 13 == 0 | TEST_SIZE
     // |                 deleted i % 5 == 0                    | TEST_SIZE
-    if key % 5 == 0 || (TEST_SIZE / 4 <= key && key < TEST_SIZE / 2) {
+    if key.is_multiple_of(5) || (TEST_SIZE / 4..TEST_SIZE / 2).contains(&key) {
         panic!("Unexpected value for key [{}] -> [{}]", key, value.len());
     }
 
@@ -195,6 +206,9 @@ async fn check_table_files(table: &Table) -> Result<()> {
         check_file(&table).await?;
     }
 
+    let report = table.check().await?;
+    assert!(report.is_ok(), "{:#?}", report);
+
     info!(
         "{:=^80}",
         style(format!(" Check Files Done ({:?}) ", start.elapsed())).green()
@@ -231,6 +245,35 @@ async fn iter_table(table: &Table) -> Result<Duration> {
     Ok(elapsed)
 }
 
+async fn range_read_table(table: &Table) -> Result<()> {
+    info!("{:=^80}", style(" Range Read Test ").yellow());
+
+    // (start, end, expected count), see `check_value` for the layout
+    let ranges = [
+        (TEST_SIZE / 2 + 100, TEST_SIZE / 2 + 1099, 800),
+        (TEST_SIZE / 4 + 10, TEST_SIZE / 4 + 500, 0),
+        (TEST_SIZE, u64::MAX, 0),
+    ];
+
+    for (start, end, expected) in ranges {
+        table.init_range_iter(start, end).await;
+
+        let mut count = 0;
+        while let Some((key, DataStore::Value(value))) = table.next().await? {
+            assert!((start..=end).contains(&key));
+            check_value(key, &value);
+            count += 1;
+        }
+
+        table.end_iter().await;
+        assert_eq!(count, expected, "Range [{}, {}]", start, end);
+    }
+
+    info!("{:=^80}", style(" Range Read Test Passed ").green());
+
+    Ok(())
+}
+
 async fn seq_read_table(table: &Table) -> Result<Duration> {
     info!(
         "{:=^80}",
@@ -247,7 +290,7 @@ async fn seq_read_table(table: &Table) -> Result<Duration> {
                 check_value(key, &value);
             }
             x => {
-                if key % 5 == 0 || (TEST_SIZE / 4 <= key && key < TEST_SIZE / 2) {
+                if key.is_multiple_of(5) || (TEST_SIZE / 4..TEST_SIZE / 2).contains(&key) {
                     continue;
                 } else {
                     panic!("Unexpected value for key {}: {:?}", key, x);
@@ -285,7 +328,7 @@ async fn rand_read_table(table: &Table) -> Result<Duration> {
                 check_value(key, &value);
             }
             x => {
-                if key % 5 == 0 || (TEST_SIZE / 4 <= key && key < TEST_SIZE / 2) {
+                if key.is_multiple_of(5) || (TEST_SIZE / 4..TEST_SIZE / 2).contains(&key) {
                     continue;
                 } else {
                     panic!("Unexpected value for key {}: {:?}", key, x);
@@ -303,3 +346,59 @@ async fn rand_read_table(table: &Table) -> Result<Duration> {
 
     Ok(elapsed)
 }
+
+const BENCH_WRITES: u64 = 200000;
+
+#[test]
+fn memtable_scaling() {
+    crate::core::runtime::block_on(async {
+        memtable_scaling_async().await.unwrap();
+    });
+}
+
+/// Measure the write throughput of the memtable with more writers.
+async fn memtable_scaling_async() -> Result<()> {
+    crate::utils::logger::init();
+    let test_dir = "helper/memtable_bench";
+
+    info!("{:=^80}", style(" MemTable Scaling ").yellow());
+
+    for writers in [1u64, 2, 4, 8] {
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let mem = std::sync::Arc::new(MemTable::new(test_dir, None).await?);
+        // never freeze during the benchmark
+        mem.set_flush_size(usize::MAX);
+
+        let start = std::time::Instant::now();
+        let per_writer = BENCH_WRITES / writers;
+
+        let handles = (0..writers)
+            .map(|w| {
+                let mem = mem.clone();
+                crate::core::spawn(async move {
+                    for i in w * per_writer..(w + 1) * per_writer {
+                        mem.set(i, vec![(i % 57 + 65) as u8; DATA_SIZE]).await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        assert_eq!(mem.len().await as u64, per_writer * writers);
+
+        info!(
+            "Writers: {} -> {:>10.0} ops/s ({:?})",
+            writers,
+            (per_writer * writers) as f64 / elapsed.as_secs_f64(),
+            elapsed
+        );
+    }
+
+    Ok(())
+}
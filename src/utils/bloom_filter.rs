@@ -1,14 +1,12 @@
 //! Ref: <https://docs.rs/bincode/2.0.0-rc.2/bincode/index.html>
 
-use std::fmt::Formatter;
+use std::{
+    fmt::Formatter,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use growable_bloom_filter::GrowableBloom;
 
-use crate::structs::MEM_BLOCK_NUM;
-
-pub const MAX_EXPECT_NUM: usize = 0xff000;
-pub const MERGE_FACTOR: usize = 4;
-
 #[derive(Clone, Encode, Decode)]
 pub struct BloomFilter {
     #[bincode(with_serde)]
@@ -16,25 +14,74 @@ pub struct BloomFilter {
 }
 
 impl BloomFilter {
-    pub fn new(level: u32) -> Self {
+    /// Create a filter for about `expect_num` keys, up to the
+    /// `max_filter_keys` option.
+    pub fn new(expect_num: usize) -> Self {
         Self {
-            filter: GrowableBloom::new(0.01, bloom_size(level)),
+            filter: GrowableBloom::new(0.01, expect_num.clamp(1, max_filter_keys())),
         }
     }
+}
 
-    pub fn new_global() -> Self {
-        Self {
-            filter: GrowableBloom::new(0.05, MAX_EXPECT_NUM),
+#[inline]
+pub(crate) fn max_filter_keys() -> usize {
+    crate::core::Runtime::global().options().max_filter_keys
+}
+
+/// A snapshot of the filter counters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilterStats {
+    /// Lookups rejected by the filter.
+    pub useful: u64,
+    /// Lookups passed the filter, but the key is not in the table.
+    pub false_positive: u64,
+    /// Lookups passed the filter, and the key is in the table.
+    pub true_positive: u64,
+}
+
+impl FilterStats {
+    /// The measured false positive rate over the keys not in the tables.
+    pub fn false_positive_rate(&self) -> f64 {
+        let negatives = self.useful + self.false_positive;
+        if negatives == 0 {
+            return 0.0;
         }
+        self.false_positive as f64 / negatives as f64
     }
 }
 
-fn bloom_size(level: u32) -> usize {
-    let num = MEM_BLOCK_NUM * MERGE_FACTOR.pow(level) * 2;
-    if num > MAX_EXPECT_NUM {
-        MAX_EXPECT_NUM
-    } else {
-        num
+/// Counters of the filter lookups.
+#[derive(Debug, Default)]
+pub struct FilterCounter {
+    useful: AtomicU64,
+    false_positive: AtomicU64,
+    true_positive: AtomicU64,
+}
+
+impl FilterCounter {
+    #[inline]
+    pub fn record_useful(&self) {
+        self.useful.fetch_add(1, Ordering::Relaxed);
+        crate::core::Runtime::global()
+            .statistics()
+            .incr(crate::core::Counter::BloomNegatives);
+    }
+
+    #[inline]
+    pub fn record_positive(&self, found: bool) {
+        if found {
+            self.true_positive.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.false_positive.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        FilterStats {
+            useful: self.useful.load(Ordering::Relaxed),
+            false_positive: self.false_positive.load(Ordering::Relaxed),
+            true_positive: self.true_positive.load(Ordering::Relaxed),
+        }
     }
 }
 
@@ -66,7 +113,7 @@ mod tests {
 
     #[test]
     fn it_works() {
-        let mut bloom = BloomFilter::new(0);
+        let mut bloom = BloomFilter::new(0x80);
         bloom.insert(&[1, 2, 3]);
 
         let config = bincode::config::standard();
@@ -77,17 +124,22 @@ mod tests {
 
         let decoded: BloomFilter = bincode::decode_from_slice(&bytes, config).unwrap().0;
         assert_eq!(bloom.filter, decoded.filter);
+    }
 
-        let mut bloom = BloomFilter::new_global();
-        bloom.insert(&[1, 2, 3]);
-
-        let config = bincode::config::standard();
-        let bytes = bincode::encode_to_vec(&bloom, config).unwrap();
+    #[test]
+    fn counter_works() {
+        let counter = FilterCounter::default();
+        assert_eq!(counter.stats().false_positive_rate(), 0.0);
 
-        assert!(bytes.len() > 0);
-        debug!("Length for Global BloomFilter Test: {}", bytes.len());
+        for _ in 0..3 {
+            counter.record_useful();
+        }
+        counter.record_positive(false);
+        counter.record_positive(true);
 
-        let decoded: BloomFilter = bincode::decode_from_slice(&bytes, config).unwrap().0;
-        assert_eq!(bloom.filter, decoded.filter);
+        let stats = counter.stats();
+        assert_eq!(stats.useful, 3);
+        assert_eq!(stats.true_positive, 1);
+        assert_eq!(stats.false_positive_rate(), 0.25);
     }
 }
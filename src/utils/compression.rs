@@ -0,0 +1,217 @@
+use std::{fmt::Display, io::Read, str::FromStr};
+
+use async_compression::Level;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use super::*;
+
+pub const DEFAULT_ZSTD_LEVEL: u8 = 3;
+
+/// The max size of a trained zstd dictionary.
+pub const ZSTD_DICT_SIZE: usize = 16 << 10;
+
+/// The max bytes of samples to train a dictionary, about 100 times of the size.
+pub const ZSTD_DICT_SAMPLES_SIZE: usize = ZSTD_DICT_SIZE * 100;
+
+/// The codec and level of the data blocks in a SSTable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd(u8),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd(DEFAULT_ZSTD_LEVEL)
+    }
+}
+
+impl Compression {
+    /// Encode as `codec << 8 | level` for the SSTable header.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1 << 8,
+            Compression::Zstd(level) => 2 << 8 | level as u32,
+        }
+    }
+
+    pub fn from_u32(value: u32) -> Result<Self> {
+        match value >> 8 {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            2 => Ok(Compression::Zstd(value as u8)),
+            _ => Err(DbError::InvalidData),
+        }
+    }
+
+    pub async fn compress(self, raw: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(raw.to_vec()),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(raw)),
+            Compression::Zstd(level) => {
+                let level = Level::Precise(level as u32);
+                let mut writer = CompressionEncoder::with_quality(Vec::new(), level);
+                writer.write_all(raw).await?;
+                writer.shutdown().await?;
+                Ok(writer.into_inner())
+            }
+        }
+    }
+
+    pub async fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Lz4 => {
+                lz4_flex::decompress_size_prepended(bytes).map_err(|_| DbError::InvalidData)
+            }
+            Compression::Zstd(_) => {
+                let mut reader = CompressionDecoder::new(bytes);
+                reader.multiple_members(true);
+                let mut raw = Vec::new();
+                reader.read_to_end(&mut raw).await?;
+                Ok(raw)
+            }
+        }
+    }
+}
+
+/// A zstd dictionary trained from the rows of a table.
+pub struct ZstdDictionary {
+    bytes: Vec<u8>,
+    encoder: Option<EncoderDictionary<'static>>,
+    decoder: DecoderDictionary<'static>,
+}
+
+impl ZstdDictionary {
+    /// Train a dictionary for compressing with `level`.
+    pub fn train(samples: &[impl AsRef<[u8]>], level: u8) -> Result<Self> {
+        let bytes = zstd::dict::from_samples(samples, ZSTD_DICT_SIZE)?;
+
+        Ok(Self {
+            encoder: Some(EncoderDictionary::copy(&bytes, level as i32)),
+            decoder: DecoderDictionary::copy(&bytes),
+            bytes,
+        })
+    }
+
+    /// Load a dictionary for decompressing only.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            encoder: None,
+            decoder: DecoderDictionary::copy(&bytes),
+            bytes,
+        }
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn compress(&self, raw: &[u8]) -> Result<Vec<u8>> {
+        let encoder = self
+            .encoder
+            .as_ref()
+            .ok_or_else(|| DbError::Other("Dictionary is for decompressing only".to_string()))?;
+
+        Ok(zstd::bulk::Compressor::with_prepared_dictionary(encoder)?.compress(raw)?)
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        zstd::stream::read::Decoder::with_prepared_dictionary(bytes, &self.decoder)?
+            .read_to_end(&mut raw)?;
+        Ok(raw)
+    }
+}
+
+impl std::fmt::Debug for ZstdDictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZstdDictionary")
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Lz4 => write!(f, "lz4"),
+            Compression::Zstd(level) => write!(f, "zstd:{}", level),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = DbError;
+
+    /// Parse `none`, `lz4`, `zstd` or `zstd:<level>`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().split_once(':') {
+            None if s.eq_ignore_ascii_case("none") => Ok(Compression::None),
+            None if s.eq_ignore_ascii_case("lz4") => Ok(Compression::Lz4),
+            None if s.eq_ignore_ascii_case("zstd") => Ok(Compression::default()),
+            Some(("zstd", level)) => match level.parse() {
+                Ok(level @ 1..=22) => Ok(Compression::Zstd(level)),
+                _ => Err(DbError::Other(format!("Invalid zstd level: {}", level))),
+            },
+            _ => Err(DbError::Other(format!("Unknown compression: {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_works() -> Result<()> {
+        let raw = (0..0x1000u32)
+            .flat_map(|i| (i % 57).to_be_bytes())
+            .collect::<Vec<_>>();
+
+        for codec in [
+            Compression::None,
+            Compression::Lz4,
+            Compression::Zstd(1),
+            Compression::Zstd(19),
+        ] {
+            let bytes = codec.compress(&raw).await?;
+            assert_eq!(codec.decompress(&bytes).await?, raw);
+            assert_eq!(Compression::from_u32(codec.to_u32())?, codec);
+            assert_eq!(codec.to_string().parse::<Compression>()?, codec);
+        }
+
+        // short and repetitive rows
+        let samples = (0..0x400u32)
+            .map(|i| {
+                format!(
+                    "{{\"id\":{},\"name\":\"user_{}\",\"age\":{}}}",
+                    i,
+                    i % 97,
+                    i % 60
+                )
+            })
+            .collect::<Vec<_>>();
+        let dict = ZstdDictionary::train(&samples, DEFAULT_ZSTD_LEVEL)?;
+        let raw = samples[..0x40].concat().into_bytes();
+
+        let bytes = dict.compress(&raw)?;
+        assert!(bytes.len() < Compression::default().compress(&raw).await?.len());
+        assert_eq!(dict.decompress(&bytes)?, raw);
+
+        let loaded = ZstdDictionary::from_bytes(dict.as_bytes().to_vec());
+        assert_eq!(loaded.decompress(&bytes)?, raw);
+        assert!(loaded.compress(&raw).is_err());
+
+        assert_eq!("zstd".parse::<Compression>()?, Compression::default());
+        assert!("zstd:0".parse::<Compression>().is_err());
+        assert!("gzip".parse::<Compression>().is_err());
+
+        Ok(())
+    }
+}
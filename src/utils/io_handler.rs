@@ -95,7 +95,7 @@ impl SizedOnDisk for IOHandler {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IOHandlerFactory {
     base_dir: Arc<PathBuf>,
 }
@@ -107,11 +107,20 @@ impl IOHandlerFactory {
         }
     }
 
-    pub async fn create(&self, key: SSTableKey) -> Result<IOHandler> {
+    #[inline]
+    pub fn base_dir(&self) -> &PathBuf {
+        &self.base_dir
+    }
+
+    /// Get the path of the SSTable file.
+    pub fn file_path(&self, key: SSTableKey) -> PathBuf {
         let mut path = self.base_dir.to_path_buf();
-        path.push(format!("{:x}.l{}", key, key.level()));
+        path.push(key.file_name());
+        path
+    }
 
-        IOHandler::new(&path).await
+    pub async fn create(&self, key: SSTableKey) -> Result<IOHandler> {
+        IOHandler::new(self.file_path(key)).await
     }
 }
 
@@ -1,50 +1,262 @@
 use log::{LevelFilter, Metadata, Record};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+
+use super::{DbError, Result};
 
 #[cfg(not(feature = "mysql"))]
 lazy_static! {
     static ref LOGGER: Logger = {
-        Logger {
-            output: |_, message| {
-                println!("{message}");
-            },
-        }
+        Logger::new(|_, message| {
+            println!("{message}");
+        })
     };
 }
 
 #[cfg(feature = "mysql")]
 lazy_static! {
     static ref LOGGER: Logger = {
-        Logger {
-            output: |level, message| crate::bridge::ffi::mysql_log_write(level as i32, message),
-        }
+        Logger::new(|level, message| crate::bridge::ffi::mysql_log_write(level as i32, message))
     };
 }
 
 /// Init the logger of YYDB.
+///
+/// The filters are read from the `LOG_LEVEL` environment variable, or the
+/// one at compile time, e.g. `info,structs::lsm=trace`.
 pub(crate) fn init() {
     // an error will be returned if the logger was
     // already initialized. this situation is expected
     // when we reinstall the plugin.
     log::set_logger(&*LOGGER).ok();
 
-    log::set_max_level(match option_env!("LOG_LEVEL") {
-        Some("error") => LevelFilter::Error,
-        Some("warn") => LevelFilter::Warn,
-        Some("info") => LevelFilter::Info,
-        Some("debug") => LevelFilter::Debug,
-        Some("trace") => LevelFilter::Trace,
-        _ => LevelFilter::Info,
-    });
+    let spec = std::env::var("LOG_LEVEL").ok();
+    let filters = spec
+        .as_deref()
+        .or(option_env!("LOG_LEVEL"))
+        .and_then(|spec| LogFilters::parse(spec).ok())
+        .unwrap_or_default();
+    // format before logging, `enabled` takes the lock again
+    let current = filters.to_string();
+    LOGGER.set_filters(filters);
 
-    info!("Current log level: {}", log::max_level());
+    info!("Current log level: {}", current);
     info!("Logger Initialized.");
 }
 
+/// Set the default level of the logs at runtime, `off` or `error` to `trace`,
+/// the filters of the modules are kept.
+pub fn set_level(level: &str) -> Result<()> {
+    let mut filters = LOGGER.filters.read().unwrap().clone();
+    filters.level = parse_level(level)?;
+
+    LOGGER.set_filters(filters);
+    info!("Current log level: {}", level);
+    Ok(())
+}
+
+/// Set the level of the modules at runtime, e.g. `structs::lsm=trace`,
+/// a level without a module sets the default one.
+pub fn set_filters(spec: &str) -> Result<()> {
+    let mut filters = LogFilters::parse(spec)?;
+    if !spec.split(',').any(|item| parse_level(item.trim()).is_ok()) {
+        filters.level = LOGGER.filters.read().unwrap().level;
+    }
+
+    let current = filters.to_string();
+    LOGGER.set_filters(filters);
+    info!("Current log level: {}", current);
+    Ok(())
+}
+
+/// Write the logs to a file instead of the default output, which is
+/// renamed with a `.1` suffix once it reaches `max_size` bytes, up to
+/// `max_files` old files are kept. `None` to switch back.
+pub fn set_file(path: Option<&str>, max_size: u64, max_files: usize) -> Result<()> {
+    let file = match path.filter(|path| !path.is_empty()) {
+        Some(path) => Some(RotatingFile::open(path, max_size, max_files)?),
+        None => None,
+    };
+
+    let mut current = LOGGER.file.lock().unwrap();
+    let previous = current.as_ref().map(|file| file.path.clone());
+    *current = file;
+    drop(current);
+
+    info!(
+        "Log file            : {:?} -> {:?}",
+        previous,
+        path.filter(|path| !path.is_empty())
+    );
+    Ok(())
+}
+
+/// The default level of the logs, and the levels of some modules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilters {
+    pub level: LevelFilter,
+    /// The module paths without the crate name, and their levels.
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilters {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl LogFilters {
+    /// Parse the comma separated `level` and `module=level` items.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut filters = Self::default();
+
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim();
+                    let module = module.strip_prefix("yydb::").unwrap_or(module);
+                    filters
+                        .modules
+                        .push((module.to_string(), parse_level(level.trim())?));
+                }
+                None => filters.level = parse_level(item)?,
+            }
+        }
+
+        Ok(filters)
+    }
+
+    /// Get the level of the module, by the longest matching path.
+    pub fn level_of(&self, target: &str) -> LevelFilter {
+        let target = target.strip_prefix("yydb::").unwrap_or(target);
+
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    /// Get the most verbose level of all the modules.
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, std::cmp::max)
+    }
+}
+
+impl std::fmt::Display for LogFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.level.as_str().to_ascii_lowercase())?;
+        for (module, level) in self.modules.iter() {
+            write!(f, ",{}={}", module, level.as_str().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    level
+        .parse()
+        .map_err(|_| DbError::Other(format!("Unknown log level: {}", level)))
+}
+
+/// A log file renamed to `<path>.1`, `<path>.2`, ... when it is full.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: impl Into<PathBuf>, max_size: u64, max_files: usize) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size: max_size.max(1),
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn write(&mut self, message: &str) -> std::io::Result<()> {
+        if self.size > 0 && self.size + message.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", message)?;
+        self.size += message.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |i: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", i));
+            PathBuf::from(path)
+        };
+
+        if self.max_files > 0 {
+            for i in (1..self.max_files).rev() {
+                std::fs::rename(rotated(i), rotated(i + 1)).ok();
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
 struct Logger {
     output: fn(log::Level, &str),
+    filters: RwLock<LogFilters>,
+    /// The file to write instead of `output`.
+    file: Mutex<Option<RotatingFile>>,
 }
 
 impl Logger {
+    fn new(output: fn(log::Level, &str)) -> Self {
+        Self {
+            output,
+            filters: RwLock::new(LogFilters::default()),
+            file: Mutex::new(None),
+        }
+    }
+
+    fn set_filters(&self, filters: LogFilters) {
+        log::set_max_level(filters.max_level());
+        *self.filters.write().unwrap() = filters;
+    }
+
     fn get_formatted_message(&self, record: &Record) -> String {
         match record.level() {
             log::Level::Error => format!(
@@ -63,16 +275,30 @@ impl Logger {
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= metadata.level()
+        metadata.level() <= self.filters.read().unwrap().level_of(metadata.target())
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            (self.output)(record.level(), &self.get_formatted_message(record));
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = self.get_formatted_message(record);
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+            if file.write(&format!("{} {}", time, message)).is_ok() {
+                return;
+            }
         }
+
+        (self.output)(record.level(), &message);
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            file.file.flush().ok();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -88,4 +314,44 @@ mod tests {
         warn!("Hello, world!");
         error!("Hello, world!");
     }
+
+    #[test]
+    fn filters_works() -> Result<()> {
+        let filters = LogFilters::parse("warn, structs::lsm=trace,yydb::structs=debug")?;
+        assert_eq!(filters.level, LevelFilter::Warn);
+        assert_eq!(filters.level_of("yydb::core::runtime"), LevelFilter::Warn);
+        assert_eq!(filters.level_of("yydb::structs::table"), LevelFilter::Debug);
+        assert_eq!(
+            filters.level_of("yydb::structs::lsm::sstable"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filters.level_of("yydb::structs::lsmx"), LevelFilter::Debug);
+        assert_eq!(filters.max_level(), LevelFilter::Trace);
+        assert_eq!(LogFilters::parse(&filters.to_string())?, filters);
+
+        assert!(LogFilters::parse("loud").is_err());
+        assert!(LogFilters::parse("structs=loud").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rotating_file_works() -> Result<()> {
+        let test_dir = "helper/log_file_test";
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(test_dir)?;
+
+        let path = format!("{}/yydb.log", test_dir);
+        let mut file = RotatingFile::open(&path, 0x40, 2)?;
+        for i in 0..8 {
+            file.write(&format!("{:031}", i))?;
+        }
+
+        // two lines in every file, the oldest ones are dropped
+        assert_eq!(std::fs::read_to_string(&path)?.lines().count(), 2);
+        assert!(std::fs::read_to_string(format!("{}.2", path))?.starts_with(&format!("{:031}", 2)));
+        assert!(!PathBuf::from(format!("{}.3", path)).exists());
+
+        Ok(())
+    }
 }
@@ -2,11 +2,13 @@
 mod macros;
 
 pub mod bloom_filter;
+pub mod compression;
 pub mod data_store;
 pub mod deque_iter;
 pub mod error;
 pub mod io_handler;
 pub mod logger;
+pub mod range_filter;
 
 use async_compression::Level;
 use bincode::config::*;
@@ -14,15 +16,27 @@ use indicatif::{ProgressBar, ProgressStyle};
 use std::fmt::Write;
 
 pub use bloom_filter::*;
+pub use compression::*;
 pub use data_store::*;
 pub use deque_iter::*;
 pub use error::*;
 pub use io_handler::*;
 pub use macros::*;
+pub use range_filter::*;
 
 pub type CompressionEncoder<T> = async_compression::tokio::write::ZstdEncoder<T>;
 pub type CompressionDecoder<T> = async_compression::tokio::bufread::ZstdDecoder<T>;
-pub const COMPRESSION_LEVEL: Level = Level::Default;
+
+/// The level of the manifest, the memtable cache and the block index,
+/// see `Options::meta_compression_level`.
+#[inline]
+pub fn compression_level() -> Level {
+    Level::Precise(
+        crate::core::Runtime::global()
+            .options()
+            .meta_compression_level,
+    )
+}
 
 pub type BincodeConfig = Configuration;
 pub const BIN_CODE_CONF: BincodeConfig = bincode::config::standard();
@@ -42,6 +56,21 @@ pub fn bar_default_style() -> ProgressStyle {
         .progress_chars("#>-")
 }
 
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a 64-bit hash, stable across platforms and Rust releases.
+pub const fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
 const HEX_VIEW_WIDTH: usize = 32;
 const HEX_VIEW_COL_WIDTH: usize = 8;
 
@@ -0,0 +1,88 @@
+use std::fmt::Formatter;
+
+use growable_bloom_filter::GrowableBloom;
+
+use super::{max_filter_keys, Key};
+
+/// The number of low bits dropped from the keys, for each prefix length in the filter.
+pub const RANGE_FILTER_SHIFTS: [u32; 4] = [4, 8, 12, 16];
+
+/// The max number of prefixes to probe for a range,
+/// wider ranges are always considered as maybe containing keys.
+pub const RANGE_FILTER_MAX_PROBES: u64 = 16;
+
+/// A prefix bloom filter on the high bits of the keys.
+///
+/// Every key is inserted with several prefix lengths, a range is probed
+/// with the longest prefix that covers it in a few probes.
+#[derive(Clone, Encode, Decode)]
+pub struct RangeFilter {
+    #[bincode(with_serde)]
+    filter: GrowableBloom,
+}
+
+impl RangeFilter {
+    /// Create a filter for about `expect_num` keys.
+    pub fn new(expect_num: usize) -> Self {
+        let expect_num = expect_num.saturating_mul(RANGE_FILTER_SHIFTS.len());
+        Self {
+            filter: GrowableBloom::new(0.01, expect_num.clamp(1, max_filter_keys())),
+        }
+    }
+
+    pub fn insert(&mut self, key: Key) {
+        for shift in RANGE_FILTER_SHIFTS {
+            self.filter.insert((shift, key >> shift));
+        }
+    }
+
+    /// Check if there may be any key in `[start, end]`.
+    pub fn may_contain_range(&self, start: Key, end: Key) -> bool {
+        if start > end {
+            return false;
+        }
+
+        for shift in RANGE_FILTER_SHIFTS {
+            let (first, last) = (start >> shift, end >> shift);
+            if last - first < RANGE_FILTER_MAX_PROBES {
+                return (first..=last).any(|prefix| self.filter.contains((shift, prefix)));
+            }
+        }
+
+        true
+    }
+}
+
+impl std::fmt::Debug for RangeFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RangeFilter")
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let mut filter = RangeFilter::new(0x100);
+        for key in (0..0x100).map(|i| i * 0x10000) {
+            filter.insert(key);
+        }
+
+        assert!(filter.may_contain_range(0, 0));
+        assert!(filter.may_contain_range(0x2fff0, 0x30010));
+        assert!(!filter.may_contain_range(0x31000, 0x3f000));
+        assert!(!filter.may_contain_range(0x3f000, 0x31000));
+
+        // too wide to be filtered
+        assert!(filter.may_contain_range(0x1000001, u64::MAX));
+
+        let config = bincode::config::standard();
+        let bytes = bincode::encode_to_vec(&filter, config).unwrap();
+        let decoded: RangeFilter = bincode::decode_from_slice(&bytes, config).unwrap().0;
+        assert_eq!(filter.filter, decoded.filter);
+    }
+}